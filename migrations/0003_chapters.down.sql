@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_chapters_file_id;
+
+DROP TABLE IF EXISTS chapters;
+
+ALTER TABLE file_scan_cache DROP COLUMN chapters;
@@ -0,0 +1,15 @@
+-- Chapters extracted from embedded markers (m4b chapter tracks, Nero chpl, ID3 CHAP)
+ALTER TABLE file_scan_cache ADD COLUMN chapters TEXT; -- json array carried until propagate_changes
+
+CREATE TABLE IF NOT EXISTS chapters (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    file_id INTEGER NOT NULL,
+    chapter_index INTEGER NOT NULL,
+    title TEXT,
+    start_ms INTEGER NOT NULL, -- offset within the file
+    end_ms INTEGER NOT NULL,
+    FOREIGN KEY (file_id) REFERENCES files (id) ON DELETE CASCADE,
+    UNIQUE (file_id, chapter_index)
+);
+
+CREATE INDEX IF NOT EXISTS idx_chapters_file_id ON chapters (file_id);
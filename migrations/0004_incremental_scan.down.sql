@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_files_hash_size;
+
+ALTER TABLE file_scan_cache DROP COLUMN missing;
+
+ALTER TABLE file_scan_cache DROP COLUMN mtime;
@@ -0,0 +1,6 @@
+-- Track file state so rescans can skip unchanged files and follow renames
+ALTER TABLE file_scan_cache ADD COLUMN mtime INTEGER; -- ms since epoch at last probe
+
+ALTER TABLE file_scan_cache ADD COLUMN missing BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_files_hash_size ON file_scan_cache (hash, file_size);
@@ -0,0 +1,29 @@
+-- Book level full text index, rowid is audiobooks.id
+CREATE VIRTUAL TABLE IF NOT EXISTS library_search USING fts5 (
+    title,
+    author,
+    series,
+    narrator,
+    file_names,
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+INSERT INTO library_search (rowid, title, author, series, narrator, file_names)
+SELECT
+    ab.id,
+    ab.title,
+    ab.author,
+    ab.series,
+    (
+        SELECT group_concat(DISTINCT fsc.narrated_by)
+        FROM files f
+        JOIN file_scan_cache fsc ON fsc.file_path = f.file_path
+        WHERE f.book_id = ab.id
+    ),
+    (
+        SELECT group_concat(f.file_name, ' ')
+        FROM files f
+        WHERE f.book_id = ab.id
+    )
+FROM audiobooks ab;
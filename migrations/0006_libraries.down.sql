@@ -0,0 +1,46 @@
+DROP INDEX IF EXISTS idx_scan_cache_library;
+
+CREATE TABLE audiobooks_old (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    author TEXT NOT NULL,
+    series TEXT,
+    title TEXT NOT NULL,
+    files_location TEXT NOT NULL,
+    cover_art TEXT,
+    metadata TEXT,
+    duration INTEGER DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (author, title)
+);
+
+-- Books that only differ by library collapse into the lowest id
+INSERT OR IGNORE INTO audiobooks_old (
+    id, author, series, title, files_location, cover_art, metadata, duration, created_at, updated_at
+)
+SELECT
+    id, author, series, title, files_location, cover_art, metadata, duration, created_at, updated_at
+FROM audiobooks
+ORDER BY id;
+
+DROP TABLE audiobooks;
+
+ALTER TABLE audiobooks_old RENAME TO audiobooks;
+
+CREATE INDEX IF NOT EXISTS idx_audiobooks_author ON audiobooks (author);
+
+CREATE INDEX IF NOT EXISTS idx_audiobooks_title ON audiobooks (title);
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_audiobooks_author_title ON audiobooks (author, title);
+
+-- Dropping the old table took its trigger with it
+CREATE TRIGGER update_audiobooks_timestamp
+AFTER UPDATE ON audiobooks
+FOR EACH ROW
+BEGIN
+    UPDATE audiobooks SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+DROP TABLE IF EXISTS library_access;
+
+DROP TABLE IF EXISTS libraries;
@@ -0,0 +1,71 @@
+-- Libraries each own a root folder. Library 1 stands in for the old single
+-- AUDIOBOOKS_LOCATION root and is given that path on first start.
+CREATE TABLE IF NOT EXISTS libraries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    root_path TEXT NOT NULL,
+    media_type TEXT NOT NULL DEFAULT 'audiobook', -- audiobook, podcast
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO libraries (id, name, root_path) VALUES (1, 'Default', '');
+
+-- Which libraries a non admin user can see
+CREATE TABLE IF NOT EXISTS library_access (
+    user_id INTEGER NOT NULL,
+    library_id INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, library_id),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (library_id) REFERENCES libraries (id) ON DELETE CASCADE
+);
+
+-- Existing users keep seeing what they saw before
+INSERT INTO library_access (user_id, library_id) SELECT id, 1 FROM users;
+
+-- Rebuild audiobooks so a book is unique per library rather than globally.
+-- Migrations run with foreign keys off, so files, progress and chapters
+-- keep pointing at the same ids.
+CREATE TABLE audiobooks_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    library_id INTEGER NOT NULL DEFAULT 1,
+    author TEXT NOT NULL,
+    series TEXT,
+    title TEXT NOT NULL,
+    files_location TEXT NOT NULL,
+    cover_art TEXT,
+    metadata TEXT,
+    duration INTEGER DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (library_id) REFERENCES libraries (id) ON DELETE CASCADE,
+    UNIQUE (library_id, author, title)
+);
+
+INSERT INTO audiobooks_new (
+    id, library_id, author, series, title, files_location, cover_art, metadata, duration, created_at, updated_at
+)
+SELECT
+    id, 1, author, series, title, files_location, cover_art, metadata, duration, created_at, updated_at
+FROM audiobooks;
+
+DROP TABLE audiobooks;
+
+ALTER TABLE audiobooks_new RENAME TO audiobooks;
+
+CREATE INDEX IF NOT EXISTS idx_audiobooks_author ON audiobooks (author);
+
+CREATE INDEX IF NOT EXISTS idx_audiobooks_title ON audiobooks (title);
+
+CREATE INDEX IF NOT EXISTS idx_audiobooks_library ON audiobooks (library_id);
+
+-- Dropping the old table took its trigger with it
+CREATE TRIGGER update_audiobooks_timestamp
+AFTER UPDATE ON audiobooks
+FOR EACH ROW
+BEGIN
+    UPDATE audiobooks SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE INDEX IF NOT EXISTS idx_scan_cache_library ON file_scan_cache (library_id);
@@ -0,0 +1,18 @@
+-- One row per signed in device. Access tokens carry the session id so a
+-- revoked session stops working before its token expires.
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY, -- uuid, the `sid` claim
+    user_id INTEGER NOT NULL,
+    refresh_token_hash TEXT NOT NULL UNIQUE, -- sha256 of the current refresh token
+    previous_token_hash TEXT, -- last rotated out token, reuse revokes the session
+    device_name TEXT,
+    ip_address TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_seen_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_sessions_user ON sessions (user_id);
+CREATE INDEX IF NOT EXISTS idx_sessions_previous_token ON sessions (previous_token_hash);
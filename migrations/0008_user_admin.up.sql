@@ -0,0 +1,2 @@
+-- Disabled accounts cannot log in and their sessions stop working
+ALTER TABLE users ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT FALSE;
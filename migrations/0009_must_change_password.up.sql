@@ -0,0 +1,3 @@
+-- Set for the bootstrap admin and after an admin resets a password. The
+-- user can do nothing but change their password until it is cleared.
+ALTER TABLE users ADD COLUMN must_change_password BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,9 @@
+-- Failed logins per username and per client address, times are unix seconds
+CREATE TABLE IF NOT EXISTS login_attempts (
+    scope TEXT NOT NULL, -- user, ip
+    key TEXT NOT NULL, -- lowercased username or address
+    failures INTEGER NOT NULL DEFAULT 0,
+    last_failure_at INTEGER NOT NULL,
+    locked_until INTEGER,
+    PRIMARY KEY (scope, key)
+);
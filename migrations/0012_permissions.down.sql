@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_group_members_user;
+DROP TABLE IF EXISTS user_permissions;
+DROP TABLE IF EXISTS group_members;
+DROP TABLE IF EXISTS group_permissions;
+DROP TABLE IF EXISTS groups;
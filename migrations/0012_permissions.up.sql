@@ -0,0 +1,37 @@
+-- Permissions are granted to users directly or through groups. Admins
+-- implicitly hold every permission.
+CREATE TABLE IF NOT EXISTS groups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS group_permissions (
+    group_id INTEGER NOT NULL,
+    permission TEXT NOT NULL, -- library.scan, library.organize, library.manage, upload, download, users.manage
+    PRIMARY KEY (group_id, permission),
+    FOREIGN KEY (group_id) REFERENCES groups (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS group_members (
+    group_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    PRIMARY KEY (group_id, user_id),
+    FOREIGN KEY (group_id) REFERENCES groups (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS user_permissions (
+    user_id INTEGER NOT NULL,
+    permission TEXT NOT NULL,
+    PRIMARY KEY (user_id, permission),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_group_members_user ON group_members (user_id);
+
+-- New users join Listeners unless told otherwise. Existing users keep
+-- playback but lose scanning, organising and uploading.
+INSERT INTO groups (id, name) VALUES (1, 'Listeners');
+INSERT INTO group_permissions (group_id, permission) VALUES (1, 'download');
+INSERT INTO group_members (group_id, user_id) SELECT 1, id FROM users WHERE is_admin = 0;
@@ -0,0 +1,18 @@
+-- Single use invites for self registration. Only a hash of the token is
+-- kept; library_ids and group_ids are JSON arrays, NULL meaning the defaults.
+CREATE TABLE IF NOT EXISTS invites (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    token_hash TEXT NOT NULL UNIQUE, -- sha256 of the token
+    created_by INTEGER,
+    note TEXT,
+    is_admin BOOLEAN NOT NULL DEFAULT 0,
+    library_ids TEXT,
+    group_ids TEXT,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    used_at TIMESTAMP,
+    used_by INTEGER,
+    revoked_at TIMESTAMP,
+    FOREIGN KEY (created_by) REFERENCES users (id) ON DELETE SET NULL,
+    FOREIGN KEY (used_by) REFERENCES users (id) ON DELETE SET NULL
+);
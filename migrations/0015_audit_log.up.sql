@@ -0,0 +1,20 @@
+-- Who changed what. actor_name is kept so entries stay readable after the
+-- user is deleted; before_json and after_json hold the changed state.
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    actor_id INTEGER,
+    actor_name TEXT,
+    action TEXT NOT NULL, -- e.g. user.create, library.organize, login.failure
+    target_type TEXT,
+    target_id TEXT,
+    before_json TEXT,
+    after_json TEXT,
+    request_id TEXT,
+    ip_address TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (actor_id) REFERENCES users (id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_created ON audit_log (created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_actor ON audit_log (actor_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_action ON audit_log (action, created_at);
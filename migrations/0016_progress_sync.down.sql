@@ -0,0 +1 @@
+ALTER TABLE progress DROP COLUMN device_id;
@@ -0,0 +1,3 @@
+-- updated_at now holds the time the position was reached on the client
+-- rather than when it arrived, so the newest position wins on sync.
+ALTER TABLE progress ADD COLUMN device_id TEXT;
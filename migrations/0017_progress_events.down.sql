@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_progress_events_created;
+DROP TABLE IF EXISTS progress_events;
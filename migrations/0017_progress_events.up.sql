@@ -0,0 +1,11 @@
+-- Client generated ids of progress events already applied from a batch, so
+-- a retried upload does not apply anything twice. Pruned after 30 days.
+CREATE TABLE IF NOT EXISTS progress_events (
+    user_id INTEGER NOT NULL,
+    event_id TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, event_id),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_progress_events_created ON progress_events (created_at);
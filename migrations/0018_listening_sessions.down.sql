@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_listening_sessions_open;
+DROP INDEX IF EXISTS idx_listening_sessions_user;
+DROP TABLE IF EXISTS listening_sessions;
@@ -0,0 +1,25 @@
+-- One stretch of listening on a device. Opened by an explicit start call or
+-- by the first progress update after a pause, extended by later updates and
+-- closed by a stop call, a pause longer than the idle gap or the device
+-- moving to another book. duration_ms is wall clock time.
+CREATE TABLE IF NOT EXISTS listening_sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    book_id INTEGER NOT NULL,
+    device_id TEXT,
+    start_file_id INTEGER NOT NULL,
+    start_offset_ms INTEGER NOT NULL DEFAULT 0,
+    end_file_id INTEGER NOT NULL,
+    end_offset_ms INTEGER NOT NULL DEFAULT 0,
+    playback_rate REAL NOT NULL DEFAULT 1.0,
+    started_at TIMESTAMP NOT NULL,
+    last_active_at TIMESTAMP NOT NULL,
+    closed_at TIMESTAMP,
+    duration_ms INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (book_id) REFERENCES audiobooks (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_listening_sessions_user ON listening_sessions (user_id, started_at);
+CREATE INDEX IF NOT EXISTS idx_listening_sessions_open ON listening_sessions (user_id, device_id)
+    WHERE closed_at IS NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE login_attempts DROP COLUMN pending_at;
+ALTER TABLE login_attempts DROP COLUMN pending;
@@ -0,0 +1,5 @@
+-- Logins still being verified, counted before the password is checked so
+-- parallel requests cannot all read the same failure count. pending_at lets
+-- a reservation left behind by a dropped request expire.
+ALTER TABLE login_attempts ADD COLUMN pending INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE login_attempts ADD COLUMN pending_at INTEGER NOT NULL DEFAULT 0;
@@ -1,6 +1,6 @@
 use axum::{
     Json,
-    http::StatusCode,
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
 };
 use lofty::error::LoftyError;
@@ -19,9 +19,24 @@ pub enum ApiError {
     #[error("Bad request: {0}")]
     BadRequest(String),
 
+    #[error("Not found: {0}")]
+    NotFound(String),
+
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Too many requests, retry after {0}s")]
+    TooManyRequests(u64),
+
+    #[error("Identity provider error: {0}")]
+    IdentityProvider(String),
+
+    #[error("Password change required")]
+    PasswordChangeRequired,
+
     #[error("JWT error")]
     JwtErr(#[from] jsonwebtoken::errors::Error),
 
@@ -58,7 +73,18 @@ impl IntoResponse for ApiError {
             ),
             ApiError::Internal(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg.clone()),
             ApiError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
+            ApiError::NotFound(msg) => (StatusCode::NOT_FOUND, msg.clone()),
             ApiError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg.clone()),
+            ApiError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg.clone()),
+            ApiError::TooManyRequests(_) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many failed login attempts, try again later".to_string(),
+            ),
+            ApiError::IdentityProvider(msg) => (StatusCode::BAD_GATEWAY, msg.clone()),
+            ApiError::PasswordChangeRequired => (
+                StatusCode::FORBIDDEN,
+                "Password must be changed before continuing".to_string(),
+            ),
             ApiError::JwtErr(_) => (
                 StatusCode::UNAUTHORIZED,
                 "Invalid or expired token".to_string(),
@@ -89,9 +115,26 @@ impl IntoResponse for ApiError {
             ),
         };
 
-        let body = Json(json!({
-            "error": error_message,
-        }));
+        // Lets clients tell this apart from other 403s and send the user to
+        // the change password screen
+        let body = match &self {
+            ApiError::PasswordChangeRequired => Json(json!({
+                "error": error_message,
+                "code": "password_change_required",
+            })),
+            _ => Json(json!({
+                "error": error_message,
+            })),
+        };
+
+        if let ApiError::TooManyRequests(retry_after) = self {
+            return (
+                status,
+                [(header::RETRY_AFTER, retry_after.max(1).to_string())],
+                body,
+            )
+                .into_response();
+        }
 
         (status, body).into_response()
     }
@@ -0,0 +1,135 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde_json::json;
+
+use crate::{
+    AppState,
+    api::{
+        api_error::ApiError,
+        audit::AuditContext,
+        auth_extractor::AuthUser,
+        sessions::{hash_token, random_token},
+    },
+    db::api_keys::{create_api_key, list_api_keys, revoke_api_key},
+    models::{
+        api_keys::{API_KEY_PREFIX, CreateApiKeyDto, MAX_API_KEY_TTL_DAYS},
+        audit::AuditAction,
+        events::ServerEvent,
+        user::Claims,
+    },
+    services::audit::AuditEvent,
+};
+
+const MAX_NAME_LEN: usize = 100;
+// Enough of the key to recognise it in a list without making it guessable
+const DISPLAY_PREFIX_LEN: usize = API_KEY_PREFIX.len() + 6;
+
+// A leaked key must not be able to mint more keys
+fn ensure_session(claims: &Claims) -> Result<(), ApiError> {
+    if claims.api_key.is_some() {
+        return Err(ApiError::Forbidden(
+            "API keys cannot be managed with an API key".into(),
+        ));
+    }
+    Ok(())
+}
+
+// The calling user's API keys
+pub async fn list_api_keys_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+) -> Result<impl IntoResponse, ApiError> {
+    ensure_session(&claims)?;
+    let keys = list_api_keys(&state.db_pool, claims.sub).await?;
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "count": keys.len(), "api_keys": keys })),
+    ))
+}
+
+// Create a key. The key itself is only ever returned here.
+pub async fn create_api_key_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    audit: AuditContext,
+    Json(payload): Json<CreateApiKeyDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    ensure_session(&claims)?;
+
+    let name = payload.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(ApiError::BadRequest(format!(
+            "Key name must be 1 to {} characters",
+            MAX_NAME_LEN
+        )));
+    }
+    if payload
+        .expires_in_days
+        .is_some_and(|days| !(1..=MAX_API_KEY_TTL_DAYS).contains(&days))
+    {
+        return Err(ApiError::BadRequest(format!(
+            "expires_in_days must be between 1 and {}",
+            MAX_API_KEY_TTL_DAYS
+        )));
+    }
+
+    let key = format!("{}{}", API_KEY_PREFIX, random_token());
+    let api_key = create_api_key(
+        &state.db_pool,
+        claims.sub,
+        name,
+        &key[..DISPLAY_PREFIX_LEN],
+        &hash_token(&key),
+        payload.scope,
+        payload.expires_in_days,
+    )
+    .await?;
+
+    audit
+        .record(
+            AuditEvent::new(AuditAction::ApiKeyCreated)
+                .actor(&claims)
+                .target("api_key", api_key.id)
+                .after(&api_key),
+        )
+        .await;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "message": "Store this key now, it will not be shown again",
+            "key": key,
+            "api_key": api_key,
+        })),
+    ))
+}
+
+pub async fn revoke_api_key_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    audit: AuditContext,
+    Path(key_id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    ensure_session(&claims)?;
+    if !revoke_api_key(&state.db_pool, claims.sub, key_id).await? {
+        return Err(ApiError::NotFound("API key not found".into()));
+    }
+    state
+        .events
+        .to_user(claims.sub, ServerEvent::ApiKeyRevoked { key_id });
+    audit
+        .record(
+            AuditEvent::new(AuditAction::ApiKeyRevoked)
+                .actor(&claims)
+                .target("api_key", key_id),
+        )
+        .await;
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "message": "API key revoked", "id": key_id })),
+    ))
+}
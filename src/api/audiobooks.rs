@@ -1,37 +1,56 @@
+use crate::api::audit::AuditContext;
 use crate::api::auth_extractor::AuthUser;
-use crate::db::audiobooks::{get_file_path, get_files_by_book_id, list_all_books};
-use crate::db::meta_scan::{get_grouped_files, scan_cache_count};
-use crate::file_ops::book_cover::cover_links;
+use crate::api::libraries::{
+    accessible_library, ensure_book_access, ensure_file_access, library_scope, submit_library_scan,
+};
+use crate::api::middleware::{Permitted, perm};
+use crate::api::range::serve_file;
+use crate::db::audiobooks::{
+    count_books, get_audiobook_by_id, get_chapters_by_book_id, get_file_path, get_files_by_book_id,
+    list_books_page,
+};
+use crate::db::libraries::get_library;
+use crate::db::meta_scan::{get_grouped_files, get_organized_files, scan_cache_count};
+use crate::file_ops::book_cover::{
+    cover_content_type, cover_links, cover_path, ensure_thumbnail, thumb_size,
+};
+use crate::file_ops::chapters::book_chapters;
 use crate::file_ops::org_books::save_organized_books;
-use crate::file_ops::{file_ops, scan_files::scan_files};
-use crate::models::audiobooks::FileMetadata;
+use crate::file_ops::scan_files::scan_files;
+use crate::file_ops::zip_stream::{ZipEntry, ZipStream, relative_entry_name};
+use crate::models::audiobooks::{BookListItem, BookListQuery, CoverQuery, FileMetadata};
+use crate::models::audit::AuditAction;
+use crate::models::events::{LibraryUpdateReason, ServerEvent};
+use crate::models::libraries::{DEFAULT_LIBRARY_ID, ScanQuery};
 use crate::models::meta_scan::ChangeDto;
+use crate::services::audit::AuditEvent;
 use crate::{AppState, api::api_error::ApiError};
-use axum::extract::Multipart;
+use axum::body::Body;
+use axum::extract::{Multipart, Query};
 use axum::http::HeaderMap;
 use axum::{
     Json,
-    body::Body,
     extract::{Path, State},
     http::{Response, StatusCode, header},
     response::IntoResponse,
 };
 
-use lofty::io::Length;
-use serde::Deserialize;
 use sqlx::{Pool, Sqlite};
 
+use chrono::{DateTime, Utc};
 use serde_json::json;
-use std::io::Write;
+use std::collections::HashSet;
 use std::path::PathBuf;
 use tokio::fs::{self, File, create_dir_all, read_dir, remove_dir_all};
-use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom};
-use zip::CompressionMethod;
-use zip::write::FileOptions;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
 
 pub async fn upload_handler(
-    State(state): State<AppState>,
-    AuthUser(_claims): AuthUser,
+    State(_state): State<AppState>,
+    Permitted(claims, _): Permitted<perm::Upload>,
+    audit: AuditContext,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse, String> {
     let mut file_name = None;
@@ -71,7 +90,7 @@ pub async fn upload_handler(
     // Save chunk
     let chunk_path = format!("{parts_dir}/{chunk_index}");
     let mut f = File::create(&chunk_path).await.unwrap();
-    f.write(&file_bytes).await.unwrap();
+    f.write_all(&file_bytes).await.unwrap();
 
     let mut item_count = 0;
     let mut entries = read_dir(&parts_dir).await.unwrap();
@@ -98,6 +117,14 @@ pub async fn upload_handler(
         // cleanup
         remove_dir_all(&parts_dir).await.unwrap();
         println!("✅ File saved to {final_path}");
+        audit
+            .record(
+                AuditEvent::new(AuditAction::FileUploaded)
+                    .actor(&claims)
+                    .target("file", &final_path)
+                    .after(&json!({ "path": final_path, "chunks": total_chunks })),
+            )
+            .await;
         return Ok((
             StatusCode::OK,
             Json(json!({
@@ -116,36 +143,36 @@ pub async fn upload_handler(
     ))
 }
 
-// Scan all audiobook files on local hard drive
+// Queue a scan of all audiobook files on local hard drive, progress is
+// reported through /api/jobs/{id}
 pub async fn scan_files_handler(
     State(state): State<AppState>,
-    AuthUser(_claims): AuthUser,
+    Permitted(claims, _): Permitted<perm::LibraryScan>,
+    audit: AuditContext,
+    Query(query): Query<ScanQuery>,
 ) -> Result<impl IntoResponse, ApiError> {
-    let path = &state.config.book_files;
-    let db = &state.db_pool;
-
-    let files_count = scan_files(path, db).await?;
-    Ok((
-        StatusCode::OK,
-        Json(json!({
-            "message": "Scan completed successfully",
-            "files_scanned": files_count,
-        })),
-        // TODO: Append failed scan locations into a warn/ err array response
-    ))
+    let library_id = query.library_id.unwrap_or(DEFAULT_LIBRARY_ID);
+    let library = accessible_library(&state.db_pool, &claims, library_id).await?;
+    submit_library_scan(&state, &library, &claims, &audit).await
 }
 
 // Get list of all audiobookfiles grouped by author -> book -> files
 pub async fn list_scanned_files_handler(
     State(state): State<AppState>,
-    AuthUser(_claims): AuthUser,
+    Permitted(_claims, _): Permitted<perm::LibraryOrganize>,
 ) -> Result<impl IntoResponse, ApiError> {
-    let path = &state.config.book_files;
     let db = &state.db_pool;
 
     if scan_cache_count(db).await? == 0 {
-        scan_files(path, db).await?;
-        cover_links(db).await?;
+        let library = get_library(db, DEFAULT_LIBRARY_ID)
+            .await?
+            .ok_or_else(|| ApiError::NotFound("Library not found".into()))?;
+        let _guard = state.jobs.scan_lock().await;
+        // A queued job may have filled the cache while we waited
+        if scan_cache_count(db).await? == 0 {
+            scan_files(&library.root_path, library.id, db, None).await?;
+            cover_links(db).await?;
+        }
     }
     let grouped_files = get_grouped_files(db).await?;
 
@@ -160,11 +187,52 @@ pub async fn list_scanned_files_handler(
 // Save organization made by user on their local audiofiles
 pub async fn save_organized_files_handler(
     State(state): State<AppState>,
-    AuthUser(_claims): AuthUser,
+    Permitted(claims, _): Permitted<perm::LibraryOrganize>,
+    audit: AuditContext,
     Json(payload): Json<Vec<ChangeDto>>,
 ) -> Result<impl IntoResponse, ApiError> {
     let db = &state.db_pool;
-    let _ = save_organized_books(db, payload).await;
+
+    let mut file_ids: Vec<i64> = payload.iter().flat_map(|c| c.file_ids.clone()).collect();
+    file_ids.sort_unstable();
+    file_ids.dedup();
+    let before = get_organized_files(db, &file_ids).await?;
+
+    match save_organized_books(db, payload.clone()).await {
+        Ok(()) => {
+            let library_ids: HashSet<i64> = before.iter().map(|f| f.library_id).collect();
+            for library_id in library_ids {
+                state.events.to_library(
+                    library_id,
+                    ServerEvent::LibraryUpdated {
+                        library_id,
+                        reason: LibraryUpdateReason::Organize,
+                        stats: None,
+                    },
+                );
+            }
+            audit
+                .record(
+                    AuditEvent::new(AuditAction::LibraryOrganized)
+                        .actor(&claims)
+                        .target(
+                            "files",
+                            file_ids
+                                .iter()
+                                .map(i64::to_string)
+                                .collect::<Vec<_>>()
+                                .join(","),
+                        )
+                        .before(&before)
+                        .after(&json!({
+                            "changes": payload,
+                            "files": get_organized_files(db, &file_ids).await?,
+                        })),
+                )
+                .await;
+        }
+        Err(e) => tracing::error!("Failed to save organized files {}", e),
+    }
     Ok((
         StatusCode::OK,
         Json(json!({
@@ -173,145 +241,175 @@ pub async fn save_organized_files_handler(
     ))
 }
 
-// List audiobooks from AudioBooks table
+// List audiobooks a page at a time, sorted and filtered for the calling user
 pub async fn list_books_handler(
     State(state): State<AppState>,
-    AuthUser(_claims): AuthUser,
+    AuthUser(claims): AuthUser,
+    Query(query): Query<BookListQuery>,
 ) -> Result<impl IntoResponse, ApiError> {
     let db = &state.db_pool;
-    match list_all_books(&db).await {
-        Ok(books) => Ok(Json(json!({
-            "message": "Books list",
-            "count": books.len(),
-            "books": books
-        }))),
-        Err(e) => {
-            // Log the detailed error for debugging
-            tracing::error!("Error scanning files: {}", e);
-            Err(ApiError::Internal("Failed to scan audiobooks".to_string()))
-        }
-    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let scope = library_scope(db, &claims).await?;
+    let page = list_books_page(db, claims.sub, &query, scope.as_deref(), limit).await?;
+    let library_total = count_books(db, scope.as_deref()).await?;
+    let books: Vec<BookListItem> = page.books.into_iter().map(BookListItem::from).collect();
+
+    Ok(Json(json!({
+        "message": "Books list",
+        "count": books.len(),
+        "total": page.total,
+        "library_total": library_total,
+        "next_cursor": page.next_cursor,
+        "books": books
+    })))
 }
 
-// User downloads entire book
+// User downloads entire book as a zip streamed straight from disk
 pub async fn download_book(
     State(state): State<AppState>,
     Path(book_id): Path<i64>,
-    AuthUser(_claims): AuthUser,
-) -> impl IntoResponse {
-    let files = match get_file_metadata(&state.db_pool, book_id).await {
-        Ok(f) => f,
-        Err(_) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Error retrieving files".to_string(),
-            )
-                .into_response();
-        }
-    };
-
-    let mut buffer = Vec::new();
-    {
-        let cursor = std::io::Cursor::new(&mut buffer);
-        let mut zip = zip::ZipWriter::new(cursor);
-
-        let options: FileOptions<'_, ()> = FileOptions::default()
-            .compression_method(CompressionMethod::Stored)
-            .unix_permissions(0o644);
+    Permitted(claims, _): Permitted<perm::Download>,
+) -> Result<impl IntoResponse, ApiError> {
+    ensure_book_access(&state.db_pool, &claims, book_id).await?;
+    let book = get_audiobook_by_id(&state.db_pool, book_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Book {} not found", book_id)))?;
 
-        for file in files {
-            let file_name = file.data.file_path.clone();
-            zip.start_file(&file_name, options).unwrap();
+    let mut files = get_files_by_book_id(&state.db_pool, book_id).await?;
+    if files.is_empty() {
+        return Err(ApiError::NotFound(format!(
+            "No files found for book {}",
+            book_id
+        )));
+    }
+    files.sort_by_key(|f| (f.data.file_id, f.id));
+
+    let book_root = std::path::Path::new(&book.files_location);
+    let mut entries = Vec::with_capacity(files.len());
+    let mut used_names = HashSet::new();
+
+    // Stat everything up front so a missing file fails the request
+    // instead of producing a truncated archive
+    for file in files {
+        let path = PathBuf::from(&file.data.file_path);
+        let meta = fs::metadata(&path).await.map_err(|e| {
+            tracing::error!(
+                "Missing file for book {}: {} {}",
+                book_id,
+                path.display(),
+                e
+            );
+            ApiError::NotFound(format!("File missing on server: {}", file.data.file_name))
+        })?;
 
-            // Read file content asynchronously
-            if let Ok(data) = tokio::fs::read(&file_name).await {
-                zip.write_all(&data).unwrap();
-            }
+        let mut name = relative_entry_name(book_root, &path);
+        if !used_names.insert(name.clone()) {
+            name = format!("{}_{}", file.id, name);
+            used_names.insert(name.clone());
         }
-        zip.finish().unwrap();
+
+        entries.push(ZipEntry {
+            name,
+            path,
+            size: meta.len(),
+            modified: meta
+                .modified()
+                .map(DateTime::<Utc>::from)
+                .unwrap_or_else(|_| Utc::now()),
+        });
     }
 
-    // 3. Create Content-Disposition header
+    let zip = ZipStream::new(entries);
     let disposition_value = format!("attachment; filename=\"book_{}.zip\"", book_id);
 
-    // 4. Build the response with headers
     Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "application/zip")
         .header(header::CONTENT_DISPOSITION, disposition_value)
-        .body(Body::from(buffer))
-        .unwrap()
+        .header(header::CONTENT_LENGTH, zip.content_length())
+        .body(zip.into_body())
+        .map_err(|e| ApiError::Internal(format!("Failed to build response: {e}")))
 }
 
-#[derive(Debug, Deserialize)]
-pub struct ChunkParams {
-    pub index: i64,
-    pub size: i64,
-}
+// Stream a single audio file, honouring Range requests for seeking
 pub async fn download_chunk(
     State(state): State<AppState>,
-    AuthUser(_claims): AuthUser,
+    Permitted(claims, _): Permitted<perm::Download>,
     Path(file_id): Path<i64>,
     headers: HeaderMap,
 ) -> Result<impl IntoResponse, ApiError> {
+    ensure_file_access(&state.db_pool, &claims, file_id).await?;
     let file_path = get_file_path(&state.db_pool, file_id).await?;
     tracing::info!("Download init {file_path}");
-    if !PathBuf::new().join(file_path.clone()).exists() {
-        return Err(ApiError::BadRequest("File not found".into()));
-    }
 
-    let mut file = File::open(&file_path).await.unwrap();
-    let file_size = file.metadata().await.unwrap().len();
-
-    // parse Range header manually
-    let (start, end) = if let Some(range) = headers.get("range") {
-        let range_str = range.to_str().unwrap_or("");
-
-        // Expecting "bytes=start-end"
-        if let Some(range_vals) = range_str.strip_prefix("bytes=") {
-            let mut parts = range_vals.split('-');
-            let start: u64 = parts.next().unwrap_or("0").parse().unwrap_or(0);
-            let end: u64 = parts
-                .next()
-                .unwrap_or(&(file_size - 1).to_string())
-                .parse()
-                .unwrap_or(file_size - 1);
-            (start, end)
-        } else {
-            (0, file_size - 1)
-        }
-    } else {
-        (0, file_size - 1)
-    };
+    serve_file(std::path::Path::new(&file_path), &headers).await
+}
 
-    let chunk_size = end - start + 1;
-    file.seek(SeekFrom::Start(start))
-        .await
-        .map_err(|_| ApiError::Internal("Failed to seek in file".to_string()))?;
-    let mut buffer = vec![0; chunk_size as usize];
+// Serve the book's cover, or one of its thumbnails with ?size=small|medium|large
+pub async fn book_cover_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(book_id): Path<i64>,
+    Query(query): Query<CoverQuery>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    ensure_book_access(&state.db_pool, &claims, book_id).await?;
+    let size = thumb_size(query.size.as_deref())?;
+    let book = get_audiobook_by_id(&state.db_pool, book_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Book not found".into()))?;
+    let key = book
+        .cover_art
+        .ok_or_else(|| ApiError::NotFound("Book has no cover".into()))?;
+
+    // The key is a content hash, so it doubles as a strong validator
+    let etag = format!("\"{}-{}\"", key, size.unwrap_or(0));
+    let cache_control = "private, max-age=86400";
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| {
+            v.split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        });
+    if not_modified {
+        return Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::ETAG, &etag)
+            .header(header::CACHE_CONTROL, cache_control)
+            .body(Body::empty())
+            .map_err(|e| ApiError::Internal(format!("Failed to build response: {e}")));
+    }
 
-    file.read_exact(&mut buffer)
+    let path = match size {
+        Some(px) => ensure_thumbnail(&key, px).await?,
+        None => cover_path(&key, None)?,
+    };
+    let data = fs::read(&path)
         .await
-        .map_err(|_| ApiError::Internal("Failed to read file".to_string()))?;
+        .map_err(|_| ApiError::NotFound("Cover not found".into()))?;
 
-    let content_range = format!("bytes {}-{}/{}", start, end, file_size);
-
-    Ok((
-        StatusCode::PARTIAL_CONTENT,
-        [
-            ("Content-Type", "audio/mpeg".to_owned()),
-            ("Content-Length", chunk_size.to_string()),
-            ("Content-Range", content_range.to_owned()),
-        ],
-        buffer.to_owned(),
-    ))
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, cover_content_type(&path))
+        .header(header::CONTENT_LENGTH, data.len())
+        .header(header::ETAG, &etag)
+        .header(header::CACHE_CONTROL, cache_control)
+        .body(Body::from(data))
+        .map_err(|e| ApiError::Internal(format!("Failed to build response: {e}")))
 }
 
 pub async fn file_metadata(
     State(state): State<AppState>,
-    AuthUser(_claims): AuthUser,
+    AuthUser(claims): AuthUser,
     Path(book_id): Path<i64>,
 ) -> Result<impl IntoResponse, ApiError> {
+    ensure_book_access(&state.db_pool, &claims, book_id).await?;
     let files = get_file_metadata(&state.db_pool, book_id)
         .await
         .map_err(|e| {
@@ -326,6 +424,32 @@ pub async fn file_metadata(
     })))
 }
 
+// Chapters across all files of a book with book-global offsets
+pub async fn book_chapters_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(book_id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    ensure_book_access(&state.db_pool, &claims, book_id).await?;
+    let mut files = get_files_by_book_id(&state.db_pool, book_id).await?;
+    if files.is_empty() {
+        return Err(ApiError::NotFound(format!(
+            "No files found for book {}",
+            book_id
+        )));
+    }
+    files.sort_by_key(|f| (f.data.file_id, f.id));
+
+    let rows = get_chapters_by_book_id(&state.db_pool, book_id).await?;
+    let chapters = book_chapters(&files, &rows);
+
+    Ok(Json(json!({
+        "message": "",
+        "count": chapters.len(),
+        "data": chapters,
+    })))
+}
+
 async fn get_file_metadata(db: &Pool<Sqlite>, book_id: i64) -> anyhow::Result<Vec<FileMetadata>> {
     let files = get_files_by_book_id(db, book_id).await.map_err(|e| {
         eprintln!("Error retrieving files from db: {e}");
@@ -0,0 +1,144 @@
+use std::net::SocketAddr;
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, FromRef, FromRequestParts, Query, State},
+    http::{StatusCode, request::Parts},
+    response::IntoResponse,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::json;
+use sqlx::SqlitePool;
+
+use crate::{
+    AppState,
+    api::{api_error::ApiError, middleware::AdminUser, sessions::client_ip},
+    db::audit::list_audit_entries,
+    models::audit::{AuditEntry, AuditFilter, AuditQuery},
+    services::audit::{AuditEvent, record},
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 500;
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Request details stamped on every audit entry a handler writes: the id
+/// set by `SetRequestIdLayer` and the client address
+pub struct AuditContext {
+    db: SqlitePool,
+    request_id: Option<String>,
+    ip_address: Option<String>,
+}
+
+impl AuditContext {
+    pub async fn record(&self, event: AuditEvent) {
+        record(
+            &self.db,
+            event,
+            self.request_id.as_deref(),
+            self.ip_address.as_deref(),
+        )
+        .await;
+    }
+}
+
+impl<S> FromRequestParts<S> for AuditContext
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AppState::from_ref(state);
+        let request_id = parts
+            .headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let ip_address = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| client_ip(&state, &parts.headers, *addr));
+
+        Ok(AuditContext {
+            db: state.db_pool.clone(),
+            request_id,
+            ip_address,
+        })
+    }
+}
+
+// Accepts RFC 3339 or a plain date, stored times are UTC in SQLite's format
+fn parse_time(name: &str, value: &str) -> Result<String, ApiError> {
+    let parsed = DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(|d| d.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+        })
+        .map_err(|_| {
+            ApiError::BadRequest(format!(
+                "{} must be an RFC 3339 timestamp or a YYYY-MM-DD date",
+                name
+            ))
+        })?;
+    Ok(parsed.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+// Query the audit log, newest first
+pub async fn list_audit_log_handler(
+    State(state): State<AppState>,
+    AdminUser(_claims): AdminUser,
+    Query(query): Query<AuditQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let (action, action_prefix) = match query.action.as_deref().map(str::trim) {
+        Some(a) if a.ends_with('*') => (None, Some(a.trim_end_matches('*').to_string())),
+        Some(a) if !a.is_empty() => (Some(a.to_string()), None),
+        _ => (None, None),
+    };
+    let filter = AuditFilter {
+        actor_id: query.actor_id,
+        actor: query.actor.filter(|a| !a.is_empty()),
+        action,
+        action_prefix,
+        since: query
+            .since
+            .as_deref()
+            .map(|v| parse_time("since", v))
+            .transpose()?,
+        until: query
+            .until
+            .as_deref()
+            .map(|v| parse_time("until", v))
+            .transpose()?,
+        before_id: query.before_id,
+    };
+
+    let entries: Vec<AuditEntry> = list_audit_entries(&state.db_pool, &filter, limit)
+        .await?
+        .into_iter()
+        .map(AuditEntry::from)
+        .collect();
+
+    // A full page may have more behind it
+    let next_before_id = if entries.len() as i64 == limit {
+        entries.last().map(|e| e.id)
+    } else {
+        None
+    };
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "count": entries.len(),
+            "entries": entries,
+            "next_before_id": next_before_id,
+        })),
+    ))
+}
@@ -1,38 +1,150 @@
-use axum::{extract::FromRequestParts, http::request::Parts};
-use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode};
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::{Method, request::Parts},
+};
 
-use crate::{api::api_error::ApiError, models::user::Claims};
+use crate::{
+    AppState,
+    api::api_error::ApiError,
+    api::sessions::hash_token,
+    db::{api_keys::find_api_key, permissions::effective_permissions, sessions::check_session},
+    models::{
+        api_keys::{API_KEY_PREFIX, ApiKeyScope},
+        sessions::SessionUser,
+        user::{Claims, role_name},
+    },
+};
 
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Caller authenticated with a session JWT or an API key, sent either as
+/// `X-Api-Key` or as a bearer token
 pub struct AuthUser(pub Claims);
 
-impl<S> FromRequestParts<S> for AuthUser
+/// Like `AuthUser` but also admits users who still have to change their
+/// password. Only the change password endpoint should use it.
+pub struct PasswordChangeUser(pub Claims);
+
+async fn authenticate<S>(parts: &mut Parts, state: &S) -> Result<(Claims, SessionUser), ApiError>
 where
+    AppState: FromRef<S>,
     S: Send + Sync,
 {
-    type Rejection = ApiError;
+    let state = AppState::from_ref(state);
+    let (mut claims, user) = verify_credentials(&state, parts).await?;
 
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        let auth_header = parts
-            .headers
-            .get(axum::http::header::AUTHORIZATION)
-            .and_then(|h| h.to_str().ok())
-            .ok_or_else(|| ApiError::BadRequest("Missing Authorization header".into()))?;
+    // Looked up on every request so grants and revocations apply immediately
+    if !user.is_admin {
+        claims.permissions = effective_permissions(&state.db_pool, claims.sub).await?;
+    }
 
-        if !auth_header.starts_with("Bearer ") {
-            return Err(ApiError::BadRequest("Invalid auth header".into()));
-        }
+    Ok((claims, user))
+}
+
+async fn verify_credentials(
+    state: &AppState,
+    parts: &Parts,
+) -> Result<(Claims, SessionUser), ApiError> {
+    if let Some(key) = parts
+        .headers
+        .get(API_KEY_HEADER)
+        .and_then(|h| h.to_str().ok())
+    {
+        return authenticate_api_key(state, parts, key.trim()).await;
+    }
+
+    let auth_header = parts
+        .headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .ok_or_else(|| ApiError::Unauthorized("Missing Authorization header".into()))?;
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .ok_or_else(|| ApiError::Unauthorized("Expected a Bearer token".into()))?;
+
+    if token.starts_with(API_KEY_PREFIX) {
+        return authenticate_api_key(state, parts, token).await;
+    }
+
+    let mut claims: Claims = state.keys.verify(token)?;
+
+    // Tokens outlive a logout, the session row is what actually revokes them
+    let user = check_session(&state.db_pool, &claims.sid)
+        .await?
+        .ok_or_else(|| ApiError::Unauthorized("Session has been revoked".into()))?;
+
+    // Role changes apply immediately rather than when the token expires
+    claims.role = role_name(user.is_admin).to_string();
+
+    Ok((claims, user))
+}
 
-        let token = &auth_header[7..];
+async fn authenticate_api_key(
+    state: &AppState,
+    parts: &Parts,
+    key: &str,
+) -> Result<(Claims, SessionUser), ApiError> {
+    let owner = find_api_key(&state.db_pool, &hash_token(key))
+        .await?
+        .ok_or_else(|| ApiError::Unauthorized("Invalid or expired API key".into()))?;
 
-        let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+    // `Permitted` also refuses read-only keys by permission, this covers the
+    // routes that only need a signed in user
+    if owner.scope == ApiKeyScope::ReadOnly && !matches!(parts.method, Method::GET | Method::HEAD) {
+        return Err(ApiError::Forbidden("API key is read only".into()));
+    }
+
+    let claims = Claims {
+        sub: owner.user_id,
+        role: role_name(owner.is_admin).to_string(),
+        username: owner.username,
+        sid: String::new(),
+        exp: 0,
+        iat: 0,
+        api_key: Some(owner.scope),
+        api_key_id: Some(owner.id),
+        permissions: Vec::new(),
+    };
+    let user = SessionUser {
+        is_admin: owner.is_admin,
+        must_change_password: owner.must_change_password,
+    };
+    Ok((claims, user))
+}
+
+impl<S> FromRequestParts<S> for AuthUser
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
 
-        let token_data = decode::<Claims>(
-            token,
-            &DecodingKey::from_secret(secret.as_bytes()),
-            &Validation::new(Algorithm::HS256),
-        )
-        .map_err(|_| ApiError::BadRequest("Invalid token".into()))?;
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let (claims, user) = authenticate(parts, state).await?;
+        if user.must_change_password {
+            return Err(ApiError::PasswordChangeRequired);
+        }
+        Ok(AuthUser(claims))
+    }
+}
 
-        Ok(AuthUser(token_data.claims))
+impl<S> FromRequestParts<S> for PasswordChangeUser
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let (claims, _) = authenticate(parts, state).await?;
+        if claims.api_key.is_some() {
+            return Err(ApiError::Forbidden(
+                "Passwords cannot be changed with an API key".into(),
+            ));
+        }
+        Ok(PasswordChangeUser(claims))
     }
 }
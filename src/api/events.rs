@@ -0,0 +1,134 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::State,
+    response::{
+        IntoResponse,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use chrono::Utc;
+use futures::stream;
+use serde_json::json;
+use sqlx::SqlitePool;
+use tokio::{
+    sync::broadcast::{Receiver, error::RecvError},
+    time::{Instant, sleep_until},
+};
+
+use crate::{
+    AppState,
+    api::{api_error::ApiError, auth_extractor::AuthUser},
+    db::{api_keys::api_key_active, libraries::can_access_library},
+    models::user::Claims,
+    services::events::{Audience, Envelope},
+};
+
+// API keys do not expire with a token, streams opened with one check the
+// key is still usable this often
+const API_KEY_RECHECK: Duration = Duration::from_secs(60);
+
+/// One connected client, reading the shared channel for its own events
+struct Subscriber {
+    db: SqlitePool,
+    claims: Claims,
+    rx: Receiver<Arc<Envelope>>,
+    // When the access token runs out, or the next API key check is due
+    deadline: Instant,
+    closed: bool,
+}
+
+impl Subscriber {
+    async fn wants(&self, audience: Audience) -> bool {
+        match audience {
+            Audience::User(user_id) => user_id == self.claims.sub,
+            Audience::Library(library_id) => {
+                self.claims.is_admin()
+                    || can_access_library(&self.db, self.claims.sub, library_id)
+                        .await
+                        .unwrap_or(false)
+            }
+        }
+    }
+
+    // Tokens are done at the deadline, keys only once revoked, expired or
+    // their user disabled
+    async fn still_valid(&self) -> bool {
+        match self.claims.api_key_id {
+            Some(key_id) => api_key_active(&self.db, key_id).await.unwrap_or(false),
+            None => false,
+        }
+    }
+
+    async fn next_event(&mut self) -> Option<Result<Event, axum::Error>> {
+        if self.closed {
+            return None;
+        }
+        loop {
+            let received = tokio::select! {
+                received = self.rx.recv() => received,
+                _ = sleep_until(self.deadline) => {
+                    if self.still_valid().await {
+                        self.deadline = Instant::now() + API_KEY_RECHECK;
+                        continue;
+                    }
+                    self.closed = true;
+                    return Some(Ok(Event::default().event("token_expired").data("{}")));
+                }
+            };
+
+            match received {
+                Ok(envelope) => {
+                    if !self.wants(envelope.audience).await {
+                        continue;
+                    }
+                    let event = &envelope.event;
+                    let ends = match self.claims.api_key_id {
+                        Some(key_id) => event.ends_api_key(key_id),
+                        None => event.ends_session(&self.claims.sid),
+                    };
+                    if ends {
+                        self.closed = true;
+                    }
+                    return Some(Event::default().event(event.name()).json_data(event));
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    return Some(
+                        Event::default()
+                            .event("lagged")
+                            .json_data(json!({ "missed": missed })),
+                    );
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+// Server-sent events for the calling user: progress from their other
+// devices, library updates and session revocations. A `lagged` event means
+// some were missed and the client should refetch.
+pub async fn events_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+) -> Result<impl IntoResponse, ApiError> {
+    let deadline = if claims.api_key_id.is_some() {
+        Instant::now() + API_KEY_RECHECK
+    } else {
+        let left = claims.exp as i64 - Utc::now().timestamp();
+        Instant::now() + Duration::from_secs(left.max(0) as u64)
+    };
+    let subscriber = Subscriber {
+        db: state.db_pool.clone(),
+        rx: state.events.subscribe(),
+        claims,
+        deadline,
+        closed: false,
+    };
+
+    let stream = stream::unfold(subscriber, |mut subscriber| async move {
+        let event = subscriber.next_event().await?;
+        Some((event, subscriber))
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
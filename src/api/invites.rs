@@ -0,0 +1,206 @@
+use std::net::SocketAddr;
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use serde_json::json;
+
+use crate::{
+    AppState,
+    api::{
+        api_error::ApiError,
+        audit::AuditContext,
+        libraries::ensure_can_grant_libraries,
+        middleware::{Permitted, perm},
+        sessions::{client_ip, hash_token, random_token, start_session},
+        user::{ensure_known_ids, provision_user, requested_libraries, validate_new_user},
+    },
+    db::invites::{
+        consume_invite, create_invite, get_invite, list_invites, revoke_invite, set_invite_used_by,
+    },
+    models::{
+        audit::AuditAction,
+        invites::{
+            CreateInviteDto, DEFAULT_INVITE_TTL_HOURS, Invite, MAX_INVITE_TTL_HOURS, RegisterDto,
+            parse_ids,
+        },
+        user::UserDto,
+    },
+    services::audit::AuditEvent,
+};
+
+const MAX_NOTE_LEN: usize = 200;
+
+pub async fn list_invites_handler(
+    State(state): State<AppState>,
+    Permitted(_claims, _): Permitted<perm::UsersManage>,
+) -> Result<impl IntoResponse, ApiError> {
+    let invites: Vec<Invite> = list_invites(&state.db_pool)
+        .await?
+        .into_iter()
+        .map(Invite::from)
+        .collect();
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "count": invites.len(), "invites": invites })),
+    ))
+}
+
+// Create a single use invite. The token is only ever returned here.
+pub async fn create_invite_handler(
+    State(state): State<AppState>,
+    Permitted(claims, _): Permitted<perm::UsersManage>,
+    audit: AuditContext,
+    Json(payload): Json<CreateInviteDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    // Same limits as creating the user directly
+    if !claims.is_admin() && (payload.is_admin || payload.group_ids.is_some()) {
+        return Err(ApiError::Forbidden(
+            "Only admins can invite admins or pick groups".into(),
+        ));
+    }
+    ensure_can_grant_libraries(
+        &state.db_pool,
+        &claims,
+        &requested_libraries(&payload.library_ids),
+    )
+    .await?;
+    ensure_known_ids(
+        &state.db_pool,
+        payload.library_ids.as_deref(),
+        payload.group_ids.as_deref(),
+    )
+    .await?;
+    if payload
+        .note
+        .as_ref()
+        .is_some_and(|n| n.chars().count() > MAX_NOTE_LEN)
+    {
+        return Err(ApiError::BadRequest(format!(
+            "Note must be at most {} characters",
+            MAX_NOTE_LEN
+        )));
+    }
+
+    let ttl = payload.expires_in_hours.unwrap_or(DEFAULT_INVITE_TTL_HOURS);
+    if !(1..=MAX_INVITE_TTL_HOURS).contains(&ttl) {
+        return Err(ApiError::BadRequest(format!(
+            "expires_in_hours must be between 1 and {}",
+            MAX_INVITE_TTL_HOURS
+        )));
+    }
+
+    let token = random_token();
+    let invite = create_invite(
+        &state.db_pool,
+        &hash_token(&token),
+        claims.sub,
+        &payload,
+        ttl,
+    )
+    .await?;
+
+    let invite = Invite::from(invite);
+    audit
+        .record(
+            AuditEvent::new(AuditAction::InviteCreated)
+                .actor(&claims)
+                .target("invite", invite.id)
+                .after(&invite),
+        )
+        .await;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "token": token,
+            "invite": invite,
+            "message": "Share this token now, it will not be shown again",
+        })),
+    ))
+}
+
+pub async fn revoke_invite_handler(
+    State(state): State<AppState>,
+    Permitted(claims, _): Permitted<perm::UsersManage>,
+    audit: AuditContext,
+    Path(invite_id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let invite = get_invite(db, invite_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Invite not found".into()))?;
+
+    if invite.is_admin && !claims.is_admin() {
+        return Err(ApiError::Forbidden(
+            "Admin invites can only be revoked by admins".into(),
+        ));
+    }
+    if !revoke_invite(db, invite_id).await? {
+        return Err(ApiError::BadRequest("Invite has already been used".into()));
+    }
+    audit
+        .record(
+            AuditEvent::new(AuditAction::InviteRevoked)
+                .actor(&claims)
+                .target("invite", invite_id),
+        )
+        .await;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "message": "Invite revoked", "id": invite_id })),
+    ))
+}
+
+// Public: create an account from an invite and sign straight in
+pub async fn register_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    audit: AuditContext,
+    Json(payload): Json<RegisterDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+
+    let mut user = UserDto {
+        username: payload.username.trim().to_string(),
+        password: payload.password,
+        is_admin: false,
+        must_change_password: false,
+        library_ids: None,
+        permissions: None,
+        group_ids: None,
+    };
+    validate_new_user(db, &user).await?;
+
+    // One transaction, so a registration that fails (say racing for the
+    // same username) never burns the invite
+    let mut tx = db.begin().await?;
+    let grant = consume_invite(&mut *tx, &hash_token(payload.token.trim()))
+        .await?
+        .ok_or_else(|| ApiError::Unauthorized("Invite is invalid, used or expired".into()))?;
+
+    user.is_admin = grant.is_admin;
+    user.library_ids = parse_ids(grant.library_ids.as_deref());
+    user.group_ids = parse_ids(grant.group_ids.as_deref());
+
+    let created = provision_user(&mut tx, &user).await?;
+    set_invite_used_by(&mut *tx, grant.id, created.id).await?;
+    tx.commit().await?;
+    audit
+        .record(
+            AuditEvent::new(AuditAction::UserRegistered)
+                .actor_user(Some(created.id), &created.username)
+                .target("invite", grant.id),
+        )
+        .await;
+
+    let ip = client_ip(&state, &headers, addr);
+    let tokens = start_session(&state, &created, payload.device_name.as_deref(), &ip).await?;
+
+    Ok((StatusCode::CREATED, Json(tokens)))
+}
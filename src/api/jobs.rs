@@ -0,0 +1,82 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde_json::json;
+
+use crate::{
+    AppState,
+    api::{
+        api_error::ApiError,
+        audit::AuditContext,
+        middleware::{Permitted, perm},
+    },
+    models::audit::AuditAction,
+    services::audit::AuditEvent,
+};
+
+pub async fn list_jobs(
+    State(state): State<AppState>,
+    Permitted(_claims, _): Permitted<perm::LibraryScan>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "jobs": state.jobs.list(),
+        })),
+    ))
+}
+
+pub async fn get_job(
+    State(state): State<AppState>,
+    Permitted(_claims, _): Permitted<perm::LibraryScan>,
+    Path(job_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let job = state
+        .jobs
+        .get(&job_id)
+        .ok_or_else(|| ApiError::NotFound("Job not found".into()))?;
+
+    Ok((StatusCode::OK, Json(job.report())))
+}
+
+// Only the user who queued the job or an admin may cancel it
+pub async fn cancel_job(
+    State(state): State<AppState>,
+    Permitted(claims, _): Permitted<perm::LibraryScan>,
+    audit: AuditContext,
+    Path(job_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let job = state
+        .jobs
+        .get(&job_id)
+        .ok_or_else(|| ApiError::NotFound("Job not found".into()))?;
+
+    let report = job.report();
+    if report.requested_by != claims.sub && !claims.is_admin() {
+        return Err(ApiError::Forbidden(
+            "Only the job owner can cancel it".into(),
+        ));
+    }
+    if report.phase.is_finished() {
+        return Err(ApiError::BadRequest("Job has already finished".into()));
+    }
+
+    job.cancel();
+    audit
+        .record(
+            AuditEvent::new(AuditAction::ScanCancelled)
+                .actor(&claims)
+                .target("job", &report.id),
+        )
+        .await;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "message": "Cancellation requested",
+            "job_id": report.id,
+        })),
+    ))
+}
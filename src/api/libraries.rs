@@ -0,0 +1,421 @@
+use std::path::{MAIN_SEPARATOR, Path as FsPath};
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde_json::json;
+use sqlx::SqlitePool;
+
+use crate::{
+    AppState,
+    api::{
+        api_error::ApiError,
+        audit::AuditContext,
+        auth_extractor::AuthUser,
+        middleware::{Permitted, perm},
+    },
+    db::{
+        libraries::{
+            accessible_library_ids, can_access_book, can_access_file, can_access_library,
+            create_library, delete_library, get_library, list_libraries, list_library_access,
+            list_user_libraries, mark_outside_root_missing, set_library_access, update_library,
+        },
+        search::rebuild_search_index,
+    },
+    models::{
+        audit::AuditAction,
+        libraries::{
+            DEFAULT_LIBRARY_ID, Library, LibraryAccessDto, LibraryDto, LibraryUpdateDto,
+            MEDIA_TYPES,
+        },
+        user::Claims,
+    },
+    services::{audit::AuditEvent, watcher::spawn_library_watcher},
+};
+
+/// Library ids the caller may see, `None` meaning every library
+pub async fn library_scope(db: &SqlitePool, claims: &Claims) -> Result<Option<Vec<i64>>, ApiError> {
+    if claims.is_admin() {
+        return Ok(None);
+    }
+    Ok(Some(accessible_library_ids(db, claims.sub).await?))
+}
+
+/// Non-admins can only hand out access to libraries they can see themselves
+pub async fn ensure_can_grant_libraries(
+    db: &SqlitePool,
+    claims: &Claims,
+    library_ids: &[i64],
+) -> Result<(), ApiError> {
+    let Some(scope) = library_scope(db, claims).await? else {
+        return Ok(());
+    };
+    if library_ids.iter().any(|id| !scope.contains(id)) {
+        return Err(ApiError::Forbidden(
+            "Access can only be granted to your own libraries".into(),
+        ));
+    }
+    Ok(())
+}
+
+// Books outside the caller's libraries are reported as missing rather than
+// forbidden so their existence is not leaked
+pub async fn ensure_book_access(
+    db: &SqlitePool,
+    claims: &Claims,
+    book_id: i64,
+) -> Result<(), ApiError> {
+    if claims.is_admin() || can_access_book(db, claims.sub, book_id).await? {
+        return Ok(());
+    }
+    Err(ApiError::NotFound("Book not found".into()))
+}
+
+pub async fn ensure_file_access(
+    db: &SqlitePool,
+    claims: &Claims,
+    file_id: i64,
+) -> Result<(), ApiError> {
+    if claims.is_admin() || can_access_file(db, claims.sub, file_id).await? {
+        return Ok(());
+    }
+    Err(ApiError::NotFound("File not found".into()))
+}
+
+/// Loads a library the caller is allowed to see
+pub async fn accessible_library(
+    db: &SqlitePool,
+    claims: &Claims,
+    library_id: i64,
+) -> Result<Library, ApiError> {
+    let library = get_library(db, library_id).await?;
+    match library {
+        Some(library)
+            if claims.is_admin() || can_access_library(db, claims.sub, library_id).await? =>
+        {
+            Ok(library)
+        }
+        _ => Err(ApiError::NotFound("Library not found".into())),
+    }
+}
+
+fn validate_media_type(media_type: &str) -> Result<(), ApiError> {
+    if MEDIA_TYPES.contains(&media_type) {
+        return Ok(());
+    }
+    Err(ApiError::BadRequest(format!(
+        "Unknown media type {}, expected one of {}",
+        media_type,
+        MEDIA_TYPES.join(", ")
+    )))
+}
+
+// Roots are stored without a trailing separator and must be existing folders
+fn normalize_root(root: &str) -> Result<String, ApiError> {
+    let trimmed = root.trim();
+    let trimmed = match trimmed.trim_end_matches(MAIN_SEPARATOR) {
+        "" => trimmed,
+        t => t,
+    };
+
+    if trimmed.is_empty() {
+        return Err(ApiError::BadRequest("Library root path is required".into()));
+    }
+    if !FsPath::new(trimmed).is_dir() {
+        return Err(ApiError::BadRequest(format!(
+            "Library root {} is not a folder",
+            trimmed
+        )));
+    }
+    Ok(trimmed.to_string())
+}
+
+// Nested roots would have two libraries claiming the same files
+async fn check_root_overlap(
+    db: &SqlitePool,
+    root: &str,
+    except_id: Option<i64>,
+) -> Result<(), ApiError> {
+    let root_path = FsPath::new(root);
+    for library in list_libraries(db).await? {
+        if Some(library.id) == except_id || library.root_path.is_empty() {
+            continue;
+        }
+        let other = FsPath::new(&library.root_path);
+        if root_path.starts_with(other) || other.starts_with(root_path) {
+            return Err(ApiError::BadRequest(format!(
+                "Library root overlaps with library {}",
+                library.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+pub async fn list_libraries_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let libraries = if claims.is_admin() {
+        list_libraries(db).await?
+    } else {
+        list_user_libraries(db, claims.sub).await?
+    };
+
+    Ok((StatusCode::OK, Json(json!({ "libraries": libraries }))))
+}
+
+pub async fn get_library_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(library_id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let library = accessible_library(&state.db_pool, &claims, library_id).await?;
+    Ok((StatusCode::OK, Json(library)))
+}
+
+pub async fn create_library_handler(
+    State(state): State<AppState>,
+    Permitted(claims, _): Permitted<perm::LibraryManage>,
+    audit: AuditContext,
+    Json(payload): Json<LibraryDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(ApiError::BadRequest("Library name is required".into()));
+    }
+    let media_type = payload.media_type.as_deref().unwrap_or("audiobook");
+    validate_media_type(media_type)?;
+    let root = normalize_root(&payload.root_path)?;
+    check_root_overlap(db, &root, None).await?;
+
+    let library = create_library(db, name, &root, media_type).await?;
+    if state.config.watch_library {
+        spawn_library_watcher(
+            library.id,
+            library.root_path.clone(),
+            db.clone(),
+            state.jobs.clone(),
+            state.events.clone(),
+        );
+    }
+
+    audit
+        .record(
+            AuditEvent::new(AuditAction::LibraryCreated)
+                .actor(&claims)
+                .target("library", library.id)
+                .after(&library),
+        )
+        .await;
+    Ok((StatusCode::CREATED, Json(library)))
+}
+
+pub async fn update_library_handler(
+    State(state): State<AppState>,
+    Permitted(claims, _): Permitted<perm::LibraryManage>,
+    audit: AuditContext,
+    Path(library_id): Path<i64>,
+    Json(payload): Json<LibraryUpdateDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let mut library = get_library(db, library_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Library not found".into()))?;
+    let before = library.clone();
+
+    if let Some(name) = payload.name {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ApiError::BadRequest("Library name is required".into()));
+        }
+        library.name = name.to_string();
+    }
+    if let Some(media_type) = payload.media_type {
+        validate_media_type(&media_type)?;
+        library.media_type = media_type;
+    }
+
+    let mut root_changed = false;
+    if let Some(root_path) = payload.root_path {
+        let root = normalize_root(&root_path)?;
+        check_root_overlap(db, &root, Some(library_id)).await?;
+        root_changed = root != library.root_path;
+        library.root_path = root;
+    }
+
+    let library = if root_changed {
+        // The old root's watcher must not rescan into the moved library
+        state.jobs.stop_watching(library_id);
+        // Files under the old root no longer belong to this library
+        let _guard = state.jobs.scan_lock().await;
+        let library = update_library(db, &library).await?;
+        mark_outside_root_missing(db, library.id, &library.root_path).await?;
+        library
+    } else {
+        update_library(db, &library).await?
+    };
+
+    if root_changed && state.config.watch_library {
+        spawn_library_watcher(
+            library.id,
+            library.root_path.clone(),
+            db.clone(),
+            state.jobs.clone(),
+            state.events.clone(),
+        );
+    }
+
+    audit
+        .record(
+            AuditEvent::new(AuditAction::LibraryUpdated)
+                .actor(&claims)
+                .target("library", library_id)
+                .before(&before)
+                .after(&library),
+        )
+        .await;
+    Ok((StatusCode::OK, Json(library)))
+}
+
+pub async fn delete_library_handler(
+    State(state): State<AppState>,
+    Permitted(claims, _): Permitted<perm::LibraryManage>,
+    audit: AuditContext,
+    Path(library_id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    if library_id == DEFAULT_LIBRARY_ID {
+        return Err(ApiError::BadRequest(
+            "The default library cannot be deleted".into(),
+        ));
+    }
+    let library = get_library(db, library_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Library not found".into()))?;
+
+    state.jobs.stop_watching(library_id);
+    // Wait out any scan still writing rows for this library
+    let _guard = state.jobs.scan_lock().await;
+    delete_library(db, library_id).await?;
+    rebuild_search_index(db).await?;
+    audit
+        .record(
+            AuditEvent::new(AuditAction::LibraryDeleted)
+                .actor(&claims)
+                .target("library", library_id)
+                .before(&library),
+        )
+        .await;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "message": "Library deleted", "library_id": library_id })),
+    ))
+}
+
+// Queue a scan of a single library, progress is reported through /api/jobs/{id}
+pub async fn scan_library_handler(
+    State(state): State<AppState>,
+    Permitted(claims, _): Permitted<perm::LibraryScan>,
+    audit: AuditContext,
+    Path(library_id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let library = accessible_library(&state.db_pool, &claims, library_id).await?;
+    submit_library_scan(&state, &library, &claims, &audit).await
+}
+
+pub async fn submit_library_scan(
+    state: &AppState,
+    library: &Library,
+    claims: &Claims,
+    audit: &AuditContext,
+) -> Result<impl IntoResponse + use<>, ApiError> {
+    if library.root_path.is_empty() {
+        return Err(ApiError::BadRequest("Library has no root path".into()));
+    }
+
+    let (job, created) = state.jobs.submit_scan(
+        library.id,
+        library.root_path.clone(),
+        state.db_pool.clone(),
+        claims.sub,
+    );
+
+    let message = if created {
+        audit
+            .record(
+                AuditEvent::new(AuditAction::ScanStarted)
+                    .actor(claims)
+                    .target("job", job.id())
+                    .after(&json!({ "library_id": library.id, "root": library.root_path })),
+            )
+            .await;
+        "Scan queued"
+    } else {
+        "A scan of this library is already in progress"
+    };
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "message": message,
+            "job_id": job.id(),
+            "job": job.report(),
+        })),
+    ))
+}
+
+pub async fn get_library_access_handler(
+    State(state): State<AppState>,
+    Permitted(_claims, _): Permitted<perm::LibraryManage>,
+    Path(library_id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    get_library(db, library_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Library not found".into()))?;
+
+    let users = list_library_access(db, library_id).await?;
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "library_id": library_id, "users": users })),
+    ))
+}
+
+// Replace the set of users who can see a library. Admins always see everything.
+pub async fn set_library_access_handler(
+    State(state): State<AppState>,
+    Permitted(claims, _): Permitted<perm::LibraryManage>,
+    audit: AuditContext,
+    Path(library_id): Path<i64>,
+    Json(payload): Json<LibraryAccessDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    get_library(db, library_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Library not found".into()))?;
+    let before = list_library_access(db, library_id).await?;
+
+    set_library_access(db, library_id, &payload.user_ids).await?;
+    let users = list_library_access(db, library_id).await?;
+    audit
+        .record(
+            AuditEvent::new(AuditAction::LibraryAccessChanged)
+                .actor(&claims)
+                .target("library", library_id)
+                .before(&before)
+                .after(&users),
+        )
+        .await;
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "library_id": library_id, "users": users })),
+    ))
+}
@@ -0,0 +1,175 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use chrono::{Duration, Utc};
+use serde_json::json;
+
+use crate::{
+    AppState,
+    api::{api_error::ApiError, auth_extractor::AuthUser, libraries::ensure_book_access},
+    db::{
+        listening::{
+            author_totals, book_totals, daily_totals, get_listening_session,
+            list_listening_sessions, start_listening, stop_listening,
+        },
+        sync::file_in_book,
+    },
+    models::listening::{
+        ListeningHistoryQuery, ListeningPoint, ListeningStats, StartListeningDto, StatsQuery,
+        StopListeningDto, Streak, weekly_totals,
+    },
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+const MAX_STATS_DAYS: i64 = 366;
+const MAX_UTC_OFFSET_MINUTES: i32 = 14 * 60;
+
+fn validate_position(offset_ms: i64, playback_rate: Option<f64>) -> Result<(), ApiError> {
+    if offset_ms < 0 {
+        return Err(ApiError::BadRequest(
+            "offset_ms must not be negative".into(),
+        ));
+    }
+    if playback_rate.is_some_and(|r| !(r > 0.0 && r <= 10.0)) {
+        return Err(ApiError::BadRequest(
+            "playback_rate must be above 0 and at most 10".into(),
+        ));
+    }
+    Ok(())
+}
+
+// Explicitly start a session, closing whatever the device had open
+pub async fn start_listening_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Json(payload): Json<StartListeningDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    ensure_book_access(db, &claims, payload.book_id).await?;
+    validate_position(payload.offset_ms, payload.playback_rate)?;
+    if !file_in_book(db, payload.book_id, payload.file_id).await? {
+        return Err(ApiError::NotFound("File not found".into()));
+    }
+
+    let point = ListeningPoint {
+        book_id: payload.book_id,
+        file_id: payload.file_id,
+        offset_ms: payload.offset_ms,
+        device_id: payload.device_id.as_deref(),
+        playback_rate: payload.playback_rate,
+        at: Utc::now(),
+    };
+    let mut tx = db.begin().await?;
+    let session = start_listening(&mut tx, claims.sub, &point).await?;
+    tx.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(session)))
+}
+
+pub async fn stop_listening_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(session_id): Path<i64>,
+    Json(payload): Json<StopListeningDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let session = get_listening_session(db, claims.sub, session_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Listening session not found".into()))?;
+    validate_position(payload.offset_ms, None)?;
+    if !file_in_book(db, session.book_id, payload.file_id).await? {
+        return Err(ApiError::NotFound("File not found".into()));
+    }
+
+    let point = ListeningPoint {
+        book_id: session.book_id,
+        file_id: payload.file_id,
+        offset_ms: payload.offset_ms,
+        device_id: session.device_id.as_deref(),
+        playback_rate: None,
+        at: Utc::now(),
+    };
+    let mut conn = db.acquire().await?;
+    let stopped = stop_listening(&mut conn, claims.sub, session_id, &point)
+        .await?
+        .ok_or_else(|| ApiError::BadRequest("Listening session has already ended".into()))?;
+
+    Ok((StatusCode::OK, Json(stopped)))
+}
+
+// The calling user's sessions, newest first
+pub async fn list_listening_sessions_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Query(query): Query<ListeningHistoryQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let sessions = list_listening_sessions(&state.db_pool, claims.sub, &query, limit).await?;
+    let next_before_id = match sessions.last() {
+        Some(last) if sessions.len() as i64 == limit => Some(last.id),
+        _ => None,
+    };
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "count": sessions.len(),
+            "sessions": sessions,
+            "next_before_id": next_before_id,
+        })),
+    ))
+}
+
+// Listening time per day, week, book and author over a range of local days,
+// and streaks over all time
+pub async fn listening_stats_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let offset = query.utc_offset_minutes;
+    if offset.abs() > MAX_UTC_OFFSET_MINUTES {
+        return Err(ApiError::BadRequest(
+            "utc_offset_minutes must be within 14 hours".into(),
+        ));
+    }
+    let shift = format!("{:+} minutes", offset);
+
+    let today = (Utc::now() + Duration::minutes(offset.into())).date_naive();
+    let to = query.to.unwrap_or(today);
+    let from = query.from.unwrap_or(to - Duration::days(29));
+    if from > to || (to - from).num_days() >= MAX_STATS_DAYS {
+        return Err(ApiError::BadRequest(format!(
+            "from must not be after to, and the range at most {} days",
+            MAX_STATS_DAYS
+        )));
+    }
+
+    let daily = daily_totals(db, claims.sub, &shift, Some((from, to))).await?;
+    let active_days: Vec<_> = daily_totals(db, claims.sub, &shift, None)
+        .await?
+        .into_iter()
+        .map(|d| d.day)
+        .collect();
+
+    let stats = ListeningStats {
+        from,
+        to,
+        listened_ms: daily.iter().map(|d| d.listened_ms).sum(),
+        sessions: daily.iter().map(|d| d.sessions).sum(),
+        weekly: weekly_totals(&daily),
+        daily,
+        books: book_totals(db, claims.sub, &shift, from, to).await?,
+        authors: author_totals(db, claims.sub, &shift, from, to).await?,
+        streak: Streak::from_days(&active_days, today),
+    };
+    Ok((StatusCode::OK, Json(stats)))
+}
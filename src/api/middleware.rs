@@ -1,14 +1,21 @@
-use axum::{extract::FromRequestParts, http::request::Parts};
+use std::marker::PhantomData;
+
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+};
 
 use crate::{
+    AppState,
     api::{api_error::ApiError, auth_extractor::AuthUser},
-    models::user::Claims,
+    models::{api_keys::ApiKeyScope, permissions::Permission, user::Claims},
 };
 
 pub struct AdminUser(pub Claims);
 
 impl<S> FromRequestParts<S> for AdminUser
 where
+    AppState: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = ApiError;
@@ -16,10 +23,170 @@ where
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let AuthUser(claims) = AuthUser::from_request_parts(parts, state).await?;
 
-        if claims.role != "admin" {
-            return Err(ApiError::Unauthorized("Admin access required".into()));
+        if !claims.is_admin() {
+            return Err(ApiError::Forbidden("Admin access required".into()));
         }
 
         Ok(AdminUser(claims))
     }
 }
+
+/// Marker naming the permission a `Permitted` extractor checks for
+pub trait RequiredPermission {
+    const PERMISSION: Permission;
+}
+
+/// Markers for `Permitted`, one per `Permission`
+pub mod perm {
+    use super::RequiredPermission;
+    use crate::models::permissions::Permission;
+
+    macro_rules! marker {
+        ($name:ident) => {
+            pub struct $name;
+
+            impl RequiredPermission for $name {
+                const PERMISSION: Permission = Permission::$name;
+            }
+        };
+    }
+
+    marker!(LibraryScan);
+    marker!(LibraryOrganize);
+    marker!(LibraryManage);
+    marker!(Upload);
+    marker!(Download);
+    marker!(UsersManage);
+}
+
+/// Caller holding the permission named by `P`, e.g. `Permitted<perm::Upload>`.
+/// Read-only API keys only pass for permissions that do not change anything,
+/// whatever the HTTP method of the route.
+pub struct Permitted<P>(pub Claims, pub PhantomData<P>);
+
+impl<P, S> FromRequestParts<S> for Permitted<P>
+where
+    P: RequiredPermission,
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthUser(claims) = AuthUser::from_request_parts(parts, state).await?;
+
+        if claims.api_key == Some(ApiKeyScope::ReadOnly) && !P::PERMISSION.is_read() {
+            return Err(ApiError::Forbidden("API key is read only".into()));
+        }
+        if !claims.has(P::PERMISSION) {
+            return Err(ApiError::Forbidden(format!(
+                "Missing permission {}",
+                P::PERMISSION.as_str()
+            )));
+        }
+
+        Ok(Permitted(claims, PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::Arc};
+
+    use axum::{Router, http::StatusCode};
+
+    use crate::{
+        AppState,
+        api::sessions::{hash_token, random_token},
+        config::Config,
+        db::{api_keys::create_api_key, init_db_pool, user::create_user},
+        models::api_keys::{API_KEY_PREFIX, ApiKeyScope},
+        services::{events::EventHub, jobs::JobRegistry, keys::JwtKeys},
+    };
+
+    fn test_config(database_url: String) -> Config {
+        Config {
+            database_url,
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            book_files: String::new(),
+            watch_library: false,
+            trust_proxy: false,
+            admin_username: "admin".to_string(),
+            admin_password: None,
+            jwt_secret: Ok("test secret".to_string()),
+            jwt_algorithm: "HS256".to_string(),
+            jwt_key_id: "primary".to_string(),
+            jwt_private_key_path: None,
+            jwt_public_key_path: None,
+            jwt_previous_keys: Vec::new(),
+            oidc_issuer: None,
+            oidc_client_id: None,
+            oidc_client_secret: None,
+            oidc_redirect_uri: None,
+            oidc_scopes: String::new(),
+            oidc_username_claim: String::new(),
+            oidc_groups_claim: String::new(),
+            oidc_admin_groups: Vec::new(),
+            oidc_group_map: Vec::new(),
+            oidc_auto_provision: false,
+            oidc_link_existing: false,
+        }
+    }
+
+    // Serves the API on a throwaway database in `dir` and returns its base
+    // url together with a fresh API key of `scope` owned by an admin
+    async fn start_server(dir: &Path, scope: ApiKeyScope) -> (String, String) {
+        std::fs::create_dir_all(dir).unwrap();
+        let config = test_config(format!("sqlite:{}", dir.join("test.db").display()));
+        let db_pool = init_db_pool(&config.database_url).await.unwrap();
+
+        let admin = create_user(&db_pool, "admin", &true, "", "", false)
+            .await
+            .unwrap();
+        let key = format!("{}{}", API_KEY_PREFIX, random_token());
+        create_api_key(
+            &db_pool,
+            admin.id,
+            "test",
+            "test",
+            &hash_token(&key),
+            scope,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let events = EventHub::new();
+        let state = AppState {
+            db_pool,
+            keys: Arc::new(JwtKeys::from_config(&config).unwrap()),
+            config: Arc::new(config),
+            jobs: JobRegistry::new(events.clone()),
+            events,
+            oidc: None,
+        };
+        let app = Router::new()
+            .nest("/api", crate::api::routes().await)
+            .with_state(state);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}/api", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (base, key)
+    }
+
+    #[tokio::test]
+    async fn read_only_key_cannot_start_a_scan() {
+        let dir = std::env::temp_dir().join(format!("else-wer-{}", uuid::Uuid::new_v4()));
+        let (base, key) = start_server(&dir, ApiKeyScope::ReadOnly).await;
+        let response = reqwest::Client::new()
+            .get(format!("{}/scan_files", base))
+            .bearer_auth(&key)
+            .send()
+            .await
+            .unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -2,45 +2,116 @@ use axum::{
     Router,
     extract::{DefaultBodyLimit, State},
     response::{Html, IntoResponse},
-    routing::{get, post},
+    routing::{delete, get, post, put},
 };
 use tower_http::services::ServeDir;
 pub mod api_error;
+mod api_keys;
 mod audiobooks;
-mod auth_extractor;
+mod audit;
+pub mod auth_extractor;
+mod events;
+mod invites;
+mod jobs;
+mod libraries;
+mod listening;
 mod middleware;
+mod oidc;
+mod permissions;
+mod range;
+mod search;
+mod sessions;
 mod sync;
 pub mod user;
 use crate::{
     AppState,
     api::{
+        api_keys::{create_api_key_handler, list_api_keys_handler, revoke_api_key_handler},
         audiobooks::{
-            download_book, download_chunk, file_metadata, list_books_handler,
-            list_scanned_files_handler, save_organized_files_handler, upload_handler,
+            book_chapters_handler, book_cover_handler, download_book, download_chunk,
+            file_metadata, list_books_handler, list_scanned_files_handler,
+            save_organized_files_handler, upload_handler,
+        },
+        audit::list_audit_log_handler,
+        events::events_handler,
+        invites::{
+            create_invite_handler, list_invites_handler, register_handler, revoke_invite_handler,
+        },
+        jobs::{cancel_job, get_job, list_jobs},
+        libraries::{
+            create_library_handler, delete_library_handler, get_library_access_handler,
+            get_library_handler, list_libraries_handler, scan_library_handler,
+            set_library_access_handler, update_library_handler,
+        },
+        listening::{
+            list_listening_sessions_handler, listening_stats_handler, start_listening_handler,
+            stop_listening_handler,
+        },
+        oidc::{oidc_callback_handler, oidc_login_handler},
+        permissions::{
+            create_group_handler, delete_group_handler, get_user_permissions_handler,
+            list_groups_handler, list_permissions_handler, my_permissions_handler,
+            set_group_members_handler, set_user_permissions_handler, update_group_handler,
+        },
+        search::search_handler,
+        sessions::{
+            list_sessions_handler, logout_handler, refresh_token_handler, revoke_session_handler,
+        },
+        sync::{
+            batch_update_progress, book_progress_handler, continue_listening_handler,
+            get_book_progress, get_file_progress, recently_finished_handler, update_progress,
+        },
+        user::{
+            change_password_handler, clear_lockout_handler, create_user, delete_user_handler,
+            disable_user_handler, enable_user_handler, get_user_handler, list_lockouts_handler,
+            list_users_handler, login, reset_password_handler, set_role_handler,
         },
-        sync::{get_book_progress, get_file_progress, update_progress},
-        user::{create_user, login},
     },
+    file_ops::book_cover::COVERS_DIR,
 };
 
 use audiobooks::scan_files_handler;
 
 pub async fn routes() -> Router<AppState> {
     Router::new()
-        .nest_service("/covers", ServeDir::new("covers"))
+        .nest_service("/covers", ServeDir::new(COVERS_DIR))
         .route("/hello", get(hello))
         // bookscan + edit
         .route("/scan_files", get(scan_files_handler))
         .route("/list_scanned_files", get(list_scanned_files_handler))
         .route("/save_organized_files", post(save_organized_files_handler))
+        // Libraries
+        .route(
+            "/libraries",
+            get(list_libraries_handler).post(create_library_handler),
+        )
+        .route(
+            "/libraries/{library_id}",
+            get(get_library_handler)
+                .put(update_library_handler)
+                .delete(delete_library_handler),
+        )
+        .route("/libraries/{library_id}/scan", post(scan_library_handler))
+        .route(
+            "/libraries/{library_id}/access",
+            get(get_library_access_handler).put(set_library_access_handler),
+        )
+        // Jobs
+        .route("/jobs", get(list_jobs))
+        .route("/jobs/{job_id}", get(get_job))
+        .route("/jobs/{job_id}/cancel", post(cancel_job))
         // upload
         .route("/upload", post(upload_handler))
         // Books
         .route("/list_books", get(list_books_handler))
+        .route("/search", get(search_handler))
         // Files
         .route("/download_book/{book_id}", get(download_book))
         .route("/download_chunk/{file_id}", get(download_chunk))
         .route("/file_metadata/{book_id}", get(file_metadata))
+        .route("/chapters/{book_id}", get(book_chapters_handler))
+        .route("/books/{book_id}/cover", get(book_cover_handler))
+        .route("/books/{book_id}/progress", get(book_progress_handler))
         // Sync
         .route(
             "/get_file_progress/{book_id}/{file_id}",
@@ -48,9 +119,72 @@ pub async fn routes() -> Router<AppState> {
         )
         .route("/get_book_progress/{book_id}", get(get_book_progress))
         .route("/update_progress", post(update_progress))
+        .route("/update_progress/batch", post(batch_update_progress))
+        .route("/events", get(events_handler))
+        .route("/listening_sessions", post(start_listening_handler))
+        .route(
+            "/listening_sessions/{session_id}/stop",
+            post(stop_listening_handler),
+        )
         // User
         .route("/create_user", post(create_user))
+        .route("/users", get(list_users_handler))
+        .route(
+            "/users/{user_id}",
+            get(get_user_handler).delete(delete_user_handler),
+        )
+        .route("/users/{user_id}/role", put(set_role_handler))
+        .route("/users/{user_id}/disable", post(disable_user_handler))
+        .route("/users/{user_id}/enable", post(enable_user_handler))
+        .route("/users/{user_id}/password", post(reset_password_handler))
+        .route(
+            "/users/{user_id}/permissions",
+            get(get_user_permissions_handler).put(set_user_permissions_handler),
+        )
+        .route("/me/password", post(change_password_handler))
+        .route("/me/permissions", get(my_permissions_handler))
+        .route("/me/continue", get(continue_listening_handler))
+        .route("/me/finished", get(recently_finished_handler))
+        .route(
+            "/me/listening_sessions",
+            get(list_listening_sessions_handler),
+        )
+        .route("/me/stats", get(listening_stats_handler))
+        // Permissions
+        .route("/permissions", get(list_permissions_handler))
+        .route(
+            "/groups",
+            get(list_groups_handler).post(create_group_handler),
+        )
+        .route(
+            "/groups/{group_id}",
+            put(update_group_handler).delete(delete_group_handler),
+        )
+        .route("/groups/{group_id}/members", put(set_group_members_handler))
+        .route(
+            "/api_keys",
+            get(list_api_keys_handler).post(create_api_key_handler),
+        )
+        .route("/api_keys/{key_id}", delete(revoke_api_key_handler))
+        .route("/login_lockouts", get(list_lockouts_handler))
+        .route(
+            "/login_lockouts/{scope}/{key}",
+            delete(clear_lockout_handler),
+        )
+        .route(
+            "/invites",
+            get(list_invites_handler).post(create_invite_handler),
+        )
+        .route("/invites/{invite_id}", delete(revoke_invite_handler))
+        .route("/register", post(register_handler))
+        .route("/audit_log", get(list_audit_log_handler))
         .route("/login", post(login))
+        .route("/oidc/login", get(oidc_login_handler))
+        .route("/oidc/callback", get(oidc_callback_handler))
+        .route("/token/refresh", post(refresh_token_handler))
+        .route("/logout", post(logout_handler))
+        .route("/sessions", get(list_sessions_handler))
+        .route("/sessions/{session_id}", delete(revoke_session_handler))
         .layer(DefaultBodyLimit::max(1024 * 1024 * 10))
 }
 
@@ -0,0 +1,226 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    Json,
+    extract::{ConnectInfo, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect},
+};
+use serde_json::json;
+use sqlx::SqlitePool;
+
+use crate::{
+    AppState,
+    api::{
+        api_error::ApiError,
+        audit::AuditContext,
+        sessions::{client_ip, random_token, start_session},
+        user::{provision_user, validate_new_user},
+    },
+    db::{
+        oidc::{
+            create_pending_login, find_identity_user, link_identity, prune_pending_logins,
+            take_pending_login,
+        },
+        permissions::{add_user_to_groups, remove_user_from_groups},
+        user::{count_enabled_admins, get_user_by_id, get_user_by_username, set_user_admin},
+    },
+    models::{
+        audit::AuditAction,
+        oidc::{OidcCallbackQuery, OidcIdentity, OidcLoginQuery},
+        permissions::DEFAULT_GROUP_ID,
+        user::{User, UserDto},
+    },
+    services::{audit::AuditEvent, oidc::OidcClient},
+};
+
+fn oidc_client(state: &AppState) -> Result<Arc<OidcClient>, ApiError> {
+    state
+        .oidc
+        .clone()
+        .ok_or_else(|| ApiError::NotFound("OpenID Connect login is not configured".into()))
+}
+
+// Start a login: remember state, nonce and PKCE verifier, then send the
+// browser to the provider
+pub async fn oidc_login_handler(
+    State(state): State<AppState>,
+    Query(query): Query<OidcLoginQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let client = oidc_client(&state)?;
+    let db = &state.db_pool;
+    prune_pending_logins(db).await?;
+
+    let login_state = random_token();
+    let nonce = random_token();
+    let code_verifier = random_token();
+    let url = client
+        .authorization_url(&login_state, &nonce, &code_verifier)
+        .await?;
+
+    create_pending_login(
+        db,
+        &login_state,
+        &nonce,
+        &code_verifier,
+        query.device_name.as_deref(),
+    )
+    .await?;
+
+    Ok(Redirect::to(&url))
+}
+
+// The provider sends the browser back here. Ends with the same token pair
+// a password login returns.
+pub async fn oidc_callback_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    audit: AuditContext,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let client = oidc_client(&state)?;
+    let db = &state.db_pool;
+
+    if let Some(error) = &query.error {
+        return Err(ApiError::Unauthorized(format!(
+            "Sign in was not completed: {}",
+            query.error_description.as_deref().unwrap_or(error)
+        )));
+    }
+    let (Some(code), Some(login_state)) = (&query.code, &query.state) else {
+        return Err(ApiError::BadRequest("Missing code or state".into()));
+    };
+
+    let pending = take_pending_login(db, login_state)
+        .await?
+        .ok_or_else(|| ApiError::BadRequest("Unknown or expired sign in attempt".into()))?;
+
+    let tokens = client.exchange_code(code, &pending.code_verifier).await?;
+    let id_token = tokens
+        .id_token
+        .as_deref()
+        .ok_or_else(|| ApiError::IdentityProvider("Provider did not return an ID token".into()))?;
+
+    let mut claims = client.verify_id_token(id_token, &pending.nonce).await?;
+    client
+        .merge_userinfo(&mut claims, tokens.access_token.as_deref())
+        .await?;
+    let identity = client.identity(&claims)?;
+
+    let user = resolve_user(db, &client, &identity).await?;
+    // A disabled account must not have its role or groups touched either
+    if user.disabled {
+        return Err(ApiError::Unauthorized("Account is disabled".into()));
+    }
+    let user = sync_role_and_groups(db, &client, &identity, user).await?;
+
+    let ip = client_ip(&state, &headers, addr);
+    let tokens = start_session(&state, &user, pending.device_name.as_deref(), &ip).await?;
+    audit
+        .record(
+            AuditEvent::new(AuditAction::OidcLogin)
+                .actor_user(Some(user.id), &user.username)
+                .target("session", &tokens.session_id)
+                .after(&json!({ "issuer": client.issuer(), "subject": identity.subject })),
+        )
+        .await;
+
+    Ok((StatusCode::ACCEPTED, Json(tokens)))
+}
+
+// Linked account first, then an existing local account of the same name if
+// linking is allowed, otherwise a new account
+async fn resolve_user(
+    db: &SqlitePool,
+    client: &OidcClient,
+    identity: &OidcIdentity,
+) -> Result<User, ApiError> {
+    let issuer = client.issuer();
+    let email = identity.email.as_deref();
+
+    if let Some(user_id) = find_identity_user(db, issuer, &identity.subject, email).await? {
+        return get_user_by_id(db, user_id)
+            .await?
+            .ok_or_else(|| ApiError::NotFound("User not found".into()));
+    }
+
+    if let Some(existing) = get_user_by_username(db, &identity.username).await? {
+        if !client.link_existing {
+            return Err(ApiError::Forbidden(format!(
+                "A local account named {} already exists",
+                identity.username
+            )));
+        }
+        link_identity(db, issuer, &identity.subject, existing.id, email).await?;
+        tracing::info!(
+            "Linked OIDC subject {} to existing user {}",
+            identity.subject,
+            existing.username
+        );
+        return Ok(existing);
+    }
+
+    if !client.auto_provision {
+        return Err(ApiError::Forbidden(
+            "No account is linked to this identity".into(),
+        ));
+    }
+
+    // The random password is never shown, the account signs in through the
+    // provider unless an admin resets it
+    let (joined, _) = client.mapped_groups(identity);
+    let mut group_ids = vec![DEFAULT_GROUP_ID];
+    group_ids.extend(joined);
+    let payload = UserDto {
+        username: identity.username.clone(),
+        password: random_token(),
+        is_admin: client.admin_role(identity).unwrap_or(false),
+        must_change_password: false,
+        library_ids: None,
+        permissions: None,
+        group_ids: Some(group_ids),
+    };
+    validate_new_user(db, &payload).await?;
+
+    let mut tx = db.begin().await?;
+    let user = provision_user(&mut tx, &payload).await?;
+    link_identity(&mut *tx, issuer, &identity.subject, user.id, email).await?;
+    tx.commit().await?;
+    tracing::info!(
+        "Provisioned user {} from OIDC subject {}",
+        user.username,
+        identity.subject
+    );
+
+    Ok(user)
+}
+
+// The provider's groups are authoritative for the role and the mapped
+// groups, applied on every login
+async fn sync_role_and_groups(
+    db: &SqlitePool,
+    client: &OidcClient,
+    identity: &OidcIdentity,
+    mut user: User,
+) -> Result<User, ApiError> {
+    if let Some(is_admin) = client.admin_role(identity)
+        && is_admin != user.is_admin
+    {
+        if !is_admin && !user.disabled && count_enabled_admins(db).await? <= 1 {
+            tracing::warn!(
+                "Not demoting {}, it is the last active admin",
+                user.username
+            );
+        } else {
+            set_user_admin(db, user.id, is_admin).await?;
+            user.is_admin = is_admin;
+        }
+    }
+
+    let (joined, left) = client.mapped_groups(identity);
+    add_user_to_groups(&mut *db.acquire().await?, user.id, &joined).await?;
+    remove_user_from_groups(db, user.id, &left).await?;
+
+    Ok(user)
+}
@@ -0,0 +1,256 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde_json::json;
+use sqlx::SqlitePool;
+
+use crate::{
+    AppState,
+    api::{
+        api_error::ApiError, audit::AuditContext, auth_extractor::AuthUser, middleware::AdminUser,
+    },
+    db::{
+        permissions::{
+            create_group, delete_group, direct_permissions, effective_permissions, get_group,
+            group_name_taken, list_groups, set_group_members, set_user_permissions, update_group,
+            user_group_ids,
+        },
+        user::get_user_summary,
+    },
+    models::{
+        audit::AuditAction,
+        permissions::{
+            DEFAULT_GROUP_ID, Group, GroupDto, GroupMembersDto, GroupUpdateDto, Permission,
+            UserPermissions, UserPermissionsDto,
+        },
+    },
+    services::audit::AuditEvent,
+};
+
+async fn find_group(db: &SqlitePool, group_id: i64) -> Result<Group, ApiError> {
+    get_group(db, group_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Group not found".into()))
+}
+
+async fn validate_group_name(
+    db: &SqlitePool,
+    name: &str,
+    except_id: Option<i64>,
+) -> Result<String, ApiError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ApiError::BadRequest("Group name is required".into()));
+    }
+    if group_name_taken(db, name, except_id).await? {
+        return Err(ApiError::BadRequest(format!(
+            "A group named {} already exists",
+            name
+        )));
+    }
+    Ok(name.to_string())
+}
+
+async fn user_permissions(db: &SqlitePool, user_id: i64) -> Result<UserPermissions, ApiError> {
+    let user = get_user_summary(db, user_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".into()))?;
+
+    let effective = if user.is_admin {
+        Permission::ALL.to_vec()
+    } else {
+        let mut effective = effective_permissions(db, user_id).await?;
+        effective.sort();
+        effective
+    };
+
+    Ok(UserPermissions {
+        user_id,
+        is_admin: user.is_admin,
+        direct: direct_permissions(db, user_id).await?,
+        group_ids: user_group_ids(db, user_id).await?,
+        effective,
+    })
+}
+
+// Every permission that can be granted, with what it allows
+pub async fn list_permissions_handler(
+    AuthUser(_claims): AuthUser,
+) -> Result<impl IntoResponse, ApiError> {
+    let permissions: Vec<_> = Permission::ALL
+        .iter()
+        .map(|p| json!({ "name": p, "description": p.description() }))
+        .collect();
+    Ok((StatusCode::OK, Json(json!({ "permissions": permissions }))))
+}
+
+pub async fn my_permissions_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+) -> Result<impl IntoResponse, ApiError> {
+    let permissions = user_permissions(&state.db_pool, claims.sub).await?;
+    Ok((StatusCode::OK, Json(permissions)))
+}
+
+pub async fn list_groups_handler(
+    State(state): State<AppState>,
+    AdminUser(_claims): AdminUser,
+) -> Result<impl IntoResponse, ApiError> {
+    let groups = list_groups(&state.db_pool).await?;
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "count": groups.len(), "groups": groups })),
+    ))
+}
+
+pub async fn create_group_handler(
+    State(state): State<AppState>,
+    AdminUser(claims): AdminUser,
+    audit: AuditContext,
+    Json(payload): Json<GroupDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let name = validate_group_name(db, &payload.name, None).await?;
+
+    let id = create_group(db, &name, &payload.permissions).await?;
+    let group = find_group(db, id).await?;
+    audit
+        .record(
+            AuditEvent::new(AuditAction::GroupCreated)
+                .actor(&claims)
+                .target("group", id)
+                .after(&group),
+        )
+        .await;
+
+    Ok((StatusCode::CREATED, Json(group)))
+}
+
+pub async fn update_group_handler(
+    State(state): State<AppState>,
+    AdminUser(claims): AdminUser,
+    audit: AuditContext,
+    Path(group_id): Path<i64>,
+    Json(payload): Json<GroupUpdateDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let before = find_group(db, group_id).await?;
+
+    let name = match &payload.name {
+        Some(name) => Some(validate_group_name(db, name, Some(group_id)).await?),
+        None => None,
+    };
+    update_group(
+        db,
+        group_id,
+        name.as_deref(),
+        payload.permissions.as_deref(),
+    )
+    .await?;
+
+    let group = find_group(db, group_id).await?;
+    audit
+        .record(
+            AuditEvent::new(AuditAction::GroupUpdated)
+                .actor(&claims)
+                .target("group", group_id)
+                .before(&before)
+                .after(&group),
+        )
+        .await;
+
+    Ok((StatusCode::OK, Json(group)))
+}
+
+// Members lose whatever the group granted them
+pub async fn delete_group_handler(
+    State(state): State<AppState>,
+    AdminUser(claims): AdminUser,
+    audit: AuditContext,
+    Path(group_id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    if group_id == DEFAULT_GROUP_ID {
+        return Err(ApiError::BadRequest(
+            "The default group cannot be deleted".into(),
+        ));
+    }
+    let group = find_group(&state.db_pool, group_id).await?;
+    delete_group(&state.db_pool, group_id).await?;
+    audit
+        .record(
+            AuditEvent::new(AuditAction::GroupDeleted)
+                .actor(&claims)
+                .target("group", group_id)
+                .before(&group),
+        )
+        .await;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "message": format!("Group {} deleted", group.group.name) })),
+    ))
+}
+
+pub async fn set_group_members_handler(
+    State(state): State<AppState>,
+    AdminUser(claims): AdminUser,
+    audit: AuditContext,
+    Path(group_id): Path<i64>,
+    Json(payload): Json<GroupMembersDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let before = find_group(db, group_id).await?;
+    set_group_members(db, group_id, &payload.user_ids).await?;
+
+    let group = find_group(db, group_id).await?;
+    audit
+        .record(
+            AuditEvent::new(AuditAction::GroupMembersChanged)
+                .actor(&claims)
+                .target("group", group_id)
+                .before(&before)
+                .after(&group),
+        )
+        .await;
+
+    Ok((StatusCode::OK, Json(group)))
+}
+
+pub async fn get_user_permissions_handler(
+    State(state): State<AppState>,
+    AdminUser(_claims): AdminUser,
+    Path(user_id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let permissions = user_permissions(&state.db_pool, user_id).await?;
+    Ok((StatusCode::OK, Json(permissions)))
+}
+
+// Replaces the permissions granted to the user directly, group grants are
+// left alone. Applies to live sessions on their next request.
+pub async fn set_user_permissions_handler(
+    State(state): State<AppState>,
+    AdminUser(claims): AdminUser,
+    audit: AuditContext,
+    Path(user_id): Path<i64>,
+    Json(payload): Json<UserPermissionsDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let before = user_permissions(db, user_id).await?;
+    set_user_permissions(db, user_id, &payload.permissions).await?;
+
+    let permissions = user_permissions(db, user_id).await?;
+    audit
+        .record(
+            AuditEvent::new(AuditAction::UserPermissionsChanged)
+                .actor(&claims)
+                .target("user", user_id)
+                .before(&before)
+                .after(&permissions),
+        )
+        .await;
+
+    Ok((StatusCode::OK, Json(permissions)))
+}
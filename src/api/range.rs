@@ -0,0 +1,413 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    body::{Body, Bytes},
+    http::{HeaderMap, HeaderValue, Response, StatusCode, header},
+};
+use futures::{StreamExt, TryStreamExt, stream};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, SeekFrom},
+};
+use tokio_util::io::ReaderStream;
+
+use crate::api::api_error::ApiError;
+
+// Anything above this is treated as abuse and the Range header is ignored
+const MAX_RANGES: usize = 32;
+const MULTIPART_BOUNDARY: &str = "else_wer_byteranges";
+
+/// Inclusive byte range within a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable Range header, serve the whole file
+    Full,
+    Partial(Vec<ByteRange>),
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header value against a file of `size` bytes.
+/// Syntactically invalid headers are ignored as RFC 9110 allows.
+pub fn parse_range(value: &str, size: u64) -> RangeRequest {
+    let Some((unit, specs)) = value.split_once('=') else {
+        return RangeRequest::Full;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return RangeRequest::Full;
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',') {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            continue;
+        }
+        let Some((first, last)) = spec.split_once('-') else {
+            return RangeRequest::Full;
+        };
+        let (first, last) = (first.trim(), last.trim());
+
+        let range = if first.is_empty() {
+            // Suffix range: the last N bytes
+            let Ok(suffix) = last.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            if suffix == 0 || size == 0 {
+                None
+            } else {
+                Some(ByteRange {
+                    start: size.saturating_sub(suffix),
+                    end: size - 1,
+                })
+            }
+        } else {
+            let Ok(start) = first.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            let end = if last.is_empty() {
+                size.saturating_sub(1)
+            } else {
+                match last.parse::<u64>() {
+                    Ok(end) if end >= start => end.min(size.saturating_sub(1)),
+                    _ => return RangeRequest::Full,
+                }
+            };
+            (start < size).then_some(ByteRange { start, end })
+        };
+
+        if let Some(range) = range {
+            ranges.push(range);
+        }
+        if ranges.len() > MAX_RANGES {
+            return RangeRequest::Full;
+        }
+    }
+
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+
+    RangeRequest::Partial(coalesce(ranges))
+}
+
+// Merge overlapping and adjacent ranges so we never send the same bytes twice
+fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(prev) if range.start <= prev.end + 1 => prev.end = prev.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+pub fn content_type_for(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    match ext.as_deref() {
+        Some("mp3") => "audio/mpeg",
+        Some("m4b") | Some("m4a") | Some("mp4") => "audio/mp4",
+        Some("flac") => "audio/flac",
+        Some("ogg") => "audio/ogg",
+        Some("opus") => "audio/opus",
+        Some("wav") => "audio/wav",
+        Some("aac") => "audio/aac",
+        _ => "application/octet-stream",
+    }
+}
+
+fn etag_for(size: u64, modified: Option<SystemTime>) -> String {
+    let mtime = modified
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("\"{:x}-{:x}\"", size, mtime)
+}
+
+// If-Range only honours strong validators: an exact ETag or an exact date
+fn if_range_matches(headers: &HeaderMap, etag: &str, modified: Option<SystemTime>) -> bool {
+    let Some(value) = headers.get(header::IF_RANGE).and_then(|v| v.to_str().ok()) else {
+        return true;
+    };
+    let value = value.trim();
+
+    if value.starts_with('"') || value.starts_with("W/") {
+        return value == etag;
+    }
+
+    match (httpdate::parse_http_date(value), modified) {
+        (Ok(date), Some(modified)) => {
+            httpdate::fmt_http_date(date) == httpdate::fmt_http_date(modified)
+        }
+        _ => false,
+    }
+}
+
+async fn open_range(
+    path: PathBuf,
+    range: ByteRange,
+) -> std::io::Result<ReaderStream<tokio::io::Take<File>>> {
+    let mut file = File::open(&path).await?;
+    file.seek(SeekFrom::Start(range.start)).await?;
+    Ok(ReaderStream::new(file.take(range.len())))
+}
+
+/// Serves `path` honouring `Range` / `If-Range`, streaming the body from disk.
+pub async fn serve_file(path: &Path, headers: &HeaderMap) -> Result<Response<Body>, ApiError> {
+    let meta = match tokio::fs::metadata(path).await {
+        Ok(meta) if meta.is_file() => meta,
+        _ => return Err(ApiError::NotFound("File not found".into())),
+    };
+
+    let size = meta.len();
+    let modified = meta.modified().ok();
+    let etag = etag_for(size, modified);
+    let content_type = content_type_for(path);
+
+    let mut builder = Response::builder()
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, &etag);
+    if let Some(modified) = modified {
+        builder = builder.header(header::LAST_MODIFIED, httpdate::fmt_http_date(modified));
+    }
+
+    let range = match headers.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(value) if if_range_matches(headers, &etag, modified) => parse_range(value, size),
+        _ => RangeRequest::Full,
+    };
+
+    let path = path.to_path_buf();
+    let response = match range {
+        RangeRequest::Full => {
+            let stream = open_range(
+                path,
+                ByteRange {
+                    start: 0,
+                    end: size.saturating_sub(1),
+                },
+            )
+            .await?;
+            builder
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, content_type)
+                .header(header::CONTENT_LENGTH, size)
+                .body(Body::from_stream(stream))
+        }
+        RangeRequest::Unsatisfiable => builder
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+            .body(Body::empty()),
+        RangeRequest::Partial(ranges) if ranges.len() == 1 => {
+            let range = ranges[0];
+            let stream = open_range(path, range).await?;
+            builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_TYPE, content_type)
+                .header(header::CONTENT_LENGTH, range.len())
+                .header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", range.start, range.end, size),
+                )
+                .body(Body::from_stream(stream))
+        }
+        RangeRequest::Partial(ranges) => {
+            let (content_length, body) = multipart_body(path, &ranges, size, content_type);
+            builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_str(&format!(
+                        "multipart/byteranges; boundary={}",
+                        MULTIPART_BOUNDARY
+                    ))
+                    .map_err(|e| ApiError::Internal(e.to_string()))?,
+                )
+                .header(header::CONTENT_LENGTH, content_length)
+                .body(body)
+        }
+    };
+
+    response.map_err(|e| ApiError::Internal(format!("Failed to build response: {e}")))
+}
+
+enum Segment {
+    Bytes(Bytes),
+    File(ByteRange),
+}
+
+// Lays out a multipart/byteranges body up front so Content-Length is exact,
+// then streams each part's file slice lazily
+fn multipart_body(
+    path: PathBuf,
+    ranges: &[ByteRange],
+    size: u64,
+    content_type: &str,
+) -> (u64, Body) {
+    let mut segments = Vec::with_capacity(ranges.len() * 2 + 1);
+    let mut content_length = 0u64;
+
+    for range in ranges {
+        let part_header = format!(
+            "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+            MULTIPART_BOUNDARY, content_type, range.start, range.end, size
+        );
+        content_length += part_header.len() as u64 + range.len();
+        segments.push(Segment::Bytes(Bytes::from(part_header)));
+        segments.push(Segment::File(*range));
+    }
+
+    let trailer = format!("\r\n--{}--\r\n", MULTIPART_BOUNDARY);
+    content_length += trailer.len() as u64;
+    segments.push(Segment::Bytes(Bytes::from(trailer)));
+
+    let body = stream::iter(segments)
+        .map(move |segment| match segment {
+            Segment::Bytes(bytes) => stream::once(async move { Ok(bytes) }).boxed(),
+            Segment::File(range) => stream::once(open_range(path.clone(), range))
+                .try_flatten()
+                .boxed(),
+        })
+        .flatten();
+
+    (content_length, Body::from_stream(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn r(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn single_and_open_ended_ranges() {
+        assert_eq!(
+            parse_range("bytes=0-99", 1000),
+            RangeRequest::Partial(vec![r(0, 99)])
+        );
+        assert_eq!(
+            parse_range("bytes=900-", 1000),
+            RangeRequest::Partial(vec![r(900, 999)])
+        );
+        // End past the file is clamped
+        assert_eq!(
+            parse_range("bytes=500-5000", 1000),
+            RangeRequest::Partial(vec![r(500, 999)])
+        );
+    }
+
+    #[test]
+    fn suffix_ranges() {
+        assert_eq!(
+            parse_range("bytes=-100", 1000),
+            RangeRequest::Partial(vec![r(900, 999)])
+        );
+        // Longer than the file means the whole file
+        assert_eq!(
+            parse_range("bytes=-5000", 1000),
+            RangeRequest::Partial(vec![r(0, 999)])
+        );
+        assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(
+            parse_range("bytes=1000-", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(
+            parse_range("bytes=2000-3000", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-10", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn satisfiable_ranges_survive_unsatisfiable_ones() {
+        assert_eq!(
+            parse_range("bytes=5000-6000, 0-9", 1000),
+            RangeRequest::Partial(vec![r(0, 9)])
+        );
+    }
+
+    #[test]
+    fn invalid_headers_are_ignored() {
+        assert_eq!(parse_range("items=0-10", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes 0-10", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=abc-10", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=10", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=50-10", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=-x", 1000), RangeRequest::Full);
+    }
+
+    #[test]
+    fn unit_is_case_insensitive_and_whitespace_tolerated() {
+        assert_eq!(
+            parse_range("Bytes= 0-9 , 20-29", 1000),
+            RangeRequest::Partial(vec![r(0, 9), r(20, 29)])
+        );
+    }
+
+    #[test]
+    fn overlapping_and_adjacent_ranges_are_merged() {
+        assert_eq!(
+            parse_range("bytes=0-9,5-19,20-29", 1000),
+            RangeRequest::Partial(vec![r(0, 29)])
+        );
+        assert_eq!(
+            parse_range("bytes=500-599,0-9,-100", 1000),
+            RangeRequest::Partial(vec![r(0, 9), r(500, 599), r(900, 999)])
+        );
+    }
+
+    #[test]
+    fn coalesce_keeps_gaps_and_contained_ranges() {
+        assert_eq!(
+            coalesce(vec![r(10, 20), r(0, 100), r(200, 300), r(302, 400)]),
+            vec![r(0, 100), r(200, 300), r(302, 400)]
+        );
+        assert_eq!(coalesce(vec![r(0, 0), r(1, 1)]), vec![r(0, 1)]);
+        assert!(coalesce(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn too_many_ranges_serve_the_whole_file() {
+        let specs = |n: u64| {
+            (0..n)
+                .map(|i| format!("{}-{}", i * 10, i * 10 + 1))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let at_cap = parse_range(&format!("bytes={}", specs(MAX_RANGES as u64)), 10_000);
+        assert!(matches!(at_cap, RangeRequest::Partial(ref v) if v.len() == MAX_RANGES));
+        let over = parse_range(&format!("bytes={}", specs(MAX_RANGES as u64 + 1)), 10_000);
+        assert_eq!(over, RangeRequest::Full);
+    }
+
+    #[test]
+    fn byte_range_len_is_inclusive() {
+        assert_eq!(r(0, 0).len(), 1);
+        assert_eq!(r(10, 19).len(), 10);
+    }
+}
@@ -0,0 +1,45 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde_json::json;
+
+use crate::{
+    AppState,
+    api::{api_error::ApiError, auth_extractor::AuthUser, libraries::library_scope},
+    db::search::{search_books, to_match_query},
+    models::search::{SearchHit, SearchQuery},
+};
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 100;
+
+// Ranked search over titles, authors, series, narrators and file names
+pub async fn search_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Query(query): Query<SearchQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let match_query = to_match_query(&query.q)
+        .ok_or_else(|| ApiError::BadRequest("Search query is empty".into()))?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let scope = library_scope(&state.db_pool, &claims).await?;
+
+    let hits: Vec<SearchHit> = search_books(&state.db_pool, &match_query, scope.as_deref(), limit)
+        .await?
+        .into_iter()
+        .map(SearchHit::from)
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "query": query.q,
+            "count": hits.len(),
+            "results": hits,
+        })),
+    ))
+}
@@ -0,0 +1,248 @@
+use std::net::SocketAddr;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{
+    Json,
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{Duration, Utc};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    AppState,
+    api::{api_error::ApiError, audit::AuditContext, auth_extractor::AuthUser},
+    db::{
+        sessions::{
+            create_session, list_active_sessions, prune_sessions, revoke_reused_token,
+            revoke_session, rotate_session,
+        },
+        user::get_user_by_id,
+    },
+    models::{
+        audit::AuditAction,
+        events::ServerEvent,
+        sessions::{RefreshDto, SessionInfo, TokenResponse},
+        user::{Claims, User, role_name},
+    },
+    services::audit::AuditEvent,
+};
+
+/// Access tokens are short lived, clients renew them with the refresh token
+pub const ACCESS_TOKEN_TTL_MINUTES: i64 = 15;
+pub const REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+
+const MAX_DEVICE_NAME: usize = 100;
+
+/// Address the request came from. Forwarded headers are only believed when
+/// the server is configured to sit behind a proxy.
+pub fn client_ip(state: &AppState, headers: &HeaderMap, addr: SocketAddr) -> String {
+    if state.config.trust_proxy {
+        let forwarded = headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .map(str::trim)
+            .filter(|v| !v.is_empty());
+        if let Some(ip) = forwarded {
+            return ip.to_string();
+        }
+    }
+    addr.ip().to_string()
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn issue_access_token(state: &AppState, user: &User, session_id: &str) -> Result<String, ApiError> {
+    let now = Utc::now();
+    let exp = now + Duration::minutes(ACCESS_TOKEN_TTL_MINUTES);
+
+    let claims = Claims {
+        sub: user.id,
+        role: role_name(user.is_admin).to_owned(),
+        username: user.username.clone(),
+        sid: session_id.to_string(),
+        iat: now.timestamp() as usize,
+        exp: exp.timestamp() as usize,
+        api_key: None,
+        api_key_id: None,
+        permissions: Vec::new(),
+    };
+
+    state.keys.sign(&claims)
+}
+
+fn token_response(
+    user: &User,
+    access: String,
+    refresh: String,
+    session_id: String,
+) -> TokenResponse {
+    TokenResponse {
+        token: access,
+        token_type: "Bearer",
+        expires_in: ACCESS_TOKEN_TTL_MINUTES * 60,
+        refresh_token: refresh,
+        session_id,
+        must_change_password: user.must_change_password,
+    }
+}
+
+/// Opens a session for a user who just proved who they are
+pub async fn start_session(
+    state: &AppState,
+    user: &User,
+    device_name: Option<&str>,
+    ip_address: &str,
+) -> Result<TokenResponse, ApiError> {
+    let db = &state.db_pool;
+    prune_sessions(db).await?;
+
+    let device_name = device_name
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(|d| d.chars().take(MAX_DEVICE_NAME).collect::<String>());
+
+    let refresh = random_token();
+    let session_id = uuid::Uuid::new_v4().to_string();
+    create_session(
+        db,
+        &session_id,
+        user.id,
+        &hash_token(&refresh),
+        device_name.as_deref(),
+        Some(ip_address),
+        REFRESH_TOKEN_TTL_DAYS,
+    )
+    .await?;
+
+    let access = issue_access_token(state, user, &session_id)?;
+    Ok(token_response(user, access, refresh, session_id))
+}
+
+// Trade a refresh token for a new access token. The refresh token is
+// rotated on every use, replaying an old one revokes the whole session.
+pub async fn refresh_token_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    audit: AuditContext,
+    Json(payload): Json<RefreshDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let old_hash = hash_token(&payload.refresh_token);
+    let refresh = random_token();
+    let ip = client_ip(&state, &headers, addr);
+
+    let session = match rotate_session(
+        db,
+        &old_hash,
+        &hash_token(&refresh),
+        Some(&ip),
+        REFRESH_TOKEN_TTL_DAYS,
+    )
+    .await?
+    {
+        Some(session) => session,
+        None => {
+            if let Some((session_id, user_id)) = revoke_reused_token(db, &old_hash).await? {
+                tracing::warn!("Refresh token reuse from {}, session revoked", ip);
+                state
+                    .events
+                    .to_user(user_id, ServerEvent::SessionRevoked { session_id });
+                audit
+                    .record(AuditEvent::new(AuditAction::RefreshTokenReused))
+                    .await;
+            }
+            return Err(ApiError::Unauthorized(
+                "Invalid or expired refresh token".into(),
+            ));
+        }
+    };
+
+    let user = get_user_by_id(db, session.user_id)
+        .await?
+        .filter(|user| !user.disabled)
+        .ok_or_else(|| ApiError::Unauthorized("Invalid or expired refresh token".into()))?;
+
+    let access = issue_access_token(&state, &user, &session.id)?;
+    Ok((
+        StatusCode::OK,
+        Json(token_response(&user, access, refresh, session.id)),
+    ))
+}
+
+// Revoke the session the request was made with
+pub async fn logout_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    audit: AuditContext,
+) -> Result<impl IntoResponse, ApiError> {
+    revoke_session(&state.db_pool, claims.sub, &claims.sid).await?;
+    state.events.to_user(
+        claims.sub,
+        ServerEvent::SessionRevoked {
+            session_id: claims.sid.clone(),
+        },
+    );
+    audit
+        .record(
+            AuditEvent::new(AuditAction::Logout)
+                .actor(&claims)
+                .target("session", &claims.sid),
+        )
+        .await;
+    Ok((StatusCode::OK, Json(json!({ "message": "Logged out" }))))
+}
+
+// Devices the calling user is signed in on
+pub async fn list_sessions_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+) -> Result<impl IntoResponse, ApiError> {
+    let sessions: Vec<SessionInfo> = list_active_sessions(&state.db_pool, claims.sub)
+        .await?
+        .into_iter()
+        .map(|session| SessionInfo {
+            current: session.id == claims.sid,
+            session,
+        })
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "count": sessions.len(), "sessions": sessions })),
+    ))
+}
+
+// Sign out one of the calling user's devices
+pub async fn revoke_session_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(session_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !revoke_session(&state.db_pool, claims.sub, &session_id).await? {
+        return Err(ApiError::NotFound("Session not found".into()));
+    }
+    state.events.to_user(
+        claims.sub,
+        ServerEvent::SessionRevoked {
+            session_id: session_id.clone(),
+        },
+    );
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "message": "Session revoked", "session_id": session_id })),
+    ))
+}
@@ -1,22 +1,51 @@
 use crate::{
     AppState,
-    api::{api_error::ApiError, auth_extractor::AuthUser},
-    db::sync::{get_progress_by_bookid, get_progress_by_fileid, upsert_progress},
-    models::user::ProgressUpdate,
+    api::{
+        api_error::ApiError,
+        auth_extractor::AuthUser,
+        libraries::{ensure_book_access, library_scope},
+    },
+    db::{
+        audiobooks::list_books_page,
+        listening::record_listening,
+        sync::{
+            apply_progress, compute_book_progress, file_in_book, get_progress_by_bookid,
+            get_progress_by_fileid, prune_progress_events, record_progress_event,
+        },
+    },
+    models::{
+        audiobooks::{BookListItem, BookListQuery, BookSort, CompletionState, SortOrder},
+        events::ServerEvent,
+        listening::ListeningPoint,
+        user::{
+            Claims, DEFAULT_SHELF_SIZE, MAX_PROGRESS_BATCH, MAX_SHELF_SIZE, ProgressBatchDto,
+            ProgressEvent, ProgressEventOutcome, ProgressEventResult, ProgressSync, ProgressUpdate,
+            ShelfItem, ShelfQuery,
+        },
+    },
 };
 use Result::Ok;
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
 };
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::SqliteConnection;
+use std::collections::{HashMap, hash_map::Entry};
+
+const MAX_EVENT_ID_LEN: usize = 100;
 
 pub async fn get_file_progress(
     State(state): State<AppState>,
     AuthUser(claims): AuthUser,
     Path((book_id, file_id)): Path<(i64, i64)>,
 ) -> impl IntoResponse {
+    if let Err(e) = ensure_book_access(&state.db_pool, &claims, book_id).await {
+        return e.into_response();
+    }
     match get_progress_by_fileid(&state.db_pool, claims.sub, book_id, file_id).await {
         Ok(Some(progress)) => Json(progress).into_response(),
         Ok(None) => (StatusCode::NOT_FOUND, "Progress not found").into_response(),
@@ -32,6 +61,9 @@ pub async fn get_book_progress(
     AuthUser(claims): AuthUser,
     Path(book_id): Path<i64>,
 ) -> impl IntoResponse {
+    if let Err(e) = ensure_book_access(&state.db_pool, &claims, book_id).await {
+        return e.into_response();
+    }
     match get_progress_by_bookid(&state.db_pool, claims.sub, book_id).await {
         Ok(rows) => Json(rows).into_response(),
         Err(e) => {
@@ -41,20 +73,230 @@ pub async fn get_book_progress(
     }
 }
 
+// Overall position in the book: elapsed time, percent and where to resume
+pub async fn book_progress_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Path(book_id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    ensure_book_access(&state.db_pool, &claims, book_id).await?;
+    let progress = compute_book_progress(&state.db_pool, claims.sub, book_id).await?;
+    if progress.current_file_id.is_none() {
+        return Err(ApiError::NotFound("Book not found".into()));
+    }
+    Ok(Json(progress))
+}
+
+// Books in the given state, most recently listened first
+async fn shelf(
+    state: &AppState,
+    claims: &Claims,
+    completion: CompletionState,
+    limit: Option<i64>,
+) -> Result<Vec<ShelfItem>, ApiError> {
+    let db = &state.db_pool;
+    let query = BookListQuery {
+        limit,
+        sort: BookSort::RecentlyListened,
+        order: Some(SortOrder::Desc),
+        completion: Some(completion),
+        ..Default::default()
+    };
+    let limit = limit.unwrap_or(DEFAULT_SHELF_SIZE).clamp(1, MAX_SHELF_SIZE);
+
+    let scope = library_scope(db, claims).await?;
+    let page = list_books_page(db, claims.sub, &query, scope.as_deref(), limit).await?;
+
+    let mut items = Vec::with_capacity(page.books.len());
+    for row in page.books {
+        let progress = compute_book_progress(db, claims.sub, row.book.id).await?;
+        items.push(ShelfItem {
+            book: BookListItem::from(row),
+            progress,
+        });
+    }
+    Ok(items)
+}
+
+pub async fn continue_listening_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Query(query): Query<ShelfQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let books = shelf(&state, &claims, CompletionState::InProgress, query.limit).await?;
+    Ok(Json(json!({ "count": books.len(), "books": books })))
+}
+
+pub async fn recently_finished_handler(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Query(query): Query<ShelfQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let books = shelf(&state, &claims, CompletionState::Finished, query.limit).await?;
+    Ok(Json(json!({ "count": books.len(), "books": books })))
+}
+
+// A device clock running ahead must not lock out every other device
+fn reached_at(update: &ProgressUpdate, now: DateTime<Utc>) -> DateTime<Utc> {
+    update.updated_at.map_or(now, |t| t.min(now))
+}
+
+fn listening_point(update: &ProgressUpdate, at: DateTime<Utc>) -> ListeningPoint<'_> {
+    ListeningPoint {
+        book_id: update.book_id,
+        file_id: update.file_id,
+        offset_ms: update.progress_ms,
+        device_id: update.device_id.as_deref(),
+        playback_rate: update.playback_rate,
+        at,
+    }
+}
+
+// Checks every update passes, whether sent alone or in a batch. Book access
+// is checked by the callers.
+async fn validate_update(
+    conn: &mut SqliteConnection,
+    update: &ProgressUpdate,
+) -> Result<(), ApiError> {
+    if update.progress_ms < 0 {
+        return Err(ApiError::BadRequest(
+            "progress_ms must not be negative".into(),
+        ));
+    }
+    if !file_in_book(conn, update.book_id, update.file_id).await? {
+        return Err(ApiError::NotFound("File not found".into()));
+    }
+    Ok(())
+}
+
+// Newest position wins, judged by when it was reached on the device. An
+// older update gets 409 with the stored position so the client can catch up.
 pub async fn update_progress(
     State(state): State<AppState>,
     AuthUser(claims): AuthUser, // this needs to be in middle. Axum wants in this order
     Json(payload): Json<ProgressUpdate>,
 ) -> Result<impl IntoResponse, ApiError> {
-    println!("👉 Incoming update payload: {:#?}", payload);
+    ensure_book_access(&state.db_pool, &claims, payload.book_id).await?;
+
+    let updated_at = reached_at(&payload, Utc::now());
+    let mut tx = state.db_pool.begin().await?;
+    validate_update(&mut tx, &payload).await?;
+    let result = apply_progress(&mut tx, claims.sub, &payload, updated_at).await?;
+    if let ProgressSync::Applied { .. } = result {
+        record_listening(&mut tx, claims.sub, &listening_point(&payload, updated_at)).await?;
+    }
+    tx.commit().await?;
+
+    let status = match &result {
+        ProgressSync::Applied { progress } => {
+            state.events.to_user(
+                claims.sub,
+                ServerEvent::ProgressChanged {
+                    progress: progress.clone(),
+                },
+            );
+            StatusCode::OK
+        }
+        ProgressSync::Conflict { .. } => StatusCode::CONFLICT,
+    };
+    Ok((status, Json(result)))
+}
+
+// Replays events queued while offline, in order, in one transaction. Bad
+// events fail on their own; a database error rolls back the whole batch.
+pub async fn batch_update_progress(
+    State(state): State<AppState>,
+    AuthUser(claims): AuthUser,
+    Json(payload): Json<ProgressBatchDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    if payload.events.is_empty() || payload.events.len() > MAX_PROGRESS_BATCH {
+        return Err(ApiError::BadRequest(format!(
+            "A batch must hold between 1 and {} events",
+            MAX_PROGRESS_BATCH
+        )));
+    }
+    let db = &state.db_pool;
+
+    let mut book_access = HashMap::new();
+    for event in &payload.events {
+        let book_id = event.update.book_id;
+        if let Entry::Vacant(entry) = book_access.entry(book_id) {
+            let allowed = match ensure_book_access(db, &claims, book_id).await {
+                Ok(()) => true,
+                Err(ApiError::NotFound(_)) => false,
+                Err(e) => return Err(e),
+            };
+            entry.insert(allowed);
+        }
+    }
+    prune_progress_events(db).await?;
+
+    let now = Utc::now();
+    let mut tx = db.begin().await?;
+    let mut results = Vec::with_capacity(payload.events.len());
+    for event in payload.events {
+        let allowed = book_access[&event.update.book_id];
+        let outcome = apply_event(&mut tx, claims.sub, &event, allowed, now).await?;
+        results.push(ProgressEventResult {
+            event_id: event.event_id,
+            outcome,
+        });
+    }
+    tx.commit().await?;
+
+    for result in &results {
+        if let ProgressEventOutcome::Applied { progress } = &result.outcome {
+            state.events.to_user(
+                claims.sub,
+                ServerEvent::ProgressChanged {
+                    progress: progress.clone(),
+                },
+            );
+        }
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "count": results.len(), "results": results })),
+    ))
+}
 
-    upsert_progress(&state.db_pool, claims.sub, &payload)
-        .await
-        .map_err(|e| {
-            println!("🚨 Upsert Error: {e}");
-            ApiError::Internal("Upsert failed".into())
-        })?;
+async fn apply_event(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    event: &ProgressEvent,
+    allowed: bool,
+    now: DateTime<Utc>,
+) -> Result<ProgressEventOutcome, ApiError> {
+    let update = &event.update;
+    let failed = |error: &str| {
+        Ok(ProgressEventOutcome::Failed {
+            error: error.to_string(),
+        })
+    };
 
-    println!("✅ Upsert succeeded");
-    Ok(StatusCode::ACCEPTED)
+    if event.event_id.trim().is_empty() || event.event_id.len() > MAX_EVENT_ID_LEN {
+        return failed("event_id must be between 1 and 100 characters");
+    }
+    if !allowed {
+        return failed("Book not found");
+    }
+    match validate_update(&mut *conn, update).await {
+        Ok(()) => {}
+        Err(ApiError::BadRequest(error) | ApiError::NotFound(error)) => return failed(&error),
+        Err(e) => return Err(e),
+    }
+
+    if !record_progress_event(conn, user_id, &event.event_id).await? {
+        let progress =
+            get_progress_by_fileid(&mut *conn, user_id, update.book_id, update.file_id).await?;
+        return Ok(ProgressEventOutcome::Duplicate { progress });
+    }
+
+    let updated_at = reached_at(update, now);
+    let sync = apply_progress(&mut *conn, user_id, update, updated_at).await?;
+    if let ProgressSync::Applied { .. } = sync {
+        record_listening(conn, user_id, &listening_point(update, updated_at)).await?;
+    }
+    Ok(sync.into())
 }
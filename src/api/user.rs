@@ -1,7 +1,32 @@
 use crate::api::api_error::ApiError;
-use crate::api::middleware::AdminUser;
-use crate::db::user::{self, get_user_by_username};
-use crate::models::user::{Claims, User};
+use crate::api::audit::AuditContext;
+use crate::api::auth_extractor::PasswordChangeUser;
+use crate::api::libraries::ensure_can_grant_libraries;
+use crate::api::middleware::{AdminUser, Permitted, perm};
+use crate::api::sessions::{client_ip, start_session};
+use crate::db::libraries::{grant_library_access, unknown_library_ids};
+use crate::db::login_attempts::{clear_login_attempts, list_login_attempts};
+use crate::db::permissions::{
+    add_user_to_groups, effective_permissions, replace_user_permissions, unknown_group_ids,
+};
+use crate::db::sessions::revoke_user_sessions;
+use crate::db::user::{
+    self, count_enabled_admins, delete_user, get_user_by_id, get_user_by_username,
+    get_user_summary, list_users, set_user_admin, set_user_disabled, update_user_password,
+};
+use crate::models::audit::AuditAction;
+use crate::models::events::ServerEvent;
+use crate::models::libraries::DEFAULT_LIBRARY_ID;
+use crate::models::login_attempts::AttemptScope;
+use crate::models::permissions::{DEFAULT_GROUP_ID, Permission};
+use crate::models::user::{
+    ChangePasswordDto, Claims, ResetPasswordDto, Role, RoleDto, User, UserSummary,
+};
+use crate::services::audit::AuditEvent;
+use crate::services::login_guard::{
+    FAILURE_WINDOW_SECS, record_failed_login, record_successful_login, release_login,
+    reserve_login, username_key,
+};
 use crate::{
     AppState,
     models::user::{LoginDto, UserDto},
@@ -10,99 +35,559 @@ use argon2::{
     Argon2,
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
 };
-use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
-use chrono::{Duration, Utc};
-use jsonwebtoken::{EncodingKey, Header, encode};
+use axum::{
+    Json,
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use chrono::Utc;
 use serde_json::json;
-use sqlx::{Pool, Sqlite};
+use sqlx::{Pool, Sqlite, SqliteConnection, SqliteExecutor};
+use std::net::SocketAddr;
+use std::sync::LazyLock;
+
+const MIN_PASSWORD_LEN: usize = 8;
+
+// Verified against when the username does not exist
+static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| {
+    hash_password(&uuid::Uuid::new_v4().to_string())
+        .map(|(hash, _)| hash)
+        .unwrap_or_default()
+});
 
 // Create user
 pub async fn create_user(
-    AdminUser(_claims): AdminUser,
+    Permitted(claims, _): Permitted<perm::UsersManage>,
     State(state): State<AppState>,
+    audit: AuditContext,
     Json(payload): Json<UserDto>,
 ) -> Result<impl IntoResponse, ApiError> {
     let db = &state.db_pool;
 
-    if payload.username.is_empty() || payload.password.is_empty() {
-        return Err(ApiError::BadRequest(
-            "Provide both username and password".into(),
+    // Otherwise users.manage would be a path to any other permission
+    if !claims.is_admin()
+        && (payload.is_admin || payload.permissions.is_some() || payload.group_ids.is_some())
+    {
+        return Err(ApiError::Forbidden(
+            "Only admins can create admins or assign permissions".into(),
         ));
     }
+    ensure_can_grant_libraries(db, &claims, &requested_libraries(&payload.library_ids)).await?;
+    ensure_known_ids(
+        db,
+        payload.library_ids.as_deref(),
+        payload.group_ids.as_deref(),
+    )
+    .await?;
+    validate_new_user(db, &payload).await?;
+
+    let mut tx = db.begin().await?;
+    let user = provision_user(&mut tx, &payload).await?;
+    tx.commit().await?;
+    audit
+        .record(
+            AuditEvent::new(AuditAction::UserCreated)
+                .actor(&claims)
+                .target("user", user.id)
+                .after(&json!({
+                    "user": find_user(db, user.id).await?,
+                    "library_ids": payload.library_ids,
+                    "group_ids": payload.group_ids,
+                    "permissions": payload.permissions,
+                })),
+        )
+        .await;
 
-    let user = save_pwd_hash(&payload, db).await?;
     Ok((
         StatusCode::ACCEPTED,
         Json(json!({ "message": format!("User {} created successfully", user.username) })),
     ))
 }
 
-pub async fn save_pwd_hash(user: &UserDto, db: &Pool<Sqlite>) -> Result<User, ApiError> {
-    let argon2 = Argon2::default();
-    let password_bytes = &user.password.clone().into_bytes();
+/// Creates the account along with its library access, groups and direct
+/// permissions. Shared by admin creation and invite registration, which run
+/// it inside a transaction so a failure leaves no half made account behind.
+/// Call `validate_new_user` first.
+pub async fn provision_user(
+    conn: &mut SqliteConnection,
+    payload: &UserDto,
+) -> Result<User, ApiError> {
+    let user = save_pwd_hash(payload, &mut *conn).await?;
+    grant_library_access(conn, user.id, &requested_libraries(&payload.library_ids)).await?;
 
-    let salt = SaltString::generate(&mut OsRng);
+    if !user.is_admin {
+        let group_ids = payload
+            .group_ids
+            .clone()
+            .unwrap_or_else(|| vec![DEFAULT_GROUP_ID]);
+        add_user_to_groups(conn, user.id, &group_ids).await?;
+        if let Some(permissions) = &payload.permissions {
+            replace_user_permissions(conn, user.id, permissions).await?;
+        }
+    }
 
-    let password_hash = argon2.hash_password(&password_bytes, &salt)?.to_string();
+    Ok(user)
+}
+
+/// Unknown ids would be skipped silently when the account is created, so
+/// they are refused up front
+pub async fn ensure_known_ids(
+    db: &Pool<Sqlite>,
+    library_ids: Option<&[i64]>,
+    group_ids: Option<&[i64]>,
+) -> Result<(), ApiError> {
+    let libraries = unknown_library_ids(db, library_ids.unwrap_or_default()).await?;
+    if !libraries.is_empty() {
+        return Err(ApiError::BadRequest(format!(
+            "Unknown library ids {:?}",
+            libraries
+        )));
+    }
+    let groups = unknown_group_ids(db, group_ids.unwrap_or_default()).await?;
+    if !groups.is_empty() {
+        return Err(ApiError::BadRequest(format!(
+            "Unknown group ids {:?}",
+            groups
+        )));
+    }
+    Ok(())
+}
+
+/// Libraries a new account is given, the default library when none are named
+pub fn requested_libraries(library_ids: &Option<Vec<i64>>) -> Vec<i64> {
+    library_ids
+        .clone()
+        .unwrap_or_else(|| vec![DEFAULT_LIBRARY_ID])
+}
+
+/// Checks done before anything is written, so a bad request never burns an
+/// invite
+pub async fn validate_new_user(db: &Pool<Sqlite>, payload: &UserDto) -> Result<(), ApiError> {
+    if payload.username.trim().is_empty() || payload.password.is_empty() {
+        return Err(ApiError::BadRequest(
+            "Provide both username and password".into(),
+        ));
+    }
+    validate_new_password(&payload.password)?;
+
+    if get_user_by_username(db, &payload.username).await?.is_some() {
+        return Err(ApiError::BadRequest("Username is already taken".into()));
+    }
+    Ok(())
+}
+
+pub async fn save_pwd_hash(user: &UserDto, db: impl SqliteExecutor<'_>) -> Result<User, ApiError> {
+    let (password_hash, salt) = hash_password(&user.password)?;
 
     let user = user::create_user(
         db,
         &user.username,
         &user.is_admin,
         &password_hash,
-        &salt.to_string(),
+        &salt,
+        user.must_change_password,
     )
     .await?;
 
     Ok(user)
 }
 
-// login
-pub async fn login(
+// Returns the argon2 hash and the salt it was made with
+fn hash_password(password: &str) -> Result<(String, String), ApiError> {
+    let salt = SaltString::generate(&mut OsRng);
+    let password_hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string();
+
+    Ok((password_hash, salt.as_str().to_string()))
+}
+
+fn validate_new_password(password: &str) -> Result<(), ApiError> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(ApiError::BadRequest(format!(
+            "Password must be at least {} characters",
+            MIN_PASSWORD_LEN
+        )));
+    }
+    Ok(())
+}
+
+async fn find_user(db: &Pool<Sqlite>, user_id: i64) -> Result<UserSummary, ApiError> {
+    get_user_summary(db, user_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".into()))
+}
+
+// Admin accounts are only managed by admins. users.manage covers accounts
+// holding nothing the caller lacks, so resetting a password never hands
+// over permissions, and never other user managers.
+async fn ensure_can_manage(
+    db: &Pool<Sqlite>,
+    claims: &Claims,
+    user: &UserSummary,
+) -> Result<(), ApiError> {
+    if claims.is_admin() {
+        return Ok(());
+    }
+    if user.is_admin {
+        return Err(ApiError::Forbidden(
+            "Admin accounts can only be managed by admins".into(),
+        ));
+    }
+
+    let permissions = effective_permissions(db, user.id).await?;
+    if permissions.contains(&Permission::UsersManage) || permissions.iter().any(|p| !claims.has(*p))
+    {
+        return Err(ApiError::Forbidden(
+            "Only admins can manage accounts with permissions you do not hold".into(),
+        ));
+    }
+    Ok(())
+}
+
+// Refuse changes that would leave nobody able to administer the server
+async fn ensure_not_last_admin(db: &Pool<Sqlite>, user: &UserSummary) -> Result<(), ApiError> {
+    if user.is_admin && !user.disabled && count_enabled_admins(db).await? <= 1 {
+        return Err(ApiError::BadRequest(
+            "Cannot remove the last active admin".into(),
+        ));
+    }
+    Ok(())
+}
+
+// List all users
+pub async fn list_users_handler(
     State(state): State<AppState>,
-    Json(payload): Json<LoginDto>,
+    Permitted(_claims, _): Permitted<perm::UsersManage>,
 ) -> Result<impl IntoResponse, ApiError> {
-    let db: &Pool<Sqlite> = &state.db_pool;
-    let config = &state.config;
+    let users = list_users(&state.db_pool).await?;
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "count": users.len(), "users": users })),
+    ))
+}
+
+pub async fn get_user_handler(
+    State(state): State<AppState>,
+    Permitted(_claims, _): Permitted<perm::UsersManage>,
+    Path(user_id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let user = find_user(&state.db_pool, user_id).await?;
+    Ok((StatusCode::OK, Json(user)))
+}
 
-    let jwt = config.jwt_secret.as_ref().unwrap().as_bytes(); // TODO: Handle unwrap properly
+// Promote to admin or demote to a regular user, applies to live sessions
+pub async fn set_role_handler(
+    State(state): State<AppState>,
+    AdminUser(claims): AdminUser,
+    audit: AuditContext,
+    Path(user_id): Path<i64>,
+    Json(payload): Json<RoleDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let user = find_user(db, user_id).await?;
+    let is_admin = payload.role == Role::Admin;
 
-    let token = auth_and_issue_jwt(&payload, db, jwt).await?;
-    Ok((StatusCode::ACCEPTED, Json(json!({"token": token}))))
+    if user.is_admin && !is_admin {
+        ensure_not_last_admin(db, &user).await?;
+    }
+    set_user_admin(db, user_id, is_admin).await?;
+
+    let updated = find_user(db, user_id).await?;
+    audit
+        .record(
+            AuditEvent::new(AuditAction::RoleChanged)
+                .actor(&claims)
+                .target("user", user_id)
+                .before(&user)
+                .after(&updated),
+        )
+        .await;
+    Ok((StatusCode::OK, Json(updated)))
 }
 
-async fn auth_and_issue_jwt(
-    user_input: &LoginDto,
-    db: &Pool<Sqlite>,
-    jwt_secret: &[u8],
-) -> Result<String, ApiError> {
-    let user = get_user_by_username(db, &user_input.username)
+// Block the account from logging in and sign it out everywhere
+pub async fn disable_user_handler(
+    State(state): State<AppState>,
+    Permitted(claims, _): Permitted<perm::UsersManage>,
+    audit: AuditContext,
+    Path(user_id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let user = find_user(db, user_id).await?;
+    ensure_can_manage(db, &claims, &user).await?;
+
+    ensure_not_last_admin(db, &user).await?;
+    set_user_disabled(db, user_id, true).await?;
+    revoke_user_sessions(db, user_id, None).await?;
+    state.events.to_user(
+        user_id,
+        ServerEvent::AllSessionsRevoked {
+            except_session_id: None,
+        },
+    );
+
+    let updated = find_user(db, user_id).await?;
+    audit
+        .record(
+            AuditEvent::new(AuditAction::UserDisabled)
+                .actor(&claims)
+                .target("user", user_id)
+                .before(&user)
+                .after(&updated),
+        )
+        .await;
+    Ok((StatusCode::OK, Json(updated)))
+}
+
+pub async fn enable_user_handler(
+    State(state): State<AppState>,
+    Permitted(claims, _): Permitted<perm::UsersManage>,
+    audit: AuditContext,
+    Path(user_id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let user = find_user(db, user_id).await?;
+    ensure_can_manage(db, &claims, &user).await?;
+    set_user_disabled(db, user_id, false).await?;
+
+    let updated = find_user(db, user_id).await?;
+    audit
+        .record(
+            AuditEvent::new(AuditAction::UserEnabled)
+                .actor(&claims)
+                .target("user", user_id)
+                .before(&user)
+                .after(&updated),
+        )
+        .await;
+    Ok((StatusCode::OK, Json(updated)))
+}
+
+// Set a temporary password for a user. Their sessions are revoked and they
+// have to pick a new password on next login.
+pub async fn reset_password_handler(
+    State(state): State<AppState>,
+    Permitted(claims, _): Permitted<perm::UsersManage>,
+    audit: AuditContext,
+    Path(user_id): Path<i64>,
+    Json(payload): Json<ResetPasswordDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let user = find_user(db, user_id).await?;
+    ensure_can_manage(db, &claims, &user).await?;
+    validate_new_password(&payload.new_password)?;
+
+    let (password_hash, salt) = hash_password(&payload.new_password)?;
+    update_user_password(db, user_id, &password_hash, &salt, true).await?;
+    revoke_user_sessions(db, user_id, None).await?;
+    state.events.to_user(
+        user_id,
+        ServerEvent::AllSessionsRevoked {
+            except_session_id: None,
+        },
+    );
+    audit
+        .record(
+            AuditEvent::new(AuditAction::PasswordReset)
+                .actor(&claims)
+                .target("user", user_id),
+        )
+        .await;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "message": format!("Password of {} reset", user.username) })),
+    ))
+}
+
+// Delete a user along with their progress, sessions and library grants
+pub async fn delete_user_handler(
+    State(state): State<AppState>,
+    Permitted(claims, _): Permitted<perm::UsersManage>,
+    audit: AuditContext,
+    Path(user_id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let user = find_user(db, user_id).await?;
+    ensure_can_manage(db, &claims, &user).await?;
+
+    ensure_not_last_admin(db, &user).await?;
+    delete_user(db, user_id).await?;
+    state.events.to_user(
+        user_id,
+        ServerEvent::AllSessionsRevoked {
+            except_session_id: None,
+        },
+    );
+    audit
+        .record(
+            AuditEvent::new(AuditAction::UserDeleted)
+                .actor(&claims)
+                .target("user", user_id)
+                .before(&user),
+        )
+        .await;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "message": format!("User {} deleted", user.username) })),
+    ))
+}
+
+// Usernames and addresses currently backing off or locked out of login
+pub async fn list_lockouts_handler(
+    State(state): State<AppState>,
+    Permitted(_claims, _): Permitted<perm::UsersManage>,
+) -> Result<impl IntoResponse, ApiError> {
+    let attempts =
+        list_login_attempts(&state.db_pool, Utc::now().timestamp(), FAILURE_WINDOW_SECS).await?;
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "count": attempts.len(), "lockouts": attempts })),
+    ))
+}
+
+// Forget the failed logins of a username or address, lifting any lockout
+pub async fn clear_lockout_handler(
+    State(state): State<AppState>,
+    Permitted(claims, _): Permitted<perm::UsersManage>,
+    audit: AuditContext,
+    Path((scope, key)): Path<(AttemptScope, String)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let key = match scope {
+        AttemptScope::User => username_key(&key),
+        AttemptScope::Ip => key,
+    };
+    if !clear_login_attempts(&state.db_pool, scope, &key).await? {
+        return Err(ApiError::NotFound("No failed logins recorded".into()));
+    }
+    audit
+        .record(
+            AuditEvent::new(AuditAction::LockoutCleared)
+                .actor(&claims)
+                .target("lockout", &key)
+                .before(&json!({ "scope": scope, "key": key })),
+        )
+        .await;
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "message": "Lockout cleared", "scope": scope, "key": key })),
+    ))
+}
+
+// Change the caller's own password. Other devices are signed out, the
+// current session stays valid. Also the only way out of a forced rotation.
+pub async fn change_password_handler(
+    State(state): State<AppState>,
+    PasswordChangeUser(claims): PasswordChangeUser,
+    audit: AuditContext,
+    Json(payload): Json<ChangePasswordDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db = &state.db_pool;
+    let user = get_user_by_id(db, claims.sub)
         .await?
-        .ok_or_else(|| ApiError::BadRequest("User not found".to_string()))?;
+        .ok_or_else(|| ApiError::NotFound("User not found".into()))?;
 
     let parsed_hash = PasswordHash::new(&user.password_hash)?;
-    Argon2::default().verify_password(user_input.password.as_bytes(), &parsed_hash)?;
+    Argon2::default()
+        .verify_password(payload.current_password.as_bytes(), &parsed_hash)
+        .map_err(|_| ApiError::Unauthorized("Current password is incorrect".into()))?;
 
-    let now = Utc::now();
-    let exp = now + Duration::hours(24); // token valid for 24 hours
+    validate_new_password(&payload.new_password)?;
+    if payload.new_password == payload.current_password {
+        return Err(ApiError::BadRequest(
+            "New password must differ from the current one".into(),
+        ));
+    }
 
-    let claims = Claims {
-        sub: user.id,
-        role: if user.is_admin == true {
-            "admin".to_owned()
-        } else {
-            "user".to_owned()
+    let (password_hash, salt) = hash_password(&payload.new_password)?;
+    update_user_password(db, user.id, &password_hash, &salt, false).await?;
+    revoke_user_sessions(db, user.id, Some(&claims.sid)).await?;
+    state.events.to_user(
+        user.id,
+        ServerEvent::AllSessionsRevoked {
+            except_session_id: Some(claims.sid.clone()),
         },
-        username: user.username.clone(),
-        iat: now.timestamp() as usize,
-        exp: exp.timestamp() as usize,
+    );
+    audit
+        .record(
+            AuditEvent::new(AuditAction::PasswordChanged)
+                .actor(&claims)
+                .target("user", user.id),
+        )
+        .await;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "message": "Password changed" })),
+    ))
+}
+
+// login, opens a session for the device and returns an access/refresh token pair
+pub async fn login(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    audit: AuditContext,
+    Json(payload): Json<LoginDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    let db: &Pool<Sqlite> = &state.db_pool;
+    let ip = client_ip(&state, &headers, addr);
+
+    reserve_login(db, &payload.username, &ip).await?;
+    let user = match authenticate(&payload, db).await {
+        Ok(user) => user,
+        Err(e) => {
+            if matches!(e, ApiError::Unauthorized(_)) {
+                record_failed_login(db, &payload.username, &ip).await?;
+                audit
+                    .record(
+                        AuditEvent::new(AuditAction::LoginFailed)
+                            .actor_user(None, &payload.username),
+                    )
+                    .await;
+            } else {
+                release_login(db, &payload.username, &ip).await?;
+            }
+            return Err(e);
+        }
+    };
+    record_successful_login(db, &payload.username, &ip).await?;
+
+    let tokens = start_session(&state, &user, payload.device_name.as_deref(), &ip).await?;
+    audit
+        .record(
+            AuditEvent::new(AuditAction::LoginSucceeded)
+                .actor_user(Some(user.id), &user.username)
+                .target("session", &tokens.session_id),
+        )
+        .await;
+
+    Ok((StatusCode::ACCEPTED, Json(tokens)))
+}
+
+// Unknown users and wrong passwords get the same error and take the same
+// time, so responses do not reveal which usernames exist
+async fn authenticate(user_input: &LoginDto, db: &Pool<Sqlite>) -> Result<User, ApiError> {
+    let invalid = || ApiError::Unauthorized("Invalid username or password".into());
+
+    let Some(user) = get_user_by_username(db, &user_input.username).await? else {
+        if let Ok(dummy) = PasswordHash::new(&DUMMY_HASH) {
+            let _ = Argon2::default().verify_password(user_input.password.as_bytes(), &dummy);
+        }
+        return Err(invalid());
     };
 
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(jwt_secret),
-    )?;
+    let parsed_hash = PasswordHash::new(&user.password_hash)?;
+    Argon2::default()
+        .verify_password(user_input.password.as_bytes(), &parsed_hash)
+        .map_err(|_| invalid())?;
+
+    if user.disabled {
+        return Err(ApiError::Unauthorized("Account is disabled".into()));
+    }
 
-    Ok(token)
+    Ok(user)
 }
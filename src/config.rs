@@ -6,7 +6,46 @@ pub struct Config {
     pub host: String,
     pub port: u16,
     pub book_files: String,
+    pub watch_library: bool,
+    pub trust_proxy: bool,
+    pub admin_username: String,
+    pub admin_password: Option<String>,
     pub jwt_secret: anyhow::Result<String>,
+    pub jwt_algorithm: String,
+    pub jwt_key_id: String,
+    pub jwt_private_key_path: Option<String>,
+    pub jwt_public_key_path: Option<String>,
+    pub jwt_previous_keys: Vec<String>,
+    pub oidc_issuer: Option<String>,
+    pub oidc_client_id: Option<String>,
+    pub oidc_client_secret: Option<String>,
+    pub oidc_redirect_uri: Option<String>,
+    pub oidc_scopes: String,
+    pub oidc_username_claim: String,
+    pub oidc_groups_claim: String,
+    pub oidc_admin_groups: Vec<String>,
+    pub oidc_group_map: Vec<String>,
+    pub oidc_auto_provision: bool,
+    pub oidc_link_existing: bool,
+}
+
+// Comma separated list, blank entries dropped
+fn list_var(name: &str) -> Vec<String> {
+    env::var(name)
+        .map(|v| {
+            v.split(',')
+                .map(str::trim)
+                .filter(|k| !k.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn flag_var(name: &str, default: bool) -> bool {
+    env::var(name)
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(default)
 }
 
 impl Config {
@@ -20,7 +59,34 @@ impl Config {
                 .parse()
                 .unwrap_or(3000),
             book_files: env::var("AUDIOBOOKS_LOCATION").unwrap_or_else(|_| "data".to_string()),
+            watch_library: env::var("WATCH_LIBRARY")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            trust_proxy: flag_var("TRUST_PROXY", false),
+            admin_username: env::var("ADMIN_USERNAME").unwrap_or_else(|_| "admin".to_string()),
+            admin_password: env::var("ADMIN_PASSWORD").ok().filter(|p| !p.is_empty()),
             jwt_secret: env::var("JWT_SECRET").with_context(|| "Please set JWT SECRET"),
+            jwt_algorithm: env::var("JWT_ALGORITHM").unwrap_or_else(|_| "HS256".to_string()),
+            jwt_key_id: env::var("JWT_KEY_ID").unwrap_or_else(|_| "primary".to_string()),
+            jwt_private_key_path: env::var("JWT_PRIVATE_KEY_PATH").ok(),
+            jwt_public_key_path: env::var("JWT_PUBLIC_KEY_PATH").ok(),
+            jwt_previous_keys: list_var("JWT_PREVIOUS_KEYS"),
+            oidc_issuer: env::var("OIDC_ISSUER").ok().filter(|v| !v.is_empty()),
+            oidc_client_id: env::var("OIDC_CLIENT_ID").ok(),
+            oidc_client_secret: env::var("OIDC_CLIENT_SECRET")
+                .ok()
+                .filter(|v| !v.is_empty()),
+            oidc_redirect_uri: env::var("OIDC_REDIRECT_URI").ok(),
+            oidc_scopes: env::var("OIDC_SCOPES")
+                .unwrap_or_else(|_| "openid profile email groups".to_string()),
+            oidc_username_claim: env::var("OIDC_USERNAME_CLAIM")
+                .unwrap_or_else(|_| "preferred_username".to_string()),
+            oidc_groups_claim: env::var("OIDC_GROUPS_CLAIM")
+                .unwrap_or_else(|_| "groups".to_string()),
+            oidc_admin_groups: list_var("OIDC_ADMIN_GROUPS"),
+            oidc_group_map: list_var("OIDC_GROUP_MAP"),
+            oidc_auto_provision: flag_var("OIDC_AUTO_PROVISION", true),
+            oidc_link_existing: flag_var("OIDC_LINK_EXISTING", false),
         })
     }
 }
@@ -0,0 +1,124 @@
+use sqlx::{Pool, Sqlite};
+
+use crate::{
+    api::api_error::ApiError,
+    models::api_keys::{ApiKey, ApiKeyOwner, ApiKeyScope},
+};
+
+const API_KEY_COLUMNS: &str = "id, name, prefix, scope, \
+     CAST(expires_at AS TEXT) AS expires_at, CAST(last_used_at AS TEXT) AS last_used_at, \
+     CAST(created_at AS TEXT) AS created_at";
+
+// Same idea as sessions, only write last_used_at every few minutes
+const LAST_USED_RESOLUTION: &str = "-5 minutes";
+
+pub async fn create_api_key(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    name: &str,
+    prefix: &str,
+    key_hash: &str,
+    scope: ApiKeyScope,
+    expires_in_days: Option<i64>,
+) -> Result<ApiKey, ApiError> {
+    let key = sqlx::query_as::<_, ApiKey>(&format!(
+        "INSERT INTO api_keys (user_id, name, prefix, key_hash, scope, expires_at) \
+         VALUES (?1, ?2, ?3, ?4, ?5, CASE WHEN ?6 IS NULL THEN NULL ELSE datetime('now', ?6) END) \
+         RETURNING {}",
+        API_KEY_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(name)
+    .bind(prefix)
+    .bind(key_hash)
+    .bind(scope)
+    .bind(expires_in_days.map(|days| format!("+{} days", days)))
+    .fetch_one(db)
+    .await?;
+
+    Ok(key)
+}
+
+/// Keys that have not been revoked, expired ones included so users see why
+/// a script stopped working
+pub async fn list_api_keys(db: &Pool<Sqlite>, user_id: i64) -> Result<Vec<ApiKey>, ApiError> {
+    let keys = sqlx::query_as::<_, ApiKey>(&format!(
+        "SELECT {} FROM api_keys WHERE user_id = ?1 AND revoked_at IS NULL ORDER BY created_at DESC",
+        API_KEY_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(keys)
+}
+
+pub async fn revoke_api_key(db: &Pool<Sqlite>, user_id: i64, id: i64) -> Result<bool, ApiError> {
+    let result = sqlx::query(
+        r#"
+        UPDATE api_keys
+        SET revoked_at = CURRENT_TIMESTAMP
+        WHERE id = ?1 AND user_id = ?2 AND revoked_at IS NULL
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Whether the key would still authenticate, checked by long lived
+/// connections opened with it
+pub async fn api_key_active(db: &Pool<Sqlite>, id: i64) -> Result<bool, ApiError> {
+    let found: Option<i64> = sqlx::query_scalar(
+        r#"
+        SELECT k.id
+        FROM api_keys k
+        JOIN users u ON u.id = k.user_id
+        WHERE k.id = ?1
+          AND k.revoked_at IS NULL
+          AND (k.expires_at IS NULL OR k.expires_at > datetime('now'))
+          AND u.disabled = 0
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(found.is_some())
+}
+
+/// Looks up a usable key by hash, bumping its last used time on the way
+pub async fn find_api_key(
+    db: &Pool<Sqlite>,
+    key_hash: &str,
+) -> Result<Option<ApiKeyOwner>, ApiError> {
+    let owner = sqlx::query_as::<_, ApiKeyOwner>(
+        r#"
+        SELECT k.id, k.scope, u.id AS user_id, u.username, u.is_admin, u.must_change_password,
+               COALESCE(k.last_used_at < datetime('now', ?2), TRUE) AS stale
+        FROM api_keys k
+        JOIN users u ON u.id = k.user_id
+        WHERE k.key_hash = ?1
+          AND k.revoked_at IS NULL
+          AND (k.expires_at IS NULL OR k.expires_at > datetime('now'))
+          AND u.disabled = 0
+        "#,
+    )
+    .bind(key_hash)
+    .bind(LAST_USED_RESOLUTION)
+    .fetch_optional(db)
+    .await?;
+
+    if let Some(owner) = &owner
+        && owner.stale
+    {
+        sqlx::query("UPDATE api_keys SET last_used_at = CURRENT_TIMESTAMP WHERE id = ?1")
+            .bind(owner.id)
+            .execute(db)
+            .await?;
+    }
+    Ok(owner)
+}
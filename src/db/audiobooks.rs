@@ -1,13 +1,16 @@
 use crate::{
     api::api_error::ApiError,
-    models::audiobooks::{AudioBook, AudioBookRow, CreateFileMetadata, FileMetadata},
+    models::audiobooks::{
+        AudioBook, AudioBookRow, BookCursor, BookListQuery, BookListRow, BookPage, BookSort,
+        Chapter, ChapterRow, CreateFileMetadata, FileMetadata, SortOrder,
+    },
 };
-use sqlx::{Pool, Sqlite};
+use sqlx::{Pool, QueryBuilder, Sqlite};
 
 pub async fn list_all_books(db: &Pool<Sqlite>) -> Result<Vec<AudioBookRow>, ApiError> {
     let books = sqlx::query_as::<_, AudioBookRow>(
         r#"
-        SELECT id, author, series, title, files_location, cover_art, duration, metadata
+        SELECT id, library_id, author, series, title, files_location, cover_art, duration, metadata
         FROM audiobooks
         ORDER BY author, series, title
         "#,
@@ -18,6 +21,40 @@ pub async fn list_all_books(db: &Pool<Sqlite>) -> Result<Vec<AudioBookRow>, ApiE
     Ok(books)
 }
 
+pub async fn list_books_without_cover(db: &Pool<Sqlite>) -> Result<Vec<AudioBookRow>, ApiError> {
+    let books = sqlx::query_as::<_, AudioBookRow>(
+        r#"
+        SELECT id, library_id, author, series, title, files_location, cover_art, duration, metadata
+        FROM audiobooks
+        WHERE cover_art IS NULL
+        "#,
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(books)
+}
+
+pub async fn set_book_cover(
+    db: &Pool<Sqlite>,
+    bookid: i64,
+    cover_art: &str,
+) -> Result<(), ApiError> {
+    sqlx::query!(
+        r#"
+        UPDATE audiobooks
+        SET cover_art = ?1, updated_at = CURRENT_TIMESTAMP
+        WHERE id = ?2
+        "#,
+        cover_art,
+        bookid
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
 pub async fn insert_audiobook(db: &Pool<Sqlite>, book: &AudioBook) -> Result<i64, ApiError> {
     let id = sqlx::query_scalar!(
         r#"
@@ -63,13 +100,14 @@ pub async fn update_audiobook_duration(
 pub async fn insert_file_metadata(
     db: &Pool<Sqlite>,
     create_data: &mut CreateFileMetadata,
-) -> Result<(), ApiError> {
+) -> Result<i64, ApiError> {
     // let file_path = create_data.file_path.to_string().to_owned();
 
-    sqlx::query!(
+    let id = sqlx::query_scalar!(
         r#"
         INSERT INTO files (book_id, file_id, file_name, file_path, duration, channels, sample_rate, bitrate)
         VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        RETURNING id AS "id!"
         "#,
         create_data.book_id,
         create_data.file_id,
@@ -80,12 +118,65 @@ pub async fn insert_file_metadata(
         create_data.sample_rate,
         create_data.bitrate
     )
-    .execute(db)
+    .fetch_one(db)
     .await?;
 
+    Ok(id)
+}
+
+pub async fn replace_file_chapters(
+    db: &Pool<Sqlite>,
+    file_id: i64,
+    chapters: &[Chapter],
+) -> Result<(), ApiError> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query("DELETE FROM chapters WHERE file_id = ?1")
+        .bind(file_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for (index, chapter) in chapters.iter().enumerate() {
+        sqlx::query(
+            r#"
+            INSERT INTO chapters (file_id, chapter_index, title, start_ms, end_ms)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            "#,
+        )
+        .bind(file_id)
+        .bind(index as i64)
+        .bind(&chapter.title)
+        .bind(chapter.start_ms)
+        .bind(chapter.end_ms)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
     Ok(())
 }
 
+pub async fn get_chapters_by_book_id(
+    db: &Pool<Sqlite>,
+    book_id: i64,
+) -> Result<Vec<ChapterRow>, ApiError> {
+    let rows = sqlx::query_as::<_, ChapterRow>(
+        r#"
+        SELECT c.file_id, c.title, c.start_ms, c.end_ms
+        FROM chapters c
+        JOIN files f ON f.id = c.file_id
+        WHERE f.book_id = ?1
+        ORDER BY c.file_id, c.chapter_index
+        "#,
+    )
+    .bind(book_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows)
+}
+
+#[allow(dead_code)]
 pub async fn get_audiobook_id(db: &Pool<Sqlite>, book: &AudioBook) -> Result<i64, ApiError> {
     let row: (i64,) = sqlx::query_as(
         r#"
@@ -102,20 +193,23 @@ pub async fn get_audiobook_id(db: &Pool<Sqlite>, book: &AudioBook) -> Result<i64
     Ok(row.0)
 }
 
-// pub async fn get_audiobook_by_id(db: &Pool<Sqlite>, bookid: i64) -> Result<AudioBookRow> {
-//     let row = sqlx::query_as(
-//         r#"
-//         SELECT *
-//         FROM audiobooks
-//         WHERE id = ?1
-//         "#,
-//     )
-//     .bind(bookid)
-//     .fetch_one(db)
-//     .await?;
+pub async fn get_audiobook_by_id(
+    db: &Pool<Sqlite>,
+    bookid: i64,
+) -> Result<Option<AudioBookRow>, ApiError> {
+    let row = sqlx::query_as::<_, AudioBookRow>(
+        r#"
+        SELECT id, library_id, author, series, title, files_location, cover_art, duration, metadata
+        FROM audiobooks
+        WHERE id = ?1
+        "#,
+    )
+    .bind(bookid)
+    .fetch_optional(db)
+    .await?;
 
-//     Ok(row)
-// }
+    Ok(row)
+}
 
 pub async fn get_files_by_book_id(
     db: &Pool<Sqlite>,
@@ -178,3 +272,226 @@ pub async fn get_file_path(db: &Pool<Sqlite>, file_id: i64) -> Result<String, Ap
 
     Ok(path.0)
 }
+
+// Books joined with what the list can sort and filter on. Narrators are
+// separated by the unit separator since names may contain commas.
+const BOOK_VIEW: &str = r#"
+    WITH book_view AS (
+        SELECT
+            ab.id,
+            ab.library_id,
+            ab.author,
+            ab.series,
+            ab.title,
+            ab.files_location,
+            ab.cover_art,
+            ab.duration,
+            ab.metadata,
+            CAST(ab.created_at AS TEXT) AS created_at,
+            (
+                SELECT group_concat(narrator, char(31))
+                FROM (
+                    SELECT DISTINCT fsc.narrated_by AS narrator
+                    FROM files f
+                    JOIN file_scan_cache fsc ON fsc.file_path = f.file_path
+                    WHERE f.book_id = ab.id
+                )
+            ) AS narrators,
+            EXISTS (
+                SELECT 1
+                FROM files f
+                JOIN file_scan_cache fsc ON fsc.file_path = f.file_path
+                WHERE f.book_id = ab.id AND fsc.dramatized
+            ) AS dramatized,
+            CASE
+                WHEN up.book_id IS NULL THEN 'not_started'
+                WHEN up.complete_files >= (SELECT COUNT(*) FROM files f WHERE f.book_id = ab.id)
+                    THEN 'finished'
+                ELSE 'in_progress'
+            END AS completion,
+            up.last_listened
+        FROM audiobooks ab
+        LEFT JOIN (
+            SELECT
+                book_id,
+                SUM(complete) AS complete_files,
+                CAST(MAX(updated_at) AS TEXT) AS last_listened
+            FROM progress
+            WHERE user_id = "#;
+
+fn sort_key(sort: BookSort) -> &'static str {
+    match sort {
+        BookSort::Title => "title COLLATE NOCASE",
+        BookSort::Author => "author COLLATE NOCASE",
+        BookSort::CreatedAt => "created_at",
+        BookSort::RecentlyListened => "COALESCE(last_listened, '')",
+        BookSort::Duration => "duration",
+    }
+}
+
+fn cursor_key(sort: BookSort, row: &BookListRow) -> serde_json::Value {
+    match sort {
+        BookSort::Title => row.book.title.clone().into(),
+        BookSort::Author => row.book.author.clone().into(),
+        BookSort::CreatedAt => row.created_at.clone().into(),
+        BookSort::RecentlyListened => row.last_listened.clone().unwrap_or_default().into(),
+        BookSort::Duration => row.book.duration.into(),
+    }
+}
+
+fn push_book_view(qb: &mut QueryBuilder<'_, Sqlite>, user_id: i64) {
+    qb.push(BOOK_VIEW)
+        .push_bind(user_id)
+        .push(" GROUP BY book_id ) up ON up.book_id = ab.id ) SELECT ");
+}
+
+fn push_filters<'a>(
+    qb: &mut QueryBuilder<'a, Sqlite>,
+    query: &'a BookListQuery,
+    scope: Option<&'a [i64]>,
+) {
+    qb.push(" FROM book_view WHERE 1 = 1");
+
+    if let Some(ids) = scope {
+        if ids.is_empty() {
+            qb.push(" AND 0");
+        } else {
+            qb.push(" AND library_id IN (");
+            let mut separated = qb.separated(", ");
+            for id in ids {
+                separated.push_bind(*id);
+            }
+            separated.push_unseparated(")");
+        }
+    }
+    if let Some(library_id) = query.library_id {
+        qb.push(" AND library_id = ").push_bind(library_id);
+    }
+
+    if let Some(author) = &query.author {
+        qb.push(" AND author = ")
+            .push_bind(author)
+            .push(" COLLATE NOCASE");
+    }
+    if let Some(series) = &query.series {
+        qb.push(" AND series = ")
+            .push_bind(series)
+            .push(" COLLATE NOCASE");
+    }
+    if let Some(narrator) = &query.narrator {
+        qb.push(
+            " AND EXISTS (SELECT 1 FROM files f JOIN file_scan_cache fsc ON fsc.file_path = f.file_path \
+             WHERE f.book_id = book_view.id AND instr(lower(fsc.narrated_by), lower(",
+        )
+        .push_bind(narrator)
+        .push(")) > 0)");
+    }
+    if let Some(dramatized) = query.dramatized {
+        qb.push(" AND dramatized = ").push_bind(dramatized);
+    }
+    if let Some(completion) = query.completion {
+        qb.push(" AND completion = ").push_bind(completion);
+    }
+}
+
+/// Keyset paginated book list. Books are ordered by the sort key then id so
+/// the cursor stays stable while books are added or removed.
+pub async fn list_books_page(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    query: &BookListQuery,
+    scope: Option<&[i64]>,
+    limit: i64,
+) -> Result<BookPage, ApiError> {
+    let sort = query.sort;
+    let order = query.order.unwrap_or_else(|| sort.default_order());
+
+    let cursor = match &query.cursor {
+        Some(value) => {
+            let cursor = BookCursor::decode(value)
+                .ok_or_else(|| ApiError::BadRequest("Invalid cursor".into()))?;
+            if cursor.sort != sort || cursor.order != order {
+                return Err(ApiError::BadRequest(
+                    "Cursor was issued for a different sort".into(),
+                ));
+            }
+            Some(cursor)
+        }
+        None => None,
+    };
+
+    let mut count_qb = QueryBuilder::new("");
+    push_book_view(&mut count_qb, user_id);
+    count_qb.push("COUNT(*)");
+    push_filters(&mut count_qb, query, scope);
+    let total: i64 = count_qb.build_query_scalar().fetch_one(db).await?;
+
+    let key = sort_key(sort);
+    let (cmp, dir) = match order {
+        SortOrder::Asc => (">", "ASC"),
+        SortOrder::Desc => ("<", "DESC"),
+    };
+
+    let mut qb = QueryBuilder::new("");
+    push_book_view(&mut qb, user_id);
+    qb.push("*");
+    push_filters(&mut qb, query, scope);
+
+    if let Some(cursor) = &cursor {
+        let bind_key = |qb: &mut QueryBuilder<'_, Sqlite>| match &cursor.key {
+            serde_json::Value::Number(n) => {
+                qb.push_bind(n.as_i64().unwrap_or_default());
+            }
+            other => {
+                qb.push_bind(other.as_str().unwrap_or_default().to_string());
+            }
+        };
+
+        qb.push(format!(" AND ({} {} ", key, cmp));
+        bind_key(&mut qb);
+        qb.push(format!(" OR ({} = ", key));
+        bind_key(&mut qb);
+        qb.push(format!(" AND id {} ", cmp))
+            .push_bind(cursor.id)
+            .push("))");
+    }
+
+    qb.push(format!(" ORDER BY {} {}, id {} LIMIT ", key, dir, dir))
+        .push_bind(limit + 1);
+
+    let mut books: Vec<BookListRow> = qb.build_query_as().fetch_all(db).await?;
+
+    let next_cursor = if books.len() as i64 > limit {
+        books.truncate(limit as usize);
+        books.last().map(|last| {
+            BookCursor {
+                sort,
+                order,
+                key: cursor_key(sort, last),
+                id: last.book.id,
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+
+    Ok(BookPage {
+        books,
+        total,
+        next_cursor,
+    })
+}
+
+/// Books in the libraries of `scope`, or in every library when `None`
+pub async fn count_books(db: &Pool<Sqlite>, scope: Option<&[i64]>) -> Result<i64, ApiError> {
+    let scope = scope.map(|ids| serde_json::to_string(ids).unwrap_or_default());
+    let total: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM audiobooks \
+         WHERE ?1 IS NULL OR library_id IN (SELECT value FROM json_each(?1))",
+    )
+    .bind(scope)
+    .fetch_one(db)
+    .await?;
+    Ok(total)
+}
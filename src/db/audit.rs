@@ -0,0 +1,90 @@
+use sqlx::{Pool, QueryBuilder, Sqlite};
+
+use crate::{
+    api::api_error::ApiError,
+    models::audit::{AuditFilter, AuditRow},
+};
+
+const AUDIT_COLUMNS: &str = "id, actor_id, actor_name, action, target_type, target_id, \
+     before_json, after_json, request_id, ip_address, CAST(created_at AS TEXT) AS created_at";
+
+pub struct NewAuditEntry<'a> {
+    pub actor_id: Option<i64>,
+    pub actor_name: Option<&'a str>,
+    pub action: &'a str,
+    pub target_type: Option<&'a str>,
+    pub target_id: Option<&'a str>,
+    pub before_json: Option<String>,
+    pub after_json: Option<String>,
+    pub request_id: Option<&'a str>,
+    pub ip_address: Option<&'a str>,
+}
+
+pub async fn insert_audit_entry(
+    db: &Pool<Sqlite>,
+    entry: NewAuditEntry<'_>,
+) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        INSERT INTO audit_log (actor_id, actor_name, action, target_type, target_id,
+                               before_json, after_json, request_id, ip_address)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+        "#,
+    )
+    .bind(entry.actor_id)
+    .bind(entry.actor_name)
+    .bind(entry.action)
+    .bind(entry.target_type)
+    .bind(entry.target_id)
+    .bind(entry.before_json)
+    .bind(entry.after_json)
+    .bind(entry.request_id)
+    .bind(entry.ip_address)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Newest first, `limit` entries older than `before_id`
+pub async fn list_audit_entries(
+    db: &Pool<Sqlite>,
+    filter: &AuditFilter,
+    limit: i64,
+) -> Result<Vec<AuditRow>, ApiError> {
+    let mut qb: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+        "SELECT {} FROM audit_log WHERE 1 = 1",
+        AUDIT_COLUMNS
+    ));
+
+    if let Some(actor_id) = filter.actor_id {
+        qb.push(" AND actor_id = ").push_bind(actor_id);
+    }
+    if let Some(actor) = &filter.actor {
+        qb.push(" AND actor_name = ")
+            .push_bind(actor.clone())
+            .push(" COLLATE NOCASE");
+    }
+    if let Some(action) = &filter.action {
+        qb.push(" AND action = ").push_bind(action.clone());
+    }
+    if let Some(prefix) = &filter.action_prefix {
+        qb.push(" AND substr(action, 1, length(")
+            .push_bind(prefix.clone())
+            .push(")) = ")
+            .push_bind(prefix.clone());
+    }
+    if let Some(since) = &filter.since {
+        qb.push(" AND created_at >= ").push_bind(since.clone());
+    }
+    if let Some(until) = &filter.until {
+        qb.push(" AND created_at < ").push_bind(until.clone());
+    }
+    if let Some(before_id) = filter.before_id {
+        qb.push(" AND id < ").push_bind(before_id);
+    }
+    qb.push(" ORDER BY id DESC LIMIT ").push_bind(limit);
+
+    let rows = qb.build_query_as::<AuditRow>().fetch_all(db).await?;
+    Ok(rows)
+}
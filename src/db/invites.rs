@@ -0,0 +1,117 @@
+use sqlx::{Pool, Sqlite, SqliteExecutor};
+
+use crate::{
+    api::api_error::ApiError,
+    models::invites::{CreateInviteDto, InviteGrant, InviteRow},
+};
+
+const INVITE_COLUMNS: &str = "id, created_by, note, is_admin, library_ids, group_ids, \
+     CAST(expires_at AS TEXT) AS expires_at, CAST(created_at AS TEXT) AS created_at, \
+     CAST(used_at AS TEXT) AS used_at, used_by, \
+     CASE WHEN used_at IS NOT NULL THEN 'used' \
+          WHEN expires_at <= datetime('now') THEN 'expired' \
+          ELSE 'pending' END AS status";
+
+pub async fn create_invite(
+    db: &Pool<Sqlite>,
+    token_hash: &str,
+    created_by: i64,
+    invite: &CreateInviteDto,
+    expires_in_hours: i64,
+) -> Result<InviteRow, ApiError> {
+    let invite = sqlx::query_as::<_, InviteRow>(&format!(
+        "INSERT INTO invites (token_hash, created_by, note, is_admin, library_ids, group_ids, expires_at) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, datetime('now', ?7)) \
+         RETURNING {}",
+        INVITE_COLUMNS
+    ))
+    .bind(token_hash)
+    .bind(created_by)
+    .bind(invite.note.as_deref().map(str::trim).filter(|n| !n.is_empty()))
+    .bind(invite.is_admin)
+    .bind(invite.library_ids.as_ref().map(|ids| serde_json::json!(ids).to_string()))
+    .bind(invite.group_ids.as_ref().map(|ids| serde_json::json!(ids).to_string()))
+    .bind(format!("+{} hours", expires_in_hours))
+    .fetch_one(db)
+    .await?;
+
+    Ok(invite)
+}
+
+/// Invites that have not been revoked, newest first
+pub async fn list_invites(db: &Pool<Sqlite>) -> Result<Vec<InviteRow>, ApiError> {
+    let invites = sqlx::query_as::<_, InviteRow>(&format!(
+        "SELECT {} FROM invites WHERE revoked_at IS NULL ORDER BY created_at DESC, id DESC",
+        INVITE_COLUMNS
+    ))
+    .fetch_all(db)
+    .await?;
+
+    Ok(invites)
+}
+
+pub async fn get_invite(db: &Pool<Sqlite>, id: i64) -> Result<Option<InviteRow>, ApiError> {
+    let invite = sqlx::query_as::<_, InviteRow>(&format!(
+        "SELECT {} FROM invites WHERE id = ?1 AND revoked_at IS NULL",
+        INVITE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(invite)
+}
+
+/// Only unused invites can be revoked
+pub async fn revoke_invite(db: &Pool<Sqlite>, id: i64) -> Result<bool, ApiError> {
+    let result = sqlx::query(
+        r#"
+        UPDATE invites
+        SET revoked_at = CURRENT_TIMESTAMP
+        WHERE id = ?1 AND revoked_at IS NULL AND used_at IS NULL
+        "#,
+    )
+    .bind(id)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Marks a live invite as used in a single statement so two registrations
+/// racing on the same token cannot both succeed
+pub async fn consume_invite(
+    db: impl SqliteExecutor<'_>,
+    token_hash: &str,
+) -> Result<Option<InviteGrant>, ApiError> {
+    let grant = sqlx::query_as::<_, InviteGrant>(
+        r#"
+        UPDATE invites
+        SET used_at = CURRENT_TIMESTAMP
+        WHERE token_hash = ?1
+          AND used_at IS NULL
+          AND revoked_at IS NULL
+          AND expires_at > datetime('now')
+        RETURNING id, is_admin, library_ids, group_ids
+        "#,
+    )
+    .bind(token_hash)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(grant)
+}
+
+pub async fn set_invite_used_by(
+    db: impl SqliteExecutor<'_>,
+    id: i64,
+    user_id: i64,
+) -> Result<(), ApiError> {
+    sqlx::query("UPDATE invites SET used_by = ?1 WHERE id = ?2")
+        .bind(user_id)
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,299 @@
+use sqlx::{Pool, Sqlite, SqliteConnection};
+
+use crate::{
+    api::api_error::ApiError,
+    models::libraries::{DEFAULT_LIBRARY_ID, Library, LibraryMember},
+};
+
+const LIBRARY_COLUMNS: &str = "id, name, root_path, media_type, \
+     CAST(created_at AS TEXT) AS created_at, CAST(updated_at AS TEXT) AS updated_at";
+
+/// Points the default library at the configured root the first time the
+/// server starts after libraries were introduced
+pub async fn ensure_default_library(db: &Pool<Sqlite>, root: &str) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        UPDATE libraries
+        SET root_path = ?1, updated_at = CURRENT_TIMESTAMP
+        WHERE id = ?2 AND root_path = ''
+        "#,
+    )
+    .bind(root)
+    .bind(DEFAULT_LIBRARY_ID)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn list_libraries(db: &Pool<Sqlite>) -> Result<Vec<Library>, ApiError> {
+    let libraries = sqlx::query_as::<_, Library>(&format!(
+        "SELECT {} FROM libraries ORDER BY name",
+        LIBRARY_COLUMNS
+    ))
+    .fetch_all(db)
+    .await?;
+
+    Ok(libraries)
+}
+
+pub async fn list_user_libraries(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+) -> Result<Vec<Library>, ApiError> {
+    let libraries = sqlx::query_as::<_, Library>(&format!(
+        "SELECT {} FROM libraries \
+         WHERE id IN (SELECT library_id FROM library_access WHERE user_id = ?1) \
+         ORDER BY name",
+        LIBRARY_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(libraries)
+}
+
+pub async fn get_library(db: &Pool<Sqlite>, id: i64) -> Result<Option<Library>, ApiError> {
+    let library = sqlx::query_as::<_, Library>(&format!(
+        "SELECT {} FROM libraries WHERE id = ?1",
+        LIBRARY_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(library)
+}
+
+pub async fn create_library(
+    db: &Pool<Sqlite>,
+    name: &str,
+    root_path: &str,
+    media_type: &str,
+) -> Result<Library, ApiError> {
+    let library = sqlx::query_as::<_, Library>(&format!(
+        "INSERT INTO libraries (name, root_path, media_type) VALUES (?1, ?2, ?3) RETURNING {}",
+        LIBRARY_COLUMNS
+    ))
+    .bind(name)
+    .bind(root_path)
+    .bind(media_type)
+    .fetch_one(db)
+    .await?;
+
+    Ok(library)
+}
+
+pub async fn update_library(db: &Pool<Sqlite>, library: &Library) -> Result<Library, ApiError> {
+    let library = sqlx::query_as::<_, Library>(&format!(
+        "UPDATE libraries \
+         SET name = ?1, root_path = ?2, media_type = ?3, updated_at = CURRENT_TIMESTAMP \
+         WHERE id = ?4 RETURNING {}",
+        LIBRARY_COLUMNS
+    ))
+    .bind(&library.name)
+    .bind(&library.root_path)
+    .bind(&library.media_type)
+    .bind(library.id)
+    .fetch_one(db)
+    .await?;
+
+    Ok(library)
+}
+
+/// Removes a library with its books and scan cache rows. Books cascade to
+/// their files, chapters and progress.
+pub async fn delete_library(db: &Pool<Sqlite>, id: i64) -> Result<(), ApiError> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query("DELETE FROM file_scan_cache WHERE library_id = ?1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM libraries WHERE id = ?1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn list_library_access(
+    db: &Pool<Sqlite>,
+    library_id: i64,
+) -> Result<Vec<LibraryMember>, ApiError> {
+    let members = sqlx::query_as::<_, LibraryMember>(
+        r#"
+        SELECT u.id AS user_id, u.username
+        FROM library_access la
+        JOIN users u ON u.id = la.user_id
+        WHERE la.library_id = ?1
+        ORDER BY u.username
+        "#,
+    )
+    .bind(library_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(members)
+}
+
+/// Replaces the set of users granted access to `library_id`
+pub async fn set_library_access(
+    db: &Pool<Sqlite>,
+    library_id: i64,
+    user_ids: &[i64],
+) -> Result<(), ApiError> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query("DELETE FROM library_access WHERE library_id = ?1")
+        .bind(library_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for user_id in user_ids {
+        sqlx::query(
+            r#"
+            INSERT INTO library_access (user_id, library_id)
+            SELECT id, ?2 FROM users WHERE id = ?1
+            "#,
+        )
+        .bind(user_id)
+        .bind(library_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Unknown library ids are skipped
+pub async fn grant_library_access(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    library_ids: &[i64],
+) -> Result<(), ApiError> {
+    for library_id in library_ids {
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO library_access (user_id, library_id)
+            SELECT ?1, id FROM libraries WHERE id = ?2
+            "#,
+        )
+        .bind(user_id)
+        .bind(library_id)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// The ids in `library_ids` that name no library
+pub async fn unknown_library_ids(
+    db: &Pool<Sqlite>,
+    library_ids: &[i64],
+) -> Result<Vec<i64>, ApiError> {
+    let mut unknown = Vec::new();
+    for &library_id in library_ids {
+        if get_library(db, library_id).await?.is_none() {
+            unknown.push(library_id);
+        }
+    }
+
+    Ok(unknown)
+}
+
+pub async fn accessible_library_ids(db: &Pool<Sqlite>, user_id: i64) -> Result<Vec<i64>, ApiError> {
+    let ids: Vec<i64> =
+        sqlx::query_scalar("SELECT library_id FROM library_access WHERE user_id = ?1")
+            .bind(user_id)
+            .fetch_all(db)
+            .await?;
+
+    Ok(ids)
+}
+
+pub async fn can_access_library(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    library_id: i64,
+) -> Result<bool, ApiError> {
+    let found: Option<i64> =
+        sqlx::query_scalar("SELECT 1 FROM library_access WHERE user_id = ?1 AND library_id = ?2")
+            .bind(user_id)
+            .bind(library_id)
+            .fetch_optional(db)
+            .await?;
+
+    Ok(found.is_some())
+}
+
+pub async fn can_access_book(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    book_id: i64,
+) -> Result<bool, ApiError> {
+    let found: Option<i64> = sqlx::query_scalar(
+        r#"
+        SELECT 1
+        FROM audiobooks ab
+        JOIN library_access la ON la.library_id = ab.library_id
+        WHERE ab.id = ?1 AND la.user_id = ?2
+        "#,
+    )
+    .bind(book_id)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(found.is_some())
+}
+
+pub async fn can_access_file(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    file_id: i64,
+) -> Result<bool, ApiError> {
+    let found: Option<i64> = sqlx::query_scalar(
+        r#"
+        SELECT 1
+        FROM files f
+        JOIN audiobooks ab ON ab.id = f.book_id
+        JOIN library_access la ON la.library_id = ab.library_id
+        WHERE f.id = ?1 AND la.user_id = ?2
+        "#,
+    )
+    .bind(file_id)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(found.is_some())
+}
+
+/// Flags rows of `library_id` that are not under `root` as missing, used
+/// when a library is pointed at a new folder
+pub async fn mark_outside_root_missing(
+    db: &Pool<Sqlite>,
+    library_id: i64,
+    root: &str,
+) -> Result<(), ApiError> {
+    let prefix = format!("{}{}", root, std::path::MAIN_SEPARATOR);
+    sqlx::query(
+        r#"
+        UPDATE file_scan_cache
+        SET missing = TRUE, updated_at = CURRENT_TIMESTAMP
+        WHERE library_id = ?1 AND substr(file_path, 1, length(?2)) != ?2
+        "#,
+    )
+    .bind(library_id)
+    .bind(prefix)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
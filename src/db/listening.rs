@@ -0,0 +1,271 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::{Pool, QueryBuilder, Sqlite, SqliteConnection};
+
+use crate::{
+    api::api_error::ApiError,
+    db::sync::PROGRESS_TIME_FORMAT,
+    models::listening::{
+        AuthorTotal, BookTotal, DayTotal, LISTENING_IDLE_MINUTES, ListeningHistoryQuery,
+        ListeningPoint, ListeningSession,
+    },
+};
+
+const SESSION_COLUMNS: &str = "id, book_id, device_id, start_file_id, start_offset_ms, \
+     end_file_id, end_offset_ms, playback_rate, started_at, last_active_at, closed_at, duration_ms";
+
+fn db_time(at: DateTime<Utc>) -> String {
+    at.format(PROGRESS_TIME_FORMAT).to_string()
+}
+
+/// Closes the device's open sessions, all of them or only those on other
+/// books and those idle for longer than the gap
+async fn close_stale_sessions(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    point: &ListeningPoint<'_>,
+    all: bool,
+) -> sqlx::Result<()> {
+    let cutoff = point.at - Duration::minutes(LISTENING_IDLE_MINUTES);
+    sqlx::query(
+        r#"
+        UPDATE listening_sessions
+        SET closed_at = last_active_at
+        WHERE user_id = ?1 AND device_id IS ?2 AND closed_at IS NULL
+          AND (?3 OR book_id != ?4 OR last_active_at < ?5)
+        "#,
+    )
+    .bind(user_id)
+    .bind(point.device_id)
+    .bind(all)
+    .bind(point.book_id)
+    .bind(db_time(cutoff))
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+async fn open_session(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    point: &ListeningPoint<'_>,
+) -> sqlx::Result<ListeningSession> {
+    sqlx::query_as(&format!(
+        r#"
+        INSERT INTO listening_sessions (
+            user_id, book_id, device_id, start_file_id, start_offset_ms,
+            end_file_id, end_offset_ms, playback_rate, started_at, last_active_at
+        )
+        VALUES (?1, ?2, ?3, ?4, ?5, ?4, ?5, ?6, ?7, ?7)
+        RETURNING {}
+        "#,
+        SESSION_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(point.book_id)
+    .bind(point.device_id)
+    .bind(point.file_id)
+    .bind(point.offset_ms)
+    .bind(point.playback_rate.unwrap_or(1.0))
+    .bind(db_time(point.at))
+    .fetch_one(conn)
+    .await
+}
+
+/// Moves the end of an open session to `point`, closing it when `close`
+async fn extend_session(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    session_id: Option<i64>,
+    point: &ListeningPoint<'_>,
+    close: bool,
+) -> sqlx::Result<Option<ListeningSession>> {
+    sqlx::query_as(&format!(
+        r#"
+        UPDATE listening_sessions
+        SET end_file_id = ?1,
+            end_offset_ms = ?2,
+            playback_rate = COALESCE(?3, playback_rate),
+            last_active_at = MAX(last_active_at, ?4),
+            duration_ms = MAX(0, CAST(ROUND(
+                (julianday(MAX(last_active_at, ?4)) - julianday(started_at)) * 86400000
+            ) AS INTEGER)),
+            closed_at = CASE WHEN ?5 THEN MAX(last_active_at, ?4) END
+        WHERE user_id = ?6 AND closed_at IS NULL
+          AND ((?7 IS NULL AND book_id = ?8 AND device_id IS ?9) OR id = ?7)
+        RETURNING {}
+        "#,
+        SESSION_COLUMNS
+    ))
+    .bind(point.file_id)
+    .bind(point.offset_ms)
+    .bind(point.playback_rate)
+    .bind(db_time(point.at))
+    .bind(close)
+    .bind(user_id)
+    .bind(session_id)
+    .bind(point.book_id)
+    .bind(point.device_id)
+    .fetch_optional(conn)
+    .await
+}
+
+/// Folds an applied progress update into the device's open session, or
+/// opens one when the device was idle or on another book
+pub async fn record_listening(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    point: &ListeningPoint<'_>,
+) -> sqlx::Result<ListeningSession> {
+    close_stale_sessions(conn, user_id, point, false).await?;
+    if let Some(session) = extend_session(conn, user_id, None, point, false).await? {
+        return Ok(session);
+    }
+    open_session(conn, user_id, point).await
+}
+
+pub async fn start_listening(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    point: &ListeningPoint<'_>,
+) -> sqlx::Result<ListeningSession> {
+    close_stale_sessions(conn, user_id, point, true).await?;
+    open_session(conn, user_id, point).await
+}
+
+/// None when the session is not the user's or already closed
+pub async fn stop_listening(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    session_id: i64,
+    point: &ListeningPoint<'_>,
+) -> sqlx::Result<Option<ListeningSession>> {
+    extend_session(conn, user_id, Some(session_id), point, true).await
+}
+
+pub async fn get_listening_session(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    session_id: i64,
+) -> Result<Option<ListeningSession>, ApiError> {
+    let session = sqlx::query_as(&format!(
+        "SELECT {} FROM listening_sessions WHERE id = ? AND user_id = ?",
+        SESSION_COLUMNS
+    ))
+    .bind(session_id)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+    Ok(session)
+}
+
+pub async fn list_listening_sessions(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    query: &ListeningHistoryQuery,
+    limit: i64,
+) -> Result<Vec<ListeningSession>, ApiError> {
+    let mut qb: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+        "SELECT {} FROM listening_sessions WHERE user_id = ",
+        SESSION_COLUMNS
+    ));
+    qb.push_bind(user_id);
+    if let Some(book_id) = query.book_id {
+        qb.push(" AND book_id = ").push_bind(book_id);
+    }
+    if let Some(before_id) = query.before_id {
+        qb.push(" AND id < ").push_bind(before_id);
+    }
+    qb.push(" ORDER BY id DESC LIMIT ").push_bind(limit);
+
+    let sessions = qb.build_query_as().fetch_all(db).await?;
+    Ok(sessions)
+}
+
+// Sessions are counted on the local day they started. `shift` is an SQLite
+// date modifier such as '+120 minutes'.
+const LOCAL_DAY: &str = "date(ls.started_at, ?2)";
+
+/// Totals per local day between `from` and `to` inclusive, or for every day
+/// with listening when no range is given
+pub async fn daily_totals(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    shift: &str,
+    range: Option<(NaiveDate, NaiveDate)>,
+) -> Result<Vec<DayTotal>, ApiError> {
+    let (from, to) = range.unzip();
+    let days = sqlx::query_as(&format!(
+        r#"
+        SELECT {day} AS day, SUM(ls.duration_ms) AS listened_ms, COUNT(*) AS sessions
+        FROM listening_sessions ls
+        WHERE ls.user_id = ?1
+          AND (?3 IS NULL OR {day} >= ?3) AND (?4 IS NULL OR {day} <= ?4)
+        GROUP BY day
+        ORDER BY day
+        "#,
+        day = LOCAL_DAY
+    ))
+    .bind(user_id)
+    .bind(shift)
+    .bind(from)
+    .bind(to)
+    .fetch_all(db)
+    .await?;
+    Ok(days)
+}
+
+pub async fn book_totals(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    shift: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<BookTotal>, ApiError> {
+    let books = sqlx::query_as(&format!(
+        r#"
+        SELECT ab.id AS book_id, ab.title, ab.author,
+               SUM(ls.duration_ms) AS listened_ms, COUNT(*) AS sessions
+        FROM listening_sessions ls
+        JOIN audiobooks ab ON ab.id = ls.book_id
+        WHERE ls.user_id = ?1 AND {day} >= ?3 AND {day} <= ?4
+        GROUP BY ab.id
+        ORDER BY listened_ms DESC, ab.id
+        "#,
+        day = LOCAL_DAY
+    ))
+    .bind(user_id)
+    .bind(shift)
+    .bind(from)
+    .bind(to)
+    .fetch_all(db)
+    .await?;
+    Ok(books)
+}
+
+pub async fn author_totals(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    shift: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<AuthorTotal>, ApiError> {
+    let authors = sqlx::query_as(&format!(
+        r#"
+        SELECT ab.author, SUM(ls.duration_ms) AS listened_ms,
+               COUNT(DISTINCT ab.id) AS books
+        FROM listening_sessions ls
+        JOIN audiobooks ab ON ab.id = ls.book_id
+        WHERE ls.user_id = ?1 AND {day} >= ?3 AND {day} <= ?4
+        GROUP BY ab.author
+        ORDER BY listened_ms DESC, ab.author
+        "#,
+        day = LOCAL_DAY
+    ))
+    .bind(user_id)
+    .bind(shift)
+    .bind(from)
+    .bind(to)
+    .fetch_all(db)
+    .await?;
+    Ok(authors)
+}
@@ -0,0 +1,159 @@
+use sqlx::{Pool, Sqlite};
+
+use crate::{
+    api::api_error::ApiError,
+    models::login_attempts::{AttemptScope, LoginAttempt},
+};
+
+/// Marks a login as in flight before its password is checked and returns
+/// the row including it. Reservations older than `stale` seconds belong to
+/// requests that never finished and are dropped.
+pub async fn reserve_login_attempt(
+    db: &Pool<Sqlite>,
+    scope: AttemptScope,
+    key: &str,
+    now: i64,
+    stale: i64,
+) -> Result<LoginAttempt, ApiError> {
+    let attempt = sqlx::query_as::<_, LoginAttempt>(
+        r#"
+        INSERT INTO login_attempts (scope, key, failures, last_failure_at, pending, pending_at)
+        VALUES (?1, ?2, 0, 0, 1, ?3)
+        ON CONFLICT(scope, key) DO UPDATE SET
+            pending = CASE
+                WHEN login_attempts.pending_at < ?3 - ?4 THEN 1
+                ELSE login_attempts.pending + 1
+            END,
+            pending_at = ?3
+        RETURNING scope, key, failures, last_failure_at, locked_until, pending
+        "#,
+    )
+    .bind(scope)
+    .bind(key)
+    .bind(now)
+    .bind(stale)
+    .fetch_one(db)
+    .await?;
+
+    Ok(attempt)
+}
+
+/// Ends a reservation that did not fail, forgetting rows with nothing left
+/// to count
+pub async fn release_login_attempt(
+    db: &Pool<Sqlite>,
+    scope: AttemptScope,
+    key: &str,
+) -> Result<(), ApiError> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query(
+        "UPDATE login_attempts SET pending = MAX(pending - 1, 0) WHERE scope = ?1 AND key = ?2",
+    )
+    .bind(scope)
+    .bind(key)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "DELETE FROM login_attempts \
+         WHERE scope = ?1 AND key = ?2 AND failures = 0 AND pending = 0 AND locked_until IS NULL",
+    )
+    .bind(scope)
+    .bind(key)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Counts a failure, starting over when the previous one is older than
+/// `window` seconds, and ends its reservation. Returns the updated row.
+pub async fn record_login_failure(
+    db: &Pool<Sqlite>,
+    scope: AttemptScope,
+    key: &str,
+    now: i64,
+    window: i64,
+) -> Result<LoginAttempt, ApiError> {
+    let attempt = sqlx::query_as::<_, LoginAttempt>(
+        r#"
+        INSERT INTO login_attempts (scope, key, failures, last_failure_at)
+        VALUES (?1, ?2, 1, ?3)
+        ON CONFLICT(scope, key) DO UPDATE SET
+            failures = CASE
+                WHEN login_attempts.last_failure_at < ?3 - ?4
+                     AND COALESCE(login_attempts.locked_until, 0) <= ?3 THEN 1
+                ELSE login_attempts.failures + 1
+            END,
+            locked_until = CASE
+                WHEN COALESCE(login_attempts.locked_until, 0) > ?3 THEN login_attempts.locked_until
+                ELSE NULL
+            END,
+            last_failure_at = ?3,
+            pending = MAX(login_attempts.pending - 1, 0)
+        RETURNING scope, key, failures, last_failure_at, locked_until, pending
+        "#,
+    )
+    .bind(scope)
+    .bind(key)
+    .bind(now)
+    .bind(window)
+    .fetch_one(db)
+    .await?;
+
+    Ok(attempt)
+}
+
+pub async fn lock_login(
+    db: &Pool<Sqlite>,
+    scope: AttemptScope,
+    key: &str,
+    until: i64,
+) -> Result<(), ApiError> {
+    sqlx::query("UPDATE login_attempts SET locked_until = ?3 WHERE scope = ?1 AND key = ?2")
+        .bind(scope)
+        .bind(key)
+        .bind(until)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn clear_login_attempts(
+    db: &Pool<Sqlite>,
+    scope: AttemptScope,
+    key: &str,
+) -> Result<bool, ApiError> {
+    let result = sqlx::query("DELETE FROM login_attempts WHERE scope = ?1 AND key = ?2")
+        .bind(scope)
+        .bind(key)
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Rows still holding back logins at `now`: locked, or failed within `window`
+pub async fn list_login_attempts(
+    db: &Pool<Sqlite>,
+    now: i64,
+    window: i64,
+) -> Result<Vec<LoginAttempt>, ApiError> {
+    let attempts = sqlx::query_as::<_, LoginAttempt>(
+        r#"
+        SELECT scope, key, failures, last_failure_at, locked_until, pending
+        FROM login_attempts
+        WHERE locked_until > ?1 OR last_failure_at >= ?1 - ?2
+        ORDER BY last_failure_at DESC
+        "#,
+    )
+    .bind(now)
+    .bind(window)
+    .fetch_all(db)
+    .await?;
+
+    Ok(attempts)
+}
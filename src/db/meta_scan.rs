@@ -1,6 +1,8 @@
 use crate::{
     api::api_error::ApiError,
-    models::meta_scan::{ChangeDto, ChangeType, FileInfo, FileScanCache},
+    models::meta_scan::{
+        ChangeDto, ChangeType, FileInfo, FileScanCache, OrganizedFile, ScanIndexRow,
+    },
 };
 use sqlx::{Pool, QueryBuilder, Sqlite, SqlitePool};
 use std::collections::HashMap;
@@ -27,10 +29,12 @@ pub async fn save_meta(db: &Pool<Sqlite>, metadata: FileScanCache) -> Result<(),
                 author, title, clean_title, file_path, file_name, path_parent, series, clean_series, series_part, 
                 cover_art, pub_year, narrated_by, duration, track_number, 
                 disc_number, file_size, mime_type, channels, sample_rate, 
-                bitrate, dramatized, extracts,  raw_metadata, resolve_status, hash
+                bitrate, dramatized, extracts,  raw_metadata, resolve_status, hash, chapters, mtime,
+                library_id
             ) VALUES (
                 $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, 
-                $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25
+                $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27,
+                $28
             )
             ON CONFLICT(file_path) DO UPDATE SET
                 author = excluded.author,
@@ -57,6 +61,10 @@ pub async fn save_meta(db: &Pool<Sqlite>, metadata: FileScanCache) -> Result<(),
                 raw_metadata = excluded.raw_metadata,
                 resolve_status = excluded.resolve_status,
                 hash = excluded.hash,
+                chapters = excluded.chapters,
+                mtime = excluded.mtime,
+                library_id = excluded.library_id,
+                missing = FALSE,
                 updated_at = CURRENT_TIMESTAMP
             "#,
         metadata.author,
@@ -83,7 +91,10 @@ pub async fn save_meta(db: &Pool<Sqlite>, metadata: FileScanCache) -> Result<(),
         metadata.extracts,
         rawmet, //metadata.raw_metadata,
         resolve_status,
-        metadata.hash
+        metadata.hash,
+        metadata.chapters,
+        metadata.mtime,
+        metadata.library_id
     )
     .execute(db)
     .await;
@@ -96,18 +107,123 @@ pub async fn save_meta(db: &Pool<Sqlite>, metadata: FileScanCache) -> Result<(),
     Ok(())
 }
 
-pub async fn group_title_cleanup_multipart(db: &Pool<Sqlite>) -> Result<(), ApiError> {
-    let rows = sqlx::query!(
+// Cache rows living under `root`, used to skip unchanged files on rescan
+pub async fn get_scan_index(db: &Pool<Sqlite>, root: &str) -> Result<Vec<ScanIndexRow>, ApiError> {
+    let rows = sqlx::query_as::<_, ScanIndexRow>(
         r#"
-        SELECT series as og_series, clean_series as series, author, title, id, file_path, path_parent
+        SELECT id, file_path, file_size, mtime, missing
         FROM file_scan_cache
-        ORDER BY series, author, title
-        "#
+        WHERE substr(file_path, 1, length(?1)) = ?1
+        "#,
     )
+    .bind(root)
     .fetch_all(db)
     .await?;
 
-    for row in rows {}
+    Ok(rows)
+}
+
+pub async fn find_by_hash(
+    db: &Pool<Sqlite>,
+    hash: &str,
+    file_size: i64,
+    library_id: i64,
+) -> Result<Vec<ScanIndexRow>, ApiError> {
+    let rows = sqlx::query_as::<_, ScanIndexRow>(
+        r#"
+        SELECT id, file_path, file_size, mtime, missing
+        FROM file_scan_cache
+        WHERE hash = ?1 AND file_size = ?2 AND library_id = ?3
+        "#,
+    )
+    .bind(hash)
+    .bind(file_size)
+    .bind(library_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows)
+}
+
+pub async fn get_organized_files(
+    db: &Pool<Sqlite>,
+    ids: &[i64],
+) -> Result<Vec<OrganizedFile>, ApiError> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut qb: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "SELECT id, COALESCE(library_id, 1) AS library_id, file_path, author, clean_series, title \
+         FROM file_scan_cache WHERE id IN (",
+    );
+    let mut separated = qb.separated(", ");
+    for id in ids {
+        separated.push_bind(id);
+    }
+    separated.push_unseparated(") ORDER BY id");
+
+    let rows = qb.build_query_as::<OrganizedFile>().fetch_all(db).await?;
+    Ok(rows)
+}
+
+// Point an existing cache row (and its files row) at the file's new location
+// so ids, and with them progress rows, survive a rename or move
+pub async fn move_scan_entry(
+    db: &Pool<Sqlite>,
+    id: i64,
+    old_path: &str,
+    metadata: &FileScanCache,
+) -> Result<(), ApiError> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query(
+        r#"
+        UPDATE file_scan_cache
+        SET file_path = ?1, file_name = ?2, path_parent = ?3, mtime = ?4,
+            missing = FALSE, updated_at = CURRENT_TIMESTAMP
+        WHERE id = ?5
+        "#,
+    )
+    .bind(&metadata.file_path)
+    .bind(&metadata.file_name)
+    .bind(&metadata.path_parent)
+    .bind(metadata.mtime)
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE files
+        SET file_path = ?1, file_name = ?2
+        WHERE file_path = ?3
+        "#,
+    )
+    .bind(&metadata.file_path)
+    .bind(&metadata.file_name)
+    .bind(old_path)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn mark_missing(db: &Pool<Sqlite>, ids: &[i64]) -> Result<(), ApiError> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let mut qb: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "UPDATE file_scan_cache SET missing = TRUE, updated_at = CURRENT_TIMESTAMP WHERE id IN (",
+    );
+    let mut separated = qb.separated(", ");
+    for id in ids {
+        separated.push_bind(id);
+    }
+    separated.push_unseparated(")");
+    qb.build().execute(db).await?;
 
     Ok(())
 }
@@ -129,6 +245,7 @@ pub async fn get_grouped_files(
                         path_parent,
                         file_path
                     FROM file_scan_cache
+                    WHERE missing = 0
                 ),
                 grouped AS (
                     SELECT author, clean_series, COUNT(*) AS cnt
@@ -154,29 +271,25 @@ pub async fn get_grouped_files(
     for row in rows {
         let series = row.series.unwrap_or_else(|| "unknown".to_string());
         let author = row.author.unwrap_or_else(|| "unknown".to_string());
-        let id = row.id.unwrap_or_else(|| -1); // or some default ID
+        let id = row.id.unwrap_or(-1); // or some default ID
         let file_name = row.file_name;
         let title = row.title.unwrap_or_else(|| "unknown".to_string());
         let file_path = row.file_path;
         let path_parent = row.path_parent;
         let clean_series = row.clean_series.unwrap_or_else(|| "unknown".to_string());
 
-        let author_entry = result
-            .entry(author.trim().to_lowercase())
-            .or_insert_with(|| HashMap::new());
+        let author_entry = result.entry(author.trim().to_lowercase()).or_default();
 
         let file_info = FileInfo {
-            id: id,
-            file_name: file_name,
-            series: series,
-            title: title,
-            path_parent: path_parent,
-            file_path: file_path,
+            id,
+            file_name,
+            series,
+            title,
+            path_parent,
+            file_path,
         };
 
-        let book_entry = author_entry
-            .entry(clean_series)
-            .or_insert_with(|| Vec::new());
+        let book_entry = author_entry.entry(clean_series).or_default();
         book_entry.push(file_info);
     }
 
@@ -291,8 +404,9 @@ pub async fn apply_dbchanges(
 pub async fn propagate_changes(pool: &SqlitePool) -> Result<(), ApiError> {
     sqlx::query(
         r#"
-        INSERT INTO audiobooks (author, series, title, files_location, cover_art, metadata, duration, created_at, updated_at)
+        INSERT INTO audiobooks (library_id, author, series, title, files_location, cover_art, metadata, duration, created_at, updated_at)
         SELECT
+            fsc.library_id,
             fsc.author,
             fsc.clean_series,
             fsc.clean_series,
@@ -303,18 +417,18 @@ pub async fn propagate_changes(pool: &SqlitePool) -> Result<(), ApiError> {
             CURRENT_TIMESTAMP,
             CURRENT_TIMESTAMP
         FROM file_scan_cache fsc
-        WHERE fsc.resolve_status = 0 and fsc.author IS NOT NULL and fsc.clean_series is not null
-        ON CONFLICT(author, title) DO UPDATE SET
+        WHERE fsc.resolve_status = 0 and fsc.missing = 0 and fsc.author IS NOT NULL and fsc.clean_series is not null
+        ON CONFLICT(library_id, author, title) DO UPDATE SET
             series = excluded.series,
             files_location = excluded.files_location,
-            cover_art = excluded.cover_art,
+            cover_art = COALESCE(excluded.cover_art, audiobooks.cover_art),
             metadata = excluded.metadata,
             duration = excluded.duration,
             updated_at = CURRENT_TIMESTAMP
         "#
     )
     .execute(pool)
-    .await.unwrap();
+    .await?;
 
     // 2️⃣ Upsert files
     sqlx::query(
@@ -331,10 +445,12 @@ pub async fn propagate_changes(pool: &SqlitePool) -> Result<(), ApiError> {
             fsc.bitrate
         FROM
             file_scan_cache fsc
-            JOIN audiobooks ab ON ab.author = fsc.author
+            JOIN audiobooks ab ON ab.library_id = fsc.library_id
+            AND ab.author = fsc.author
             AND ab.title = fsc.clean_series
         WHERE
             fsc.resolve_status = 0
+            AND fsc.missing = 0
         ON CONFLICT(book_id, file_id, file_path) DO UPDATE SET
             file_name = excluded.file_name,
             file_path = excluded.file_path,
@@ -345,7 +461,48 @@ pub async fn propagate_changes(pool: &SqlitePool) -> Result<(), ApiError> {
         "#
     )
     .execute(pool)
-    .await.unwrap();
+    .await?;
+
+    // 3️⃣ Replace chapters of the files we just upserted
+    sqlx::query(
+        r#"
+        DELETE FROM chapters
+        WHERE file_id IN (
+            SELECT f.id
+            FROM files f
+            JOIN file_scan_cache fsc ON fsc.file_path = f.file_path
+            WHERE fsc.resolve_status = 0
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO chapters (file_id, chapter_index, title, start_ms, end_ms)
+        SELECT
+            f.id,
+            ch.key,
+            json_extract(ch.value, '$.title'),
+            json_extract(ch.value, '$.start_ms'),
+            json_extract(ch.value, '$.end_ms')
+        FROM
+            file_scan_cache fsc
+            JOIN files f ON f.file_path = fsc.file_path,
+            json_each(fsc.chapters) ch
+        WHERE
+            fsc.resolve_status = 0
+            AND fsc.chapters IS NOT NULL
+        ON CONFLICT(file_id, chapter_index) DO UPDATE SET
+            title = excluded.title,
+            start_ms = excluded.start_ms,
+            end_ms = excluded.end_ms
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
     Ok(())
 }
 // pub async fn get_changes(
@@ -1,8 +1,19 @@
+pub mod api_keys;
 pub mod audiobooks;
+pub mod audit;
+pub mod invites;
+pub mod libraries;
+pub mod listening;
+pub mod login_attempts;
 pub mod meta_scan;
+pub mod oidc;
+pub mod permissions;
+pub mod search;
+pub mod sessions;
 pub mod sync;
 pub mod user;
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{ConnectOptions, Connection};
 use sqlx::{Error as SqlxError, SqlitePool};
 use std::str::FromStr;
 
@@ -17,17 +28,26 @@ pub async fn init_db_pool(db_url: &str) -> Result<DbPool, SqlxError> {
         .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal)
         .synchronous(sqlx::sqlite::SqliteSynchronous::Normal);
 
+    // Run migrations on their own connection with foreign keys off, so a
+    // table rebuild does not cascade deletes into the tables pointing at it
+    let mut conn = connection_options
+        .clone()
+        .foreign_keys(false)
+        .connect()
+        .await?;
+    sqlx::migrate!().run(&mut conn).await?;
+    conn.close().await?;
+
     let pool = SqlitePoolOptions::new()
         .max_connections(5)
         .connect_with(connection_options)
         .await?;
 
-    // Run migrations
-    sqlx::migrate!().run(&pool).await?;
-
     Ok(pool)
 }
 
+// Dev helper: wipes books/files and rebuilds them with the legacy folder scanner
+#[allow(dead_code)]
 pub async fn cleanup(db: &SqlitePool) -> Result<(), ApiError> {
     let _ = sqlx::query!(
         r#"
@@ -0,0 +1,105 @@
+use sqlx::{Pool, Sqlite, SqliteExecutor};
+
+use crate::{api::api_error::ApiError, models::oidc::PendingLogin};
+
+// The user has this long to get through the provider's login page
+const PENDING_LOGIN_TTL: &str = "-10 minutes";
+
+pub async fn create_pending_login(
+    db: &Pool<Sqlite>,
+    state: &str,
+    nonce: &str,
+    code_verifier: &str,
+    device_name: Option<&str>,
+) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        INSERT INTO oidc_logins (state, nonce, code_verifier, device_name)
+        VALUES (?1, ?2, ?3, ?4)
+        "#,
+    )
+    .bind(state)
+    .bind(nonce)
+    .bind(code_verifier)
+    .bind(device_name)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Removes and returns a pending login, so each `state` is good for one callback
+pub async fn take_pending_login(
+    db: &Pool<Sqlite>,
+    state: &str,
+) -> Result<Option<PendingLogin>, ApiError> {
+    let login = sqlx::query_as::<_, PendingLogin>(
+        r#"
+        DELETE FROM oidc_logins
+        WHERE state = ?1 AND created_at > datetime('now', ?2)
+        RETURNING nonce, code_verifier, device_name
+        "#,
+    )
+    .bind(state)
+    .bind(PENDING_LOGIN_TTL)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(login)
+}
+
+pub async fn prune_pending_logins(db: &Pool<Sqlite>) -> Result<(), ApiError> {
+    sqlx::query("DELETE FROM oidc_logins WHERE created_at <= datetime('now', ?1)")
+        .bind(PENDING_LOGIN_TTL)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Local user linked to the provider subject, bumping its last login
+pub async fn find_identity_user(
+    db: &Pool<Sqlite>,
+    issuer: &str,
+    subject: &str,
+    email: Option<&str>,
+) -> Result<Option<i64>, ApiError> {
+    let user_id: Option<i64> = sqlx::query_scalar(
+        r#"
+        UPDATE oidc_identities
+        SET last_login_at = CURRENT_TIMESTAMP, email = ?3
+        WHERE issuer = ?1 AND subject = ?2
+        RETURNING user_id
+        "#,
+    )
+    .bind(issuer)
+    .bind(subject)
+    .bind(email)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(user_id)
+}
+
+pub async fn link_identity(
+    db: impl SqliteExecutor<'_>,
+    issuer: &str,
+    subject: &str,
+    user_id: i64,
+    email: Option<&str>,
+) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        INSERT INTO oidc_identities (issuer, subject, user_id, email)
+        VALUES (?1, ?2, ?3, ?4)
+        "#,
+    )
+    .bind(issuer)
+    .bind(subject)
+    .bind(user_id)
+    .bind(email)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
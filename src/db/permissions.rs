@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+
+use sqlx::{Pool, Sqlite, SqliteConnection};
+
+use crate::{
+    api::api_error::ApiError,
+    models::permissions::{Group, GroupRow, Permission},
+};
+
+const GROUP_COLUMNS: &str = "id, name, CAST(created_at AS TEXT) AS created_at";
+
+/// Permissions granted to the user directly or through any of their groups
+pub async fn effective_permissions(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+) -> Result<Vec<Permission>, ApiError> {
+    let permissions: Vec<Permission> = sqlx::query_scalar(
+        r#"
+        SELECT permission FROM user_permissions WHERE user_id = ?1
+        UNION
+        SELECT gp.permission
+        FROM group_permissions gp
+        JOIN group_members gm ON gm.group_id = gp.group_id
+        WHERE gm.user_id = ?1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(permissions)
+}
+
+pub async fn direct_permissions(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+) -> Result<Vec<Permission>, ApiError> {
+    let permissions: Vec<Permission> = sqlx::query_scalar(
+        "SELECT permission FROM user_permissions WHERE user_id = ?1 ORDER BY permission",
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(permissions)
+}
+
+pub async fn user_group_ids(db: &Pool<Sqlite>, user_id: i64) -> Result<Vec<i64>, ApiError> {
+    let ids: Vec<i64> = sqlx::query_scalar(
+        "SELECT group_id FROM group_members WHERE user_id = ?1 ORDER BY group_id",
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(ids)
+}
+
+/// Replaces the permissions granted to the user directly
+pub async fn set_user_permissions(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    permissions: &[Permission],
+) -> Result<(), ApiError> {
+    let mut tx = db.begin().await?;
+    replace_user_permissions(&mut tx, user_id, permissions).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn replace_user_permissions(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    permissions: &[Permission],
+) -> Result<(), ApiError> {
+    sqlx::query("DELETE FROM user_permissions WHERE user_id = ?1")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    for permission in permissions {
+        sqlx::query("INSERT OR IGNORE INTO user_permissions (user_id, permission) VALUES (?1, ?2)")
+            .bind(user_id)
+            .bind(permission)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Unknown group ids are skipped
+pub async fn add_user_to_groups(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    group_ids: &[i64],
+) -> Result<(), ApiError> {
+    for group_id in group_ids {
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO group_members (group_id, user_id)
+            SELECT id, ?2 FROM groups WHERE id = ?1
+            "#,
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// The ids in `group_ids` that name no group
+pub async fn unknown_group_ids(db: &Pool<Sqlite>, group_ids: &[i64]) -> Result<Vec<i64>, ApiError> {
+    let mut unknown = Vec::new();
+    for &group_id in group_ids {
+        let found: Option<i64> = sqlx::query_scalar("SELECT id FROM groups WHERE id = ?1")
+            .bind(group_id)
+            .fetch_optional(db)
+            .await?;
+        if found.is_none() {
+            unknown.push(group_id);
+        }
+    }
+
+    Ok(unknown)
+}
+
+// Groups with their permissions and members, assembled from three queries
+// rather than one row per permission and member
+async fn load_groups(db: &Pool<Sqlite>, rows: Vec<GroupRow>) -> Result<Vec<Group>, ApiError> {
+    let permissions: Vec<(i64, Permission)> =
+        sqlx::query_as("SELECT group_id, permission FROM group_permissions ORDER BY permission")
+            .fetch_all(db)
+            .await?;
+    let members: Vec<(i64, i64)> =
+        sqlx::query_as("SELECT group_id, user_id FROM group_members ORDER BY user_id")
+            .fetch_all(db)
+            .await?;
+
+    let mut by_group: HashMap<i64, (Vec<Permission>, Vec<i64>)> = HashMap::new();
+    for (group_id, permission) in permissions {
+        by_group.entry(group_id).or_default().0.push(permission);
+    }
+    for (group_id, user_id) in members {
+        by_group.entry(group_id).or_default().1.push(user_id);
+    }
+
+    Ok(rows
+        .into_iter()
+        .map(|group| {
+            let (permissions, user_ids) = by_group.remove(&group.id).unwrap_or_default();
+            Group {
+                group,
+                permissions,
+                user_ids,
+            }
+        })
+        .collect())
+}
+
+pub async fn list_groups(db: &Pool<Sqlite>) -> Result<Vec<Group>, ApiError> {
+    let rows = sqlx::query_as::<_, GroupRow>(&format!(
+        "SELECT {} FROM groups ORDER BY name",
+        GROUP_COLUMNS
+    ))
+    .fetch_all(db)
+    .await?;
+
+    load_groups(db, rows).await
+}
+
+pub async fn get_group(db: &Pool<Sqlite>, id: i64) -> Result<Option<Group>, ApiError> {
+    let row = sqlx::query_as::<_, GroupRow>(&format!(
+        "SELECT {} FROM groups WHERE id = ?1",
+        GROUP_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    match row {
+        Some(row) => Ok(load_groups(db, vec![row]).await?.pop()),
+        None => Ok(None),
+    }
+}
+
+async fn replace_group_permissions(
+    conn: &mut SqliteConnection,
+    group_id: i64,
+    permissions: &[Permission],
+) -> Result<(), ApiError> {
+    sqlx::query("DELETE FROM group_permissions WHERE group_id = ?1")
+        .bind(group_id)
+        .execute(&mut *conn)
+        .await?;
+
+    for permission in permissions {
+        sqlx::query(
+            "INSERT OR IGNORE INTO group_permissions (group_id, permission) VALUES (?1, ?2)",
+        )
+        .bind(group_id)
+        .bind(permission)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+pub async fn create_group(
+    db: &Pool<Sqlite>,
+    name: &str,
+    permissions: &[Permission],
+) -> Result<i64, ApiError> {
+    let mut tx = db.begin().await?;
+
+    let id: i64 = sqlx::query_scalar("INSERT INTO groups (name) VALUES (?1) RETURNING id")
+        .bind(name)
+        .fetch_one(&mut *tx)
+        .await?;
+    replace_group_permissions(&mut tx, id, permissions).await?;
+
+    tx.commit().await?;
+    Ok(id)
+}
+
+pub async fn update_group(
+    db: &Pool<Sqlite>,
+    id: i64,
+    name: Option<&str>,
+    permissions: Option<&[Permission]>,
+) -> Result<(), ApiError> {
+    let mut tx = db.begin().await?;
+
+    if let Some(name) = name {
+        sqlx::query("UPDATE groups SET name = ?1 WHERE id = ?2")
+            .bind(name)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    if let Some(permissions) = permissions {
+        replace_group_permissions(&mut tx, id, permissions).await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn delete_group(db: &Pool<Sqlite>, id: i64) -> Result<bool, ApiError> {
+    let result = sqlx::query("DELETE FROM groups WHERE id = ?1")
+        .bind(id)
+        .execute(db)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Replaces the members of a group, unknown user ids are skipped
+pub async fn set_group_members(
+    db: &Pool<Sqlite>,
+    group_id: i64,
+    user_ids: &[i64],
+) -> Result<(), ApiError> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query("DELETE FROM group_members WHERE group_id = ?1")
+        .bind(group_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for user_id in user_ids {
+        sqlx::query(
+            r#"
+            INSERT INTO group_members (group_id, user_id)
+            SELECT ?2, id FROM users WHERE id = ?1
+            "#,
+        )
+        .bind(user_id)
+        .bind(group_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn group_name_taken(
+    db: &Pool<Sqlite>,
+    name: &str,
+    except_id: Option<i64>,
+) -> Result<bool, ApiError> {
+    let found: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM groups WHERE name = ?1 COLLATE NOCASE AND (?2 IS NULL OR id != ?2)",
+    )
+    .bind(name)
+    .bind(except_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(found.is_some())
+}
+
+pub async fn remove_user_from_groups(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    group_ids: &[i64],
+) -> Result<(), ApiError> {
+    for group_id in group_ids {
+        sqlx::query("DELETE FROM group_members WHERE group_id = ?1 AND user_id = ?2")
+            .bind(group_id)
+            .bind(user_id)
+            .execute(db)
+            .await?;
+    }
+
+    Ok(())
+}
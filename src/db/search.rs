@@ -0,0 +1,121 @@
+use sqlx::SqlitePool;
+
+use crate::{api::api_error::ApiError, models::search::SearchRow};
+
+// Upper bound on the number of words taken from a search query
+const MAX_QUERY_TERMS: usize = 16;
+
+// One row per book, narrators and file names come from the files it owns
+const INDEX_SELECT: &str = r#"
+    INSERT INTO library_search (rowid, title, author, series, narrator, file_names)
+    SELECT
+        ab.id,
+        ab.title,
+        ab.author,
+        ab.series,
+        (
+            SELECT group_concat(DISTINCT fsc.narrated_by)
+            FROM files f
+            JOIN file_scan_cache fsc ON fsc.file_path = f.file_path
+            WHERE f.book_id = ab.id
+        ),
+        (
+            SELECT group_concat(f.file_name, ' ')
+            FROM files f
+            WHERE f.book_id = ab.id
+        )
+    FROM audiobooks ab
+"#;
+
+/// Rebuilds the whole search index from `audiobooks` and `files`
+pub async fn rebuild_search_index(db: &SqlitePool) -> Result<(), ApiError> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query("DELETE FROM library_search")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(INDEX_SELECT).execute(&mut *tx).await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Reindexes the books that own a file under `prefix`, used after a rescan
+/// of part of the library
+pub async fn reindex_books_under(db: &SqlitePool, prefix: &str) -> Result<(), ApiError> {
+    let books = r#"
+        SELECT DISTINCT book_id FROM files
+        WHERE substr(file_path, 1, length(?1)) = ?1
+    "#;
+    let mut tx = db.begin().await?;
+
+    sqlx::query(&format!(
+        "DELETE FROM library_search WHERE rowid IN ({})",
+        books
+    ))
+    .bind(prefix)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(&format!("{} WHERE ab.id IN ({})", INDEX_SELECT, books))
+        .bind(prefix)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Turns free text into an FTS5 query where every word is a prefix match.
+/// Punctuation is dropped so user input can never form FTS5 syntax.
+pub fn to_match_query(q: &str) -> Option<String> {
+    let terms: Vec<String> = q
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .take(MAX_QUERY_TERMS)
+        .map(|t| format!("\"{}\"*", t))
+        .collect();
+
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+pub async fn search_books(
+    db: &SqlitePool,
+    match_query: &str,
+    scope: Option<&[i64]>,
+    limit: i64,
+) -> Result<Vec<SearchRow>, ApiError> {
+    let scope = scope.map(|ids| serde_json::to_string(ids).unwrap_or_default());
+
+    // bm25 weights follow column order: title, author, series, narrator, file names
+    let rows = sqlx::query_as::<_, SearchRow>(
+        r#"
+        SELECT
+            ab.id,
+            ab.author,
+            ab.series,
+            ab.title,
+            ab.cover_art,
+            ab.duration,
+            highlight(library_search, 0, '<mark>', '</mark>') AS title_hl,
+            highlight(library_search, 1, '<mark>', '</mark>') AS author_hl,
+            highlight(library_search, 2, '<mark>', '</mark>') AS series_hl,
+            highlight(library_search, 3, '<mark>', '</mark>') AS narrator_hl,
+            snippet(library_search, 4, '<mark>', '</mark>', '…', 12) AS files_hl,
+            bm25(library_search, 10.0, 6.0, 4.0, 3.0, 1.0) AS score
+        FROM library_search
+        JOIN audiobooks ab ON ab.id = library_search.rowid
+        WHERE library_search MATCH ?1
+          AND (?3 IS NULL OR ab.library_id IN (SELECT value FROM json_each(?3)))
+        ORDER BY score
+        LIMIT ?2
+        "#,
+    )
+    .bind(match_query)
+    .bind(limit)
+    .bind(scope)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows)
+}
@@ -0,0 +1,206 @@
+use sqlx::{Pool, Sqlite};
+
+use crate::{
+    api::api_error::ApiError,
+    models::sessions::{Session, SessionUser},
+};
+
+const SESSION_COLUMNS: &str = "id, user_id, device_name, ip_address, \
+     CAST(created_at AS TEXT) AS created_at, CAST(last_seen_at AS TEXT) AS last_seen_at, \
+     CAST(expires_at AS TEXT) AS expires_at";
+
+// How stale last_seen_at may get before a request bumps it, keeps
+// authenticated reads from turning into writes
+const LAST_SEEN_RESOLUTION: &str = "-5 minutes";
+
+fn ttl_modifier(ttl_days: i64) -> String {
+    format!("+{} days", ttl_days)
+}
+
+pub async fn create_session(
+    db: &Pool<Sqlite>,
+    id: &str,
+    user_id: i64,
+    refresh_token_hash: &str,
+    device_name: Option<&str>,
+    ip_address: Option<&str>,
+    ttl_days: i64,
+) -> Result<Session, ApiError> {
+    let session = sqlx::query_as::<_, Session>(&format!(
+        "INSERT INTO sessions (id, user_id, refresh_token_hash, device_name, ip_address, expires_at) \
+         VALUES (?1, ?2, ?3, ?4, ?5, datetime('now', ?6)) RETURNING {}",
+        SESSION_COLUMNS
+    ))
+    .bind(id)
+    .bind(user_id)
+    .bind(refresh_token_hash)
+    .bind(device_name)
+    .bind(ip_address)
+    .bind(ttl_modifier(ttl_days))
+    .fetch_one(db)
+    .await?;
+
+    Ok(session)
+}
+
+/// Swaps the refresh token of a live session and extends it. Returns `None`
+/// when no live session holds `old_hash`.
+pub async fn rotate_session(
+    db: &Pool<Sqlite>,
+    old_hash: &str,
+    new_hash: &str,
+    ip_address: Option<&str>,
+    ttl_days: i64,
+) -> Result<Option<Session>, ApiError> {
+    let session = sqlx::query_as::<_, Session>(&format!(
+        r#"
+        UPDATE sessions
+        SET previous_token_hash = refresh_token_hash,
+            refresh_token_hash = ?2,
+            ip_address = COALESCE(?3, ip_address),
+            last_seen_at = CURRENT_TIMESTAMP,
+            expires_at = datetime('now', ?4)
+        WHERE refresh_token_hash = ?1
+          AND revoked_at IS NULL
+          AND expires_at > datetime('now')
+        RETURNING {}
+        "#,
+        SESSION_COLUMNS
+    ))
+    .bind(old_hash)
+    .bind(new_hash)
+    .bind(ip_address)
+    .bind(ttl_modifier(ttl_days))
+    .fetch_optional(db)
+    .await?;
+
+    Ok(session)
+}
+
+/// A rotated out refresh token was presented again, so either the client or
+/// an attacker holds a copy. Revoke the session it belonged to, returning
+/// its id and user.
+pub async fn revoke_reused_token(
+    db: &Pool<Sqlite>,
+    token_hash: &str,
+) -> Result<Option<(String, i64)>, ApiError> {
+    let revoked = sqlx::query_as(
+        r#"
+        UPDATE sessions
+        SET revoked_at = CURRENT_TIMESTAMP
+        WHERE previous_token_hash = ?1 AND revoked_at IS NULL
+        RETURNING id, user_id
+        "#,
+    )
+    .bind(token_hash)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(revoked)
+}
+
+pub async fn revoke_session(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    session_id: &str,
+) -> Result<bool, ApiError> {
+    let result = sqlx::query(
+        r#"
+        UPDATE sessions
+        SET revoked_at = CURRENT_TIMESTAMP
+        WHERE id = ?1 AND user_id = ?2 AND revoked_at IS NULL
+        "#,
+    )
+    .bind(session_id)
+    .bind(user_id)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Signs a user out everywhere, except on `keep` when given
+pub async fn revoke_user_sessions(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    keep: Option<&str>,
+) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"
+        UPDATE sessions
+        SET revoked_at = CURRENT_TIMESTAMP
+        WHERE user_id = ?1 AND revoked_at IS NULL AND (?2 IS NULL OR id != ?2)
+        "#,
+    )
+    .bind(user_id)
+    .bind(keep)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn list_active_sessions(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+) -> Result<Vec<Session>, ApiError> {
+    let sessions = sqlx::query_as::<_, Session>(&format!(
+        "SELECT {} FROM sessions \
+         WHERE user_id = ?1 AND revoked_at IS NULL AND expires_at > datetime('now') \
+         ORDER BY last_seen_at DESC",
+        SESSION_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(sessions)
+}
+
+/// Looks up a live session of an enabled user, bumping its last seen time
+/// on the way
+pub async fn check_session(
+    db: &Pool<Sqlite>,
+    session_id: &str,
+) -> Result<Option<SessionUser>, ApiError> {
+    let row: Option<(bool, bool, bool)> = sqlx::query_as(
+        r#"
+        SELECT s.last_seen_at < datetime('now', ?2), u.is_admin, u.must_change_password
+        FROM sessions s
+        JOIN users u ON u.id = s.user_id
+        WHERE s.id = ?1
+          AND s.revoked_at IS NULL
+          AND s.expires_at > datetime('now')
+          AND u.disabled = 0
+        "#,
+    )
+    .bind(session_id)
+    .bind(LAST_SEEN_RESOLUTION)
+    .fetch_optional(db)
+    .await?;
+
+    let Some((stale, is_admin, must_change_password)) = row else {
+        return Ok(None);
+    };
+    if stale {
+        sqlx::query("UPDATE sessions SET last_seen_at = CURRENT_TIMESTAMP WHERE id = ?1")
+            .bind(session_id)
+            .execute(db)
+            .await?;
+    }
+    Ok(Some(SessionUser {
+        is_admin,
+        must_change_password,
+    }))
+}
+
+/// Drops sessions that can no longer be used
+pub async fn prune_sessions(db: &Pool<Sqlite>) -> Result<(), ApiError> {
+    sqlx::query(
+        "DELETE FROM sessions WHERE expires_at <= datetime('now') OR revoked_at IS NOT NULL",
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
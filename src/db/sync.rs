@@ -1,16 +1,20 @@
-use sqlx::{Pool, Sqlite};
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Sqlite, SqliteConnection, SqliteExecutor};
 
-use crate::models::user::{Progress, ProgressUpdate};
+use crate::models::user::{BookProgress, FileDuration, Progress, ProgressSync, ProgressUpdate};
+
+// Same shape as CURRENT_TIMESTAMP so stored times compare as text
+pub const PROGRESS_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
 
 pub async fn get_progress_by_fileid(
-    db: &Pool<Sqlite>,
+    db: impl SqliteExecutor<'_>,
     user_id: i64,
     book_id: i64,
     file_id: i64,
 ) -> sqlx::Result<Option<Progress>> {
     sqlx::query_as::<_, Progress>(
         r#"
-    SELECT id, user_id, book_id, file_id, progress_ms, complete, updated_at
+    SELECT id, user_id, book_id, file_id, progress_ms, complete, updated_at, device_id
     FROM progress
     WHERE user_id = ?1 AND book_id = ?2 AND file_id = ?3
     "#,
@@ -29,7 +33,7 @@ pub async fn get_progress_by_bookid(
 ) -> sqlx::Result<Vec<Progress>> {
     sqlx::query_as::<_, Progress>(
         r#"
-    SELECT id, user_id, book_id, file_id, progress_ms, complete, updated_at
+    SELECT id, user_id, book_id, file_id, progress_ms, complete, updated_at, device_id
     FROM progress
     WHERE user_id = ?1 AND book_id = ?2
     "#,
@@ -40,30 +44,103 @@ pub async fn get_progress_by_bookid(
     .await
 }
 
-pub async fn upsert_progress(
-    db: &Pool<Sqlite>,
+/// Stores the position unless a newer one is already stored. Takes a
+/// connection so batches can run it inside a transaction.
+pub async fn apply_progress(
+    conn: &mut SqliteConnection,
     user_id: i64,
     p: &ProgressUpdate,
-) -> sqlx::Result<()> {
-    sqlx::query!(
+    updated_at: DateTime<Utc>,
+) -> sqlx::Result<ProgressSync> {
+    let applied: Option<Progress> = sqlx::query_as(
         r#"
-        INSERT INTO progress (user_id, book_id, file_id, progress_ms, complete)
-        VALUES (?1, ?2, ?3, ?4, ?5)
+        INSERT INTO progress (user_id, book_id, file_id, progress_ms, complete, updated_at, device_id)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
         ON CONFLICT(user_id, book_id, file_id) DO UPDATE SET
             progress_ms = excluded.progress_ms,
             complete = excluded.complete,
-            updated_at = CURRENT_TIMESTAMP
-        WHERE user_id = excluded.user_id 
-        AND book_id = excluded.book_id 
-        AND file_id = excluded.file_id
+            updated_at = excluded.updated_at,
+            device_id = excluded.device_id
+        WHERE excluded.updated_at >= progress.updated_at
+        RETURNING id, user_id, book_id, file_id, progress_ms, complete, updated_at, device_id
         "#,
-        user_id,
-        p.book_id,
-        p.file_id,
-        p.progress_ms,
-        p.complete
     )
-    .execute(db)
+    .bind(user_id)
+    .bind(p.book_id)
+    .bind(p.file_id)
+    .bind(p.progress_ms)
+    .bind(p.complete)
+    .bind(updated_at.format(PROGRESS_TIME_FORMAT).to_string())
+    .bind(&p.device_id)
+    .fetch_optional(&mut *conn)
     .await?;
+
+    if let Some(progress) = applied {
+        return Ok(ProgressSync::Applied { progress });
+    }
+
+    let progress = get_progress_by_fileid(&mut *conn, user_id, p.book_id, p.file_id)
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)?;
+
+    Ok(ProgressSync::Conflict {
+        progress,
+        rejected: ProgressUpdate {
+            updated_at: Some(updated_at),
+            ..p.clone()
+        },
+    })
+}
+
+/// Remembers a batch event id. False when it was already recorded.
+pub async fn record_progress_event(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    event_id: &str,
+) -> sqlx::Result<bool> {
+    let result = sqlx::query(
+        "INSERT INTO progress_events (user_id, event_id) VALUES (?, ?) ON CONFLICT DO NOTHING",
+    )
+    .bind(user_id)
+    .bind(event_id)
+    .execute(conn)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn prune_progress_events(db: impl SqliteExecutor<'_>) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM progress_events WHERE created_at < datetime('now', '-30 days')")
+        .execute(db)
+        .await?;
     Ok(())
 }
+
+pub async fn file_in_book(
+    db: impl SqliteExecutor<'_>,
+    book_id: i64,
+    file_id: i64,
+) -> sqlx::Result<bool> {
+    let found: Option<i64> =
+        sqlx::query_scalar("SELECT id FROM files WHERE id = ? AND book_id = ?")
+            .bind(file_id)
+            .bind(book_id)
+            .fetch_optional(db)
+            .await?;
+    Ok(found.is_some())
+}
+
+pub async fn compute_book_progress(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    book_id: i64,
+) -> sqlx::Result<BookProgress> {
+    let files: Vec<FileDuration> = sqlx::query_as(
+        "SELECT id, COALESCE(duration, 0) AS duration FROM files WHERE book_id = ? ORDER BY file_id, id",
+    )
+    .bind(book_id)
+    .fetch_all(db)
+    .await?;
+    let rows = get_progress_by_bookid(db, user_id, book_id).await?;
+
+    Ok(BookProgress::compute(book_id, &files, &rows))
+}
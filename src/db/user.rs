@@ -1,24 +1,26 @@
-use crate::models::user::User;
-use sqlx::{Pool, Result, Sqlite};
+use crate::models::user::{User, UserSummary};
+use sqlx::{Pool, Result, Sqlite, SqliteExecutor};
 
 pub async fn create_user(
-    db: &Pool<Sqlite>,
+    db: impl SqliteExecutor<'_>,
     username: &str,
     is_admin: &bool,
     password_hash: &str,
     salt: &str,
+    must_change_password: bool,
 ) -> Result<User> {
     let user = sqlx::query_as::<_, User>(
         r#"
-        INSERT INTO users (username, is_admin, password_hash, salt)
-        VALUES ($1, $2, $3, $4)
-        RETURNING id, is_admin, username, password_hash, salt
+        INSERT INTO users (username, is_admin, password_hash, salt, must_change_password)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, is_admin, username, password_hash, salt, disabled, must_change_password
         "#,
     )
     .bind(username)
     .bind(is_admin)
     .bind(password_hash)
     .bind(salt)
+    .bind(must_change_password)
     .fetch_one(db)
     .await?;
 
@@ -28,7 +30,7 @@ pub async fn create_user(
 pub async fn get_user_by_username(db: &Pool<Sqlite>, username: &str) -> Result<Option<User>> {
     let user = sqlx::query_as::<_, User>(
         r#"
-        SELECT id, username, is_admin, password_hash, salt
+        SELECT id, username, is_admin, password_hash, salt, disabled, must_change_password
         FROM users
         WHERE username = $1
         "#,
@@ -40,27 +42,116 @@ pub async fn get_user_by_username(db: &Pool<Sqlite>, username: &str) -> Result<O
     Ok(user)
 }
 
-// pub async fn update_user_password(
-//     db: &Pool<Sqlite>,
-//     user_id: i64,
-//     new_hash: &str,
-//     new_salt: &str,
-// ) -> Result<()> {
-//     sqlx::query(
-//         r#"
-//         UPDATE users
-//         SET password_hash = $1, salt = $2
-//         WHERE id = $3
-//         "#,
-//     )
-//     .bind(new_hash)
-//     .bind(new_salt)
-//     .bind(user_id)
-//     .execute(db)
-//     .await?;
-
-//     Ok(())
-// }
+pub async fn get_user_by_id(db: &Pool<Sqlite>, user_id: i64) -> Result<Option<User>> {
+    let user = sqlx::query_as::<_, User>(
+        r#"
+        SELECT id, username, is_admin, password_hash, salt, disabled, must_change_password
+        FROM users
+        WHERE id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn list_users(db: &Pool<Sqlite>) -> Result<Vec<UserSummary>> {
+    let users = sqlx::query_as::<_, UserSummary>(
+        r#"
+        SELECT u.id, u.username, u.is_admin, u.disabled, u.must_change_password,
+               CAST(u.created_at AS TEXT) AS created_at,
+               (SELECT MAX(CAST(s.last_seen_at AS TEXT)) FROM sessions s WHERE s.user_id = u.id) AS last_seen_at
+        FROM users u
+        ORDER BY u.username COLLATE NOCASE
+        "#,
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(users)
+}
+
+pub async fn get_user_summary(db: &Pool<Sqlite>, user_id: i64) -> Result<Option<UserSummary>> {
+    let user = sqlx::query_as::<_, UserSummary>(
+        r#"
+        SELECT u.id, u.username, u.is_admin, u.disabled, u.must_change_password,
+               CAST(u.created_at AS TEXT) AS created_at,
+               (SELECT MAX(CAST(s.last_seen_at AS TEXT)) FROM sessions s WHERE s.user_id = u.id) AS last_seen_at
+        FROM users u
+        WHERE u.id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn update_user_password(
+    db: &Pool<Sqlite>,
+    user_id: i64,
+    new_hash: &str,
+    new_salt: &str,
+    must_change_password: bool,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE users
+        SET password_hash = $1, salt = $2, must_change_password = $3
+        WHERE id = $4
+        "#,
+    )
+    .bind(new_hash)
+    .bind(new_salt)
+    .bind(must_change_password)
+    .bind(user_id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_user_admin(db: &Pool<Sqlite>, user_id: i64, is_admin: bool) -> Result<()> {
+    sqlx::query("UPDATE users SET is_admin = $1 WHERE id = $2")
+        .bind(is_admin)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn set_user_disabled(db: &Pool<Sqlite>, user_id: i64, disabled: bool) -> Result<()> {
+    sqlx::query("UPDATE users SET disabled = $1 WHERE id = $2")
+        .bind(disabled)
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Admins that can still log in
+pub async fn count_enabled_admins(db: &Pool<Sqlite>) -> Result<i64> {
+    let (count,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM users WHERE is_admin = 1 AND disabled = 0")
+            .fetch_one(db)
+            .await?;
+
+    Ok(count)
+}
+
+pub async fn set_must_change_password(db: &Pool<Sqlite>, user_id: i64) -> Result<()> {
+    sqlx::query("UPDATE users SET must_change_password = TRUE WHERE id = $1")
+        .bind(user_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
 
 pub async fn admin_exists(db: &Pool<Sqlite>) -> Result<i64> {
     let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users WHERE is_admin = 1")
@@ -70,15 +161,17 @@ pub async fn admin_exists(db: &Pool<Sqlite>) -> Result<i64> {
     Ok(count)
 }
 
-// pub async fn delete_user(db: &Pool<Sqlite>, user_id: i64) -> Result<()> {
-//     sqlx::query(
-//         r#"
-//         DELETE FROM users WHERE id = $1
-//         "#,
-//     )
-//     .bind(user_id)
-//     .execute(db)
-//     .await?;
-
-//     Ok(())
-// }
+/// Progress, sessions and library grants go with the user through
+/// their ON DELETE CASCADE foreign keys
+pub async fn delete_user(db: &Pool<Sqlite>, user_id: i64) -> Result<bool> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM users WHERE id = $1
+        "#,
+    )
+    .bind(user_id)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
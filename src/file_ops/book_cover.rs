@@ -1,98 +1,223 @@
-#[cfg(unix)]
-use std::os::unix::fs::symlink;
-#[cfg(windows)]
-use std::os::windows::fs::symlink_file;
+use std::io::Cursor;
 use std::path::{Path, PathBuf};
 
-use regex::Regex;
+use image::{ImageFormat, codecs::jpeg::JpegEncoder};
+use lofty::{picture::PictureType, tag::Tag};
+use sha2::{Digest, Sha256};
 use sqlx::SqlitePool;
 use tokio::fs;
-use walkdir::WalkDir;
 
 use crate::{
     api::api_error::ApiError,
-    db::audiobooks::list_all_books,
-    models::audiobooks::{AudioBook, AudioBookRow},
+    db::audiobooks::{list_books_without_cover, set_book_cover},
 };
 
-pub async fn create_cover_link(
-    source: &Path,
-    ext: &str,
-    book: &AudioBookRow,
-) -> Result<Option<String>, ApiError> {
-    let cover_name = &book.title.replace(' ', "_").to_lowercase().to_owned();
-    let re = Regex::new(r"[^a-z0-9_\-\.]").unwrap();
-    let cover_name = re.replace_all(&cover_name, "");
+pub const COVERS_DIR: &str = "covers";
+const THUMBS_DIR: &str = "thumbs";
+const THUMB_QUALITY: u8 = 85;
 
-    let link_name = format!("{}.{}", cover_name, ext);
-    let link_path = std::env::current_dir()?.join("covers").join(&link_name);
+/// Named thumbnail sizes accepted by `GET /api/books/{id}/cover?size=`
+pub const THUMB_SIZES: [(&str, u32); 3] = [("small", 160), ("medium", 320), ("large", 640)];
 
-    let source_path = std::env::current_dir()?.join(source);
+// Preferred file stems for loose images sitting next to the audio files
+const FOLDER_COVER_NAMES: [&str; 3] = ["cover", "folder", "front"];
 
-    // println!("Creating symlink: {:?} -> {:?}", link_path, source_path);
+/// Resolves a `size` query value to a thumbnail edge in pixels.
+/// `None` means the original image.
+pub fn thumb_size(size: Option<&str>) -> Result<Option<u32>, ApiError> {
+    match size {
+        None | Some("original") => Ok(None),
+        Some(name) => THUMB_SIZES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, px)| Some(*px))
+            .ok_or_else(|| ApiError::BadRequest(format!("Unknown cover size {}", name))),
+    }
+}
 
-    if !source_path.exists() {
-        return Err(ApiError::IOErrCustom(format!(
-            "Source does not exist: {:?}",
-            source_path
-        )));
+// Keys come from the database but still end up in a path, so only accept
+// the `{sha256}.{ext}` shape `store_cover` produces
+fn valid_key(key: &str) -> bool {
+    match key.split_once('.') {
+        Some((hash, ext)) => {
+            hash.len() == 64
+                && hash.bytes().all(|b| b.is_ascii_hexdigit())
+                && matches!(ext, "jpg" | "png" | "webp")
+        }
+        None => false,
     }
+}
+
+fn covers_root() -> Result<PathBuf, ApiError> {
+    Ok(std::env::current_dir()?.join(COVERS_DIR))
+}
+
+/// Path of the original image or of one of its thumbnails
+pub fn cover_path(key: &str, size: Option<u32>) -> Result<PathBuf, ApiError> {
+    if !valid_key(key) {
+        return Err(ApiError::NotFound("Cover not found".into()));
+    }
+    let root = covers_root()?;
+    Ok(match size {
+        None => root.join(key),
+        Some(px) => {
+            let hash = key.split('.').next().unwrap_or_default();
+            root.join(THUMBS_DIR).join(format!("{}_{}.jpg", hash, px))
+        }
+    })
+}
+
+pub fn cover_content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        _ => "image/jpeg",
+    }
+}
+
+fn cover_ext(data: &[u8]) -> Option<&'static str> {
+    match image::guess_format(data).ok()? {
+        ImageFormat::Jpeg => Some("jpg"),
+        ImageFormat::Png => Some("png"),
+        ImageFormat::WebP => Some("webp"),
+        _ => None,
+    }
+}
+
+/// Stores `data` under its content hash and renders the thumbnails.
+/// Returns the key saved in `cover_art`, identical images share one file.
+pub async fn store_cover(data: Vec<u8>) -> Result<String, ApiError> {
+    let ext = cover_ext(&data)
+        .ok_or_else(|| ApiError::BadRequest("Unsupported cover image format".into()))?;
+    let key = format!("{}.{}", hex::encode(Sha256::digest(&data)), ext);
+
+    let path = cover_path(&key, None)?;
+    if fs::metadata(&path).await.is_ok() {
+        return Ok(key);
+    }
+
+    fs::create_dir_all(covers_root()?.join(THUMBS_DIR)).await?;
+
+    // Write then rename so a concurrent reader never sees half an image
+    let tmp = path.with_extension("part");
+    fs::write(&tmp, &data).await?;
+    fs::rename(&tmp, &path).await?;
 
-    if let Some(parent) = link_path.parent() {
-        let _ = fs::create_dir_all(parent).await.map_err(|e| {
-            tracing::error!("Err creating dir {}", parent.display());
-            e
-        });
+    let thumb_key = key.clone();
+    tokio::task::spawn_blocking(move || render_thumbnails(&data, &thumb_key)).await??;
+
+    Ok(key)
+}
+
+/// Thumbnail for `key` at `px`, rendered on demand if it is missing
+pub async fn ensure_thumbnail(key: &str, px: u32) -> Result<PathBuf, ApiError> {
+    let thumb = cover_path(key, Some(px))?;
+    if fs::metadata(&thumb).await.is_ok() {
+        return Ok(thumb);
     }
 
-    #[cfg(unix)]
-    {
-        let _ = symlink(source_path, link_path).map_err(|e| {
-            tracing::error!("Failed cover art symlink {}. {}", link_name, e.to_string());
-        });
+    let data = fs::read(cover_path(key, None)?)
+        .await
+        .map_err(|_| ApiError::NotFound("Cover not found".into()))?;
+    fs::create_dir_all(covers_root()?.join(THUMBS_DIR)).await?;
+
+    let thumb_key = key.to_string();
+    tokio::task::spawn_blocking(move || render_thumbnails(&data, &thumb_key)).await??;
+    Ok(thumb)
+}
+
+fn render_thumbnails(data: &[u8], key: &str) -> Result<(), ApiError> {
+    let img = image::load_from_memory(data)
+        .map_err(|e| ApiError::Internal(format!("Failed to decode cover {}: {}", key, e)))?;
+
+    for (_, px) in THUMB_SIZES {
+        let path = cover_path(key, Some(px))?;
+        if path.exists() {
+            continue;
+        }
+
+        // Never upscale, small covers are just re-encoded
+        let thumb = if img.width() > px || img.height() > px {
+            img.thumbnail(px, px)
+        } else {
+            img.clone()
+        };
+
+        let mut buf = Cursor::new(Vec::new());
+        thumb
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut buf, THUMB_QUALITY))
+            .map_err(|e| {
+                ApiError::Internal(format!("Failed to encode thumbnail {}: {}", key, e))
+            })?;
+
+        let tmp = path.with_extension("part");
+        std::fs::write(&tmp, buf.into_inner())?;
+        std::fs::rename(&tmp, &path)?;
     }
+    Ok(())
+}
+
+/// Picks the embedded picture to use as cover: the front cover if one is
+/// tagged as such, otherwise the first picture found in any tag.
+pub fn embedded_picture(tags: &[Tag]) -> Option<Vec<u8>> {
+    let pictures = || tags.iter().flat_map(|t| t.pictures());
+    pictures()
+        .find(|p| p.pic_type() == PictureType::CoverFront)
+        .or_else(|| pictures().next())
+        .map(|p| p.data().to_vec())
+}
+
+/// Loose image in `dir`, preferring cover/folder/front over any other name
+pub async fn folder_cover(dir: &Path) -> Option<PathBuf> {
+    let mut entries = fs::read_dir(dir).await.ok()?;
+    let mut images = Vec::new();
 
-    #[cfg(windows)]
-    {
-        // Windows only allows symlink creation with elevated privileges or dev mode
-        if let Err(_) = symlink_file(source_path, target_path) {
-            // fallback to copy
-            fs::copy(source_path, target_path).map_err(|e| {
-                tracing::error!("Failed to copy cover art {}. {}", link_name, e.to_string());
-            });
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        let ext = path
+            .extension()
+            .and_then(|f| f.to_str())
+            .map(|f| f.to_lowercase());
+
+        if matches!(ext.as_deref(), Some("jpg" | "jpeg" | "png" | "webp")) && path.is_file() {
+            images.push(path);
         }
     }
 
-    Ok(Some(format!("/covers/{}", link_name)))
+    images.sort();
+    let preferred = images.iter().position(|p| {
+        p.file_stem()
+            .and_then(|s| s.to_str())
+            .is_some_and(|s| FOLDER_COVER_NAMES.contains(&s.to_lowercase().as_str()))
+    });
+    match preferred {
+        Some(i) => Some(images.swap_remove(i)),
+        None => images.into_iter().next(),
+    }
+}
+
+/// Stores the loose cover image of `dir`, if it has one
+pub async fn store_folder_cover(dir: &Path) -> Option<String> {
+    let image = folder_cover(dir).await?;
+    let data = fs::read(&image)
+        .await
+        .inspect_err(|e| tracing::error!("Err reading cover {}. {}", image.display(), e))
+        .ok()?;
+
+    store_cover(data)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to store cover {}. {}", image.display(), e))
+        .ok()
 }
 
+/// Backfills `cover_art` for books that have no embedded picture from a
+/// loose image in their folder
 pub async fn cover_links(db: &SqlitePool) -> Result<(), ApiError> {
-    let books = list_all_books(db).await?;
+    let books = list_books_without_cover(db).await?;
     for book in books {
-        for entry in WalkDir::new(&book.files_location)
-            .contents_first(true)
-            .max_depth(1)
-        {
-            match entry {
-                Ok(file) => {
-                    if file.file_type().is_file() {
-                        let ext = file
-                            .path()
-                            .extension()
-                            .and_then(|f| f.to_str())
-                            .map(|f| f.to_lowercase());
-
-                        if let Some(ext) = &ext {
-                            if matches!(ext.as_str(), "jpg" | "jpeg" | "png" | "webp") {
-                                let cover_art = create_cover_link(file.path(), ext, &book).await?;
-                            }
-                        }
-                    }
-                }
-                Err(e) => {
-                    tracing::error!("Err reading {:#?}", e)
-                }
-            }
+        if let Some(key) = store_folder_cover(Path::new(&book.files_location)).await {
+            set_book_cover(db, book.id, &key).await?;
         }
     }
     Ok(())
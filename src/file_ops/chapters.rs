@@ -0,0 +1,858 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::models::audiobooks::{BookChapter, Chapter, ChapterRow, FileMetadata};
+
+// Guard against corrupt headers asking us to allocate gigabytes
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+const MAX_ID3_SIZE: usize = 64 * 1024 * 1024;
+
+/// Reads embedded chapter markers from an audio file.
+/// Supports MP4 chapter tracks / Nero `chpl` atoms and ID3v2 CHAP/CTOC frames.
+/// Returns an empty list when the file has no (readable) chapters.
+pub fn extract_chapters(path: &Path, duration_ms: i64) -> Vec<Chapter> {
+    let chapters = match read_chapters(path) {
+        Ok(chapters) => chapters,
+        Err(e) => {
+            tracing::warn!("Failed reading chapters {} {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+
+    finalize(chapters, duration_ms)
+}
+
+fn read_chapters(path: &Path) -> std::io::Result<Vec<Chapter>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 8];
+    let n = file.read(&mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    if n >= 3 && &magic[..3] == b"ID3" {
+        return id3_chapters(&mut file);
+    }
+    if n >= 8 && &magic[4..8] == b"ftyp" {
+        return mp4_chapters(&mut file);
+    }
+    Ok(Vec::new())
+}
+
+// Sort, fill open ends from the next chapter / file duration and drop empties
+fn finalize(mut chapters: Vec<Chapter>, duration_ms: i64) -> Vec<Chapter> {
+    chapters.sort_by_key(|c| c.start_ms);
+
+    let starts: Vec<i64> = chapters.iter().map(|c| c.start_ms).collect();
+    for (i, chapter) in chapters.iter_mut().enumerate() {
+        let next_start = starts.get(i + 1).copied().unwrap_or(duration_ms);
+        if chapter.end_ms <= chapter.start_ms || chapter.end_ms > next_start.max(duration_ms) {
+            chapter.end_ms = next_start;
+        }
+        if duration_ms > 0 {
+            chapter.end_ms = chapter.end_ms.min(duration_ms);
+        }
+        if let Some(title) = &chapter.title {
+            let title = title.trim_matches(char::from(0)).trim();
+            chapter.title = (!title.is_empty()).then(|| title.to_string());
+        }
+    }
+
+    chapters.retain(|c| c.end_ms > c.start_ms);
+    chapters
+}
+
+/// Lays file-level chapters out on the book timeline. `files` must be in
+/// playback order; files without embedded chapters become a single chapter.
+pub fn book_chapters(files: &[FileMetadata], rows: &[ChapterRow]) -> Vec<BookChapter> {
+    let mut out = Vec::new();
+    let mut book_offset = 0i64;
+
+    for file in files {
+        let duration = file.data.duration.unwrap_or(0);
+        let file_rows: Vec<&ChapterRow> = rows.iter().filter(|r| r.file_id == file.id).collect();
+
+        if file_rows.is_empty() {
+            let title = Path::new(&file.data.file_name)
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_else(|| file.data.file_name.clone());
+            out.push(BookChapter {
+                index: out.len() as i64,
+                title,
+                file_id: file.id,
+                file_start_ms: 0,
+                file_end_ms: duration,
+                start_ms: book_offset,
+                end_ms: book_offset + duration,
+            });
+        }
+
+        for row in file_rows {
+            out.push(BookChapter {
+                index: out.len() as i64,
+                title: row
+                    .title
+                    .clone()
+                    .unwrap_or_else(|| format!("Chapter {}", out.len() + 1)),
+                file_id: file.id,
+                file_start_ms: row.start_ms,
+                file_end_ms: row.end_ms,
+                start_ms: book_offset + row.start_ms,
+                end_ms: book_offset + row.end_ms,
+            });
+        }
+
+        book_offset += duration;
+    }
+
+    out
+}
+
+// ---------------------------------------------------------------------------
+// MP4
+// ---------------------------------------------------------------------------
+
+struct Atom<'a> {
+    kind: [u8; 4],
+    body: &'a [u8],
+}
+
+fn atoms(mut data: &[u8]) -> Vec<Atom<'_>> {
+    let mut out = Vec::new();
+    while data.len() >= 8 {
+        let mut size = u32::from_be_bytes(data[0..4].try_into().unwrap()) as u64;
+        let kind: [u8; 4] = data[4..8].try_into().unwrap();
+        let mut header = 8usize;
+
+        if size == 1 {
+            if data.len() < 16 {
+                break;
+            }
+            size = u64::from_be_bytes(data[8..16].try_into().unwrap());
+            header = 16;
+        } else if size == 0 {
+            size = data.len() as u64;
+        }
+
+        if size < header as u64 || size > data.len() as u64 {
+            break;
+        }
+        out.push(Atom {
+            kind,
+            body: &data[header..size as usize],
+        });
+        data = &data[size as usize..];
+    }
+    out
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    atoms(data)
+        .into_iter()
+        .find(|a| &a.kind == kind)
+        .map(|a| a.body)
+}
+
+fn path<'a>(data: &'a [u8], kinds: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    kinds.iter().try_fold(data, |d, k| child(d, k))
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 4)
+        .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+}
+
+fn be_u64(data: &[u8], at: usize) -> Option<u64> {
+    data.get(at..at + 8)
+        .map(|b| u64::from_be_bytes(b.try_into().unwrap()))
+}
+
+// moov is usually small but may sit behind a huge mdat, so walk the top level
+// by seeking rather than reading the file
+fn read_moov(file: &mut File) -> std::io::Result<Option<Vec<u8>>> {
+    let len = file.metadata()?.len();
+    let mut pos = 0u64;
+
+    // Sizes come from the file, so every step is checked against its length
+    while len - pos >= 8 {
+        file.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 16];
+        file.read_exact(&mut header[..8])?;
+        let mut size = u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64;
+        let mut header_len = 8;
+
+        if size == 1 {
+            file.read_exact(&mut header[8..16])?;
+            size = u64::from_be_bytes(header[8..16].try_into().unwrap());
+            header_len = 16;
+        } else if size == 0 {
+            size = len - pos;
+        }
+        if size < header_len || size > len - pos {
+            break;
+        }
+
+        if &header[4..8] == b"moov" {
+            let body_len = size - header_len;
+            if body_len > MAX_MOOV_SIZE {
+                return Ok(None);
+            }
+            let mut body = vec![0u8; body_len as usize];
+            file.read_exact(&mut body)?;
+            return Ok(Some(body));
+        }
+        pos += size;
+    }
+
+    Ok(None)
+}
+
+fn mp4_chapters(file: &mut File) -> std::io::Result<Vec<Chapter>> {
+    let Some(moov) = read_moov(file)? else {
+        return Ok(Vec::new());
+    };
+
+    let track_chapters = quicktime_chapters(file, &moov)?;
+    if !track_chapters.is_empty() {
+        return Ok(track_chapters);
+    }
+
+    Ok(path(&moov, &[b"udta", b"chpl"])
+        .map(nero_chapters)
+        .unwrap_or_default())
+}
+
+// Nero chapter list: start times in 100ns units, pascal-string titles
+fn nero_chapters(chpl: &[u8]) -> Vec<Chapter> {
+    let mut chapters = Vec::new();
+    let Some(&version) = chpl.first() else {
+        return chapters;
+    };
+    let mut pos = if version == 1 { 8 } else { 4 };
+
+    let Some(&count) = chpl.get(pos) else {
+        return chapters;
+    };
+    pos += 1;
+
+    for _ in 0..count {
+        let Some(start) = be_u64(chpl, pos) else {
+            break;
+        };
+        let Some(&title_len) = chpl.get(pos + 8) else {
+            break;
+        };
+        let title_start = pos + 9;
+        let Some(title) = chpl.get(title_start..title_start + title_len as usize) else {
+            break;
+        };
+        chapters.push(Chapter {
+            title: Some(String::from_utf8_lossy(title).into_owned()),
+            start_ms: (start / 10_000) as i64,
+            end_ms: 0,
+        });
+        pos = title_start + title_len as usize;
+    }
+
+    chapters
+}
+
+// QuickTime style: an audio track references a text track via tref/chap and
+// every sample of that text track is one chapter title
+fn quicktime_chapters(file: &mut File, moov: &[u8]) -> std::io::Result<Vec<Chapter>> {
+    let traks: Vec<&[u8]> = atoms(moov)
+        .into_iter()
+        .filter(|a| &a.kind == b"trak")
+        .map(|a| a.body)
+        .collect();
+
+    let chapter_ids: Vec<u32> = traks
+        .iter()
+        .filter_map(|t| path(t, &[b"tref", b"chap"]))
+        .flat_map(|chap| {
+            chap.chunks_exact(4)
+                .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+        })
+        .collect();
+    if chapter_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let Some(trak) = traks.iter().find(|t| {
+        child(t, b"tkhd")
+            .and_then(|tkhd| {
+                let at = if tkhd.first() == Some(&1) { 20 } else { 12 };
+                be_u32(tkhd, at)
+            })
+            .is_some_and(|id| chapter_ids.contains(&id))
+    }) else {
+        return Ok(Vec::new());
+    };
+
+    let Some(mdia) = child(trak, b"mdia") else {
+        return Ok(Vec::new());
+    };
+    let timescale = child(mdia, b"mdhd")
+        .and_then(|mdhd| {
+            let at = if mdhd.first() == Some(&1) { 20 } else { 12 };
+            be_u32(mdhd, at)
+        })
+        .filter(|t| *t > 0)
+        .unwrap_or(1000) as u64;
+
+    let Some(stbl) = path(mdia, &[b"minf", b"stbl"]) else {
+        return Ok(Vec::new());
+    };
+
+    let durations = sample_durations(stbl);
+    let sizes = sample_sizes(stbl, durations.len());
+    let offsets = sample_offsets(stbl, &sizes);
+
+    let mut chapters = Vec::with_capacity(offsets.len());
+    let mut elapsed = 0u64;
+    for (i, offset) in offsets.iter().enumerate() {
+        let size = sizes.get(i).copied().unwrap_or(0).min(4096) as usize;
+        let title = if size >= 2 {
+            let mut buf = vec![0u8; size];
+            file.seek(SeekFrom::Start(*offset))?;
+            file.read_exact(&mut buf)?;
+            let text_len = (u16::from_be_bytes([buf[0], buf[1]]) as usize).min(size - 2);
+            Some(decode_text_sample(&buf[2..2 + text_len]))
+        } else {
+            None
+        };
+
+        let duration = durations.get(i).copied().unwrap_or(0) as u64;
+        chapters.push(Chapter {
+            title,
+            start_ms: (elapsed * 1000 / timescale) as i64,
+            end_ms: ((elapsed + duration) * 1000 / timescale) as i64,
+        });
+        elapsed += duration;
+    }
+
+    Ok(chapters)
+}
+
+fn decode_text_sample(bytes: &[u8]) -> String {
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        return decode_utf16(&bytes[2..], true);
+    }
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        return decode_utf16(&bytes[2..], false);
+    }
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn sample_durations(stbl: &[u8]) -> Vec<u32> {
+    let Some(stts) = child(stbl, b"stts") else {
+        return Vec::new();
+    };
+    let count = be_u32(stts, 4).unwrap_or(0) as usize;
+    let mut durations = Vec::new();
+    for i in 0..count {
+        let (Some(samples), Some(delta)) = (be_u32(stts, 8 + i * 8), be_u32(stts, 12 + i * 8))
+        else {
+            break;
+        };
+        durations.extend(std::iter::repeat_n(delta, samples.min(10_000) as usize));
+    }
+    durations
+}
+
+fn sample_sizes(stbl: &[u8], sample_count: usize) -> Vec<u32> {
+    let Some(stsz) = child(stbl, b"stsz") else {
+        return Vec::new();
+    };
+    let fixed = be_u32(stsz, 4).unwrap_or(0);
+    if fixed != 0 {
+        return vec![fixed; sample_count];
+    }
+    let count = be_u32(stsz, 8).unwrap_or(0) as usize;
+    (0..count).map_while(|i| be_u32(stsz, 12 + i * 4)).collect()
+}
+
+// Resolve stsc + stco/co64 into one file offset per sample
+fn sample_offsets(stbl: &[u8], sizes: &[u32]) -> Vec<u64> {
+    let chunk_offsets: Vec<u64> = if let Some(stco) = child(stbl, b"stco") {
+        let count = be_u32(stco, 4).unwrap_or(0) as usize;
+        (0..count)
+            .map_while(|i| be_u32(stco, 8 + i * 4).map(u64::from))
+            .collect()
+    } else if let Some(co64) = child(stbl, b"co64") {
+        let count = be_u32(co64, 4).unwrap_or(0) as usize;
+        (0..count).map_while(|i| be_u64(co64, 8 + i * 8)).collect()
+    } else {
+        return Vec::new();
+    };
+
+    let runs: Vec<(u32, u32)> = child(stbl, b"stsc")
+        .map(|stsc| {
+            let count = be_u32(stsc, 4).unwrap_or(0) as usize;
+            (0..count)
+                .map_while(|i| Some((be_u32(stsc, 8 + i * 12)?, be_u32(stsc, 12 + i * 12)?)))
+                .collect()
+        })
+        .unwrap_or_else(|| vec![(1, 1)]);
+
+    let mut offsets = Vec::new();
+    let mut sample = 0usize;
+    for (chunk_index, chunk_offset) in chunk_offsets.iter().enumerate() {
+        let chunk_no = chunk_index as u32 + 1;
+        let per_chunk = runs
+            .iter()
+            .rev()
+            .find(|(first, _)| *first <= chunk_no)
+            .map(|(_, n)| *n)
+            .unwrap_or(1);
+
+        let mut offset = *chunk_offset;
+        for _ in 0..per_chunk {
+            if sample >= sizes.len() {
+                return offsets;
+            }
+            offsets.push(offset);
+            offset = offset.saturating_add(sizes.get(sample).copied().unwrap_or(0) as u64);
+            sample += 1;
+        }
+    }
+    offsets
+}
+
+// ---------------------------------------------------------------------------
+// ID3v2
+// ---------------------------------------------------------------------------
+
+struct Frame<'a> {
+    id: [u8; 4],
+    body: std::borrow::Cow<'a, [u8]>,
+}
+
+fn synchsafe(b: &[u8]) -> usize {
+    b.iter()
+        .fold(0usize, |acc, x| (acc << 7) | (*x as usize & 0x7f))
+}
+
+fn remove_unsync(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut prev = 0u8;
+    for &b in data {
+        if !(prev == 0xFF && b == 0x00) {
+            out.push(b);
+        }
+        prev = b;
+    }
+    out
+}
+
+fn id3_frames(mut data: &[u8], major: u8, tag_unsync: bool) -> Vec<Frame<'_>> {
+    let mut frames = Vec::new();
+    while data.len() >= 10 && data[0] != 0 {
+        let id: [u8; 4] = data[0..4].try_into().unwrap();
+        let size = if major == 4 {
+            synchsafe(&data[4..8])
+        } else {
+            u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize
+        };
+        let format_flags = data[9];
+        if size > data.len() - 10 {
+            break;
+        }
+
+        let mut raw = &data[10..10 + size];
+        data = &data[10 + size..];
+
+        if major == 4 {
+            // Compressed or encrypted frames are of no use to us
+            if format_flags & 0x0C != 0 {
+                continue;
+            }
+            // Grouping identity byte, then the data length indicator, both
+            // ahead of the frame data
+            let skip =
+                usize::from(format_flags & 0x40 != 0) + 4 * usize::from(format_flags & 0x01 != 0);
+            let Some(rest) = raw.get(skip..) else {
+                continue;
+            };
+            raw = rest;
+        }
+
+        let body = if major == 4 && (tag_unsync || format_flags & 0x02 != 0) {
+            std::borrow::Cow::Owned(remove_unsync(raw))
+        } else {
+            std::borrow::Cow::Borrowed(raw)
+        };
+        frames.push(Frame { id, body });
+    }
+    frames
+}
+
+fn id3_chapters(file: &mut File) -> std::io::Result<Vec<Chapter>> {
+    let mut header = [0u8; 10];
+    file.read_exact(&mut header)?;
+    let major = header[3];
+    let flags = header[5];
+    let size = synchsafe(&header[6..10]);
+    if !(3..=4).contains(&major) || size > MAX_ID3_SIZE {
+        return Ok(Vec::new());
+    }
+
+    let mut tag = vec![0u8; size];
+    file.read_exact(&mut tag)?;
+
+    let tag_unsync = flags & 0x80 != 0;
+    if major == 3 && tag_unsync {
+        tag = remove_unsync(&tag);
+    }
+
+    let mut body: &[u8] = &tag;
+    if flags & 0x40 != 0 && body.len() >= 4 {
+        let ext_size = if major == 4 {
+            synchsafe(&body[0..4])
+        } else {
+            u32::from_be_bytes(body[0..4].try_into().unwrap()) as usize + 4
+        };
+        body = body.get(ext_size..).unwrap_or_default();
+    }
+
+    let frames = id3_frames(body, major, tag_unsync);
+
+    let mut by_element: HashMap<String, Chapter> = HashMap::new();
+    let mut order: Vec<String> = Vec::new();
+    let mut toc_order: Option<Vec<String>> = None;
+
+    for frame in &frames {
+        match &frame.id {
+            b"CHAP" => {
+                let Some((element_id, rest)) = split_cstr(&frame.body) else {
+                    continue;
+                };
+                if rest.len() < 16 {
+                    continue;
+                }
+                let start = u32::from_be_bytes(rest[0..4].try_into().unwrap());
+                let end = u32::from_be_bytes(rest[4..8].try_into().unwrap());
+                let title = id3_frames(&rest[16..], major, tag_unsync)
+                    .into_iter()
+                    .find(|f| &f.id == b"TIT2")
+                    .map(|f| decode_id3_text(&f.body));
+
+                order.push(element_id.clone());
+                by_element.insert(
+                    element_id,
+                    Chapter {
+                        title,
+                        start_ms: start as i64,
+                        end_ms: if end == u32::MAX { 0 } else { end as i64 },
+                    },
+                );
+            }
+            b"CTOC" => {
+                let Some((_, rest)) = split_cstr(&frame.body) else {
+                    continue;
+                };
+                let (Some(&toc_flags), Some(&count)) = (rest.first(), rest.get(1)) else {
+                    continue;
+                };
+                // Only the top-level, ordered table of contents decides order
+                if toc_flags & 0x03 != 0x03 {
+                    continue;
+                }
+                let mut children = Vec::with_capacity(count as usize);
+                let mut remaining = &rest[2..];
+                for _ in 0..count {
+                    let Some((child_id, tail)) = split_cstr(remaining) else {
+                        break;
+                    };
+                    children.push(child_id);
+                    remaining = tail;
+                }
+                toc_order = Some(children);
+            }
+            _ => {}
+        }
+    }
+
+    let order = toc_order.unwrap_or(order);
+    Ok(order
+        .into_iter()
+        .filter_map(|id| by_element.remove(&id))
+        .collect())
+}
+
+fn split_cstr(data: &[u8]) -> Option<(String, &[u8])> {
+    let nul = data.iter().position(|b| *b == 0)?;
+    Some((
+        String::from_utf8_lossy(&data[..nul]).into_owned(),
+        &data[nul + 1..],
+    ))
+}
+
+fn decode_id3_text(body: &[u8]) -> String {
+    let Some((&encoding, text)) = body.split_first() else {
+        return String::new();
+    };
+    match encoding {
+        0 => text.iter().map(|b| *b as char).collect(),
+        1 => {
+            if text.starts_with(&[0xFE, 0xFF]) {
+                decode_utf16(&text[2..], true)
+            } else if text.starts_with(&[0xFF, 0xFE]) {
+                decode_utf16(&text[2..], false)
+            } else {
+                decode_utf16(text, false)
+            }
+        }
+        2 => decode_utf16(text, true),
+        _ => String::from_utf8_lossy(text).into_owned(),
+    }
+}
+
+fn decode_utf16(bytes: &[u8], big_endian: bool) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|b| {
+            if big_endian {
+                u16::from_be_bytes([b[0], b[1]])
+            } else {
+                u16::from_le_bytes([b[0], b[1]])
+            }
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static FIXTURE: AtomicUsize = AtomicUsize::new(0);
+
+    // Parsers work on files, so fixtures go through a scratch file
+    fn chapters_of(bytes: &[u8], duration_ms: i64) -> Vec<(Option<String>, i64, i64)> {
+        let path = std::env::temp_dir().join(format!(
+            "chapters-{}-{}",
+            std::process::id(),
+            FIXTURE.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, bytes).unwrap();
+        let chapters = extract_chapters(&path, duration_ms);
+        std::fs::remove_file(&path).unwrap();
+        chapters
+            .into_iter()
+            .map(|c| (c.title, c.start_ms, c.end_ms))
+            .collect()
+    }
+
+    fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn full_box(fields: &[u32]) -> Vec<u8> {
+        // version + flags, then big-endian fields
+        let mut out = vec![0u8; 4];
+        for f in fields {
+            out.extend_from_slice(&f.to_be_bytes());
+        }
+        out
+    }
+
+    fn ftyp() -> Vec<u8> {
+        atom(b"ftyp", b"M4B \0\0\0\0")
+    }
+
+    fn synchsafe_bytes(n: usize) -> [u8; 4] {
+        [
+            (n >> 21) as u8 & 0x7f,
+            (n >> 14) as u8 & 0x7f,
+            (n >> 7) as u8 & 0x7f,
+            n as u8 & 0x7f,
+        ]
+    }
+
+    fn title(text: &str) -> Option<String> {
+        Some(text.to_string())
+    }
+
+    #[test]
+    fn mp4_nero_chapter_list() {
+        let mut chpl = vec![0u8; 4];
+        chpl.push(2);
+        for (start_ms, name) in [(0u64, "Intro"), (90_000, "Part One")] {
+            chpl.extend_from_slice(&(start_ms * 10_000).to_be_bytes());
+            chpl.push(name.len() as u8);
+            chpl.extend_from_slice(name.as_bytes());
+        }
+        let moov = atom(b"moov", &atom(b"udta", &atom(b"chpl", &chpl)));
+        let file = [ftyp(), moov].concat();
+
+        assert_eq!(
+            chapters_of(&file, 300_000),
+            vec![
+                (title("Intro"), 0, 90_000),
+                (title("Part One"), 90_000, 300_000),
+            ]
+        );
+    }
+
+    #[test]
+    fn quicktime_text_track() {
+        let samples: Vec<Vec<u8>> = ["One", "Two"]
+            .iter()
+            .map(|t| {
+                [
+                    (t.len() as u16).to_be_bytes().to_vec(),
+                    t.as_bytes().to_vec(),
+                ]
+                .concat()
+            })
+            .collect();
+        let mdat = atom(b"mdat", &samples.concat());
+        let first_offset = (ftyp().len() + 8) as u32;
+
+        let audio = atom(
+            b"trak",
+            &[
+                atom(b"tkhd", &full_box(&[0, 0, 1])),
+                atom(b"tref", &atom(b"chap", &2u32.to_be_bytes())),
+            ]
+            .concat(),
+        );
+        let stbl = [
+            atom(b"stts", &full_box(&[1, 2, 60_000])),
+            atom(b"stsz", &full_box(&[0, 2, 5, 5])),
+            atom(b"stsc", &full_box(&[1, 1, 2, 1])),
+            atom(b"stco", &full_box(&[1, first_offset])),
+        ]
+        .concat();
+        let text = atom(
+            b"trak",
+            &[
+                atom(b"tkhd", &full_box(&[0, 0, 2])),
+                atom(
+                    b"mdia",
+                    &[
+                        atom(b"mdhd", &full_box(&[0, 0, 1000])),
+                        atom(b"minf", &atom(b"stbl", &stbl)),
+                    ]
+                    .concat(),
+                ),
+            ]
+            .concat(),
+        );
+        let moov = atom(b"moov", &[audio, text].concat());
+        let file = [ftyp(), mdat, moov].concat();
+
+        assert_eq!(
+            chapters_of(&file, 120_000),
+            vec![(title("One"), 0, 60_000), (title("Two"), 60_000, 120_000)]
+        );
+    }
+
+    #[test]
+    fn corrupt_atom_sizes_stop_the_walk() {
+        // 64-bit largesize that would overflow the file position
+        let mut huge = 1u32.to_be_bytes().to_vec();
+        huge.extend_from_slice(b"free");
+        huge.extend_from_slice(&(u64::MAX - 4).to_be_bytes());
+        assert!(chapters_of(&[ftyp(), huge].concat(), 1000).is_empty());
+
+        // Size running past the end of the file
+        let mut truncated = 4096u32.to_be_bytes().to_vec();
+        truncated.extend_from_slice(b"moov");
+        assert!(chapters_of(&[ftyp(), truncated].concat(), 1000).is_empty());
+    }
+
+    fn id3_frame(major: u8, id: &[u8; 4], format_flags: u8, body: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        if major == 4 {
+            out.extend_from_slice(&synchsafe_bytes(body.len()));
+        } else {
+            out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        }
+        out.extend_from_slice(&[0, format_flags]);
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn id3_tag(major: u8, frames: &[Vec<u8>]) -> Vec<u8> {
+        let frames = frames.concat();
+        let mut out = b"ID3".to_vec();
+        out.extend_from_slice(&[major, 0, 0]);
+        out.extend_from_slice(&synchsafe_bytes(frames.len()));
+        out.extend_from_slice(&frames);
+        out
+    }
+
+    // CHAP with a TIT2 subframe; `dli` prefixes frame data with a length indicator
+    fn chap(major: u8, element: &str, start: u32, end: u32, name: &str, dli: bool) -> Vec<u8> {
+        let (flags, prefix) = if dli {
+            (0x01, synchsafe_bytes(name.len() + 1).to_vec())
+        } else {
+            (0, Vec::new())
+        };
+        let tit2 = [prefix, vec![3], name.as_bytes().to_vec()].concat();
+
+        let mut body = [element.as_bytes(), &[0]].concat();
+        for v in [start, end, u32::MAX, u32::MAX] {
+            body.extend_from_slice(&v.to_be_bytes());
+        }
+        body.extend(id3_frame(major, b"TIT2", flags, &tit2));
+
+        let body = if dli {
+            [synchsafe_bytes(body.len()).to_vec(), body].concat()
+        } else {
+            body
+        };
+        id3_frame(major, b"CHAP", flags, &body)
+    }
+
+    #[test]
+    fn id3_chapters_follow_table_of_contents() {
+        let mut ctoc = b"toc\0".to_vec();
+        ctoc.extend_from_slice(&[0x03, 2]);
+        ctoc.extend_from_slice(b"ch2\0ch1\0");
+
+        let tag = id3_tag(
+            3,
+            &[
+                chap(3, "ch1", 0, 5_000, "First", false),
+                chap(3, "ch2", 5_000, 9_000, "Second", false),
+                chap(3, "extra", 9_000, 9_500, "Not listed", false),
+                id3_frame(3, b"CTOC", 0, &ctoc),
+            ],
+        );
+
+        // Only chapters listed in the ordered TOC are kept
+        assert_eq!(
+            chapters_of(&tag, 9_000),
+            vec![(title("First"), 0, 5_000), (title("Second"), 5_000, 9_000)]
+        );
+    }
+
+    #[test]
+    fn id3v24_data_length_indicator_is_skipped() {
+        let tag = id3_tag(
+            4,
+            &[
+                chap(4, "a", 0, 1_000, "Opening", true),
+                chap(4, "b", 1_000, 2_000, "Closing", false),
+            ],
+        );
+
+        assert_eq!(
+            chapters_of(&tag, 2_000),
+            vec![
+                (title("Opening"), 0, 1_000),
+                (title("Closing"), 1_000, 2_000)
+            ]
+        );
+    }
+}
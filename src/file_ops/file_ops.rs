@@ -1,23 +1,20 @@
 use crate::api::api_error::ApiError;
 use crate::db::audiobooks::{
-    insert_audiobook, insert_file_metadata, list_all_books, update_audiobook_duration,
+    insert_audiobook, insert_file_metadata, list_all_books, replace_file_chapters,
+    update_audiobook_duration,
 };
-use crate::file_ops::book_cover::create_cover_link;
+use crate::file_ops::chapters::extract_chapters;
 use crate::models::audiobooks::{AudioBook, AudioBookRow, CreateFileMetadata};
 use futures::{StreamExt, stream};
 
-use lofty::file::FileType;
-use lofty::properties;
 use lofty::{
     config::{ParseOptions, ParsingMode},
     file::AudioFile,
     probe::Probe,
 };
-use regex::Regex;
 use sqlx::{Pool, Sqlite, SqlitePool};
 use std::path::{Path, PathBuf};
 use std::result::Result;
-use symphonia::core::meta;
 use tokio::{fs, task::JoinHandle};
 use tracing::{info, warn};
 
@@ -28,7 +25,7 @@ async fn has_dirs(path: &PathBuf) -> Result<bool, ApiError> {
             return Ok(true);
         }
     }
-    return Ok(false);
+    Ok(false)
 }
 
 async fn recursive_dirscan(
@@ -69,10 +66,10 @@ async fn recursive_dirscan(
             .map(|c| c.as_os_str().to_str().unwrap_or("").to_string())
             .collect();
 
-        if sub_dir_path.is_absolute() {
-            if let Some(index) = &v.iter().position(|c| c == last_path_component) {
-                v.drain(..index);
-            }
+        if sub_dir_path.is_absolute()
+            && let Some(index) = &v.iter().position(|c| c == last_path_component)
+        {
+            v.drain(..index);
         }
 
         let (author, series, title): (String, Option<String>, String) = match v.as_slice() {
@@ -88,7 +85,7 @@ async fn recursive_dirscan(
             }
         };
 
-        let is_series = series == None && has_dirs(&sub_dir_path).await?;
+        let is_series = series.is_none() && has_dirs(&sub_dir_path).await?;
         if !is_series {
             let Some(conent_path) = sub_dir_path.to_str().to_owned() else {
                 warn!("Path is not valid UTF-8");
@@ -140,7 +137,7 @@ async fn capture_files_cover_paths(
 ) -> Vec<(i64, AudioBook)> {
     let mut insert_tasks: Vec<JoinHandle<Result<(i64, AudioBook), ApiError>>> = vec![];
 
-    for mut book in audio_books {
+    for book in audio_books {
         info!("==== Before extracting, {}", book.title);
         let db = db.clone();
         insert_tasks.push(tokio::spawn(async move {
@@ -248,15 +245,28 @@ async fn capture_metadata(
             for (index, f) in metadata.iter_mut().enumerate() {
                 f.file_id = Some(index as i64 + 1);
                 total_duration += f.duration.unwrap_or(0);
-                insert_file_metadata(&db, f)
+                let Some(file_row_id) = insert_file_metadata(db, f)
                     .await
                     .inspect_err(|e| {
                         tracing::error!("Err inserting {} metadata, Err: {}", f.file_name, e)
                     })
-                    .ok();
+                    .ok()
+                else {
+                    continue;
+                };
+
+                let chapters = extract_chapters(Path::new(&f.file_path), f.duration.unwrap_or(0));
+                if !chapters.is_empty() {
+                    replace_file_chapters(db, file_row_id, &chapters)
+                        .await
+                        .inspect_err(|e| {
+                            tracing::error!("Err inserting {} chapters, Err: {}", f.file_name, e)
+                        })
+                        .ok();
+                }
             }
 
-            update_audiobook_duration(&db, book_id.to_owned(), total_duration)
+            update_audiobook_duration(db, book_id.to_owned(), total_duration)
                 .await
                 .inspect_err(|e| tracing::error!("Err updating duration {}. {}", book.title, e))
                 .ok();
@@ -289,15 +299,15 @@ pub async fn scan_for_audiobooks(
     }
     let last_path_component = path
         .iter()
-        .last()
+        .next_back()
         .and_then(|s| s.to_str())
         .ok_or_else(|| ApiError::Internal("Invalid audiobook path".into()))?;
 
     let mut audio_books: Vec<AudioBook> = Vec::new();
-    let _ = recursive_dirscan(&path, &mut audio_books, last_path_component).await?;
-    let inserted_books = capture_files_cover_paths(audio_books, &db).await;
+    recursive_dirscan(&path, &mut audio_books, last_path_component).await?;
+    let inserted_books = capture_files_cover_paths(audio_books, db).await;
 
-    capture_metadata(inserted_books, &db).await?;
+    capture_metadata(inserted_books, db).await?;
 
     let audio_books = list_all_books(db).await?;
 
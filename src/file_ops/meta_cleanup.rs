@@ -1,9 +1,6 @@
-use crate::{
-    api::api_error::ApiError, db::meta_scan::get_grouped_files, models::meta_scan::FileScanCache,
-};
+use crate::models::meta_scan::FileScanCache;
 use lazy_static::lazy_static;
 use regex::Regex;
-use sqlx::SqlitePool;
 use strsim::levenshtein;
 
 lazy_static! {
@@ -18,12 +15,12 @@ lazy_static! {
     // static ref FILE_ORDER_TOKENS: Regex = Regex::new(r"(?i)\b(?:track|episode|ep|part|chapter)?\s*(-?\d+(?:-\d+)?)\b").unwrap();
 }
 
-fn is_dramatized(text: &String) -> bool {
+fn is_dramatized(text: &str) -> bool {
     fuzzy_contain(&text.to_lowercase(), "graphic audio", 2)
         || fuzzy_contain(&text.to_lowercase(), "dramatized", 2)
 }
 /// Clean metadata and extract bracket contents
-pub fn clean_metadata(text: &String) -> (String, Vec<String>) {
+pub fn clean_metadata(text: &str) -> (String, Vec<String>) {
     let mut bracket_info = Vec::new();
 
     let mut result = REMOVE_TERMS.replace(text, "").to_string();
@@ -52,22 +49,23 @@ pub fn clean_metadata(text: &String) -> (String, Vec<String>) {
 /// Extract order number if present (Book 1, Part 2, etc.)
 fn capture_disc_order(text: &str) -> Option<i64> {
     for caps in DISC_ORDER_TOKENS.captures_iter(text) {
-        if let Some(num) = caps.get(1) {
-            if let Ok(n) = num.as_str().parse::<i32>() {
-                return Some(n as i64);
-            }
+        if let Some(num) = caps.get(1)
+            && let Ok(n) = num.as_str().parse::<i32>()
+        {
+            return Some(n as i64);
         }
     }
     None
 }
 
+#[allow(dead_code)]
 fn assign_title_if_empty(metadata: &mut FileScanCache) {
     if metadata.title.is_none() || metadata.title == Some("".to_string()) {
         let fname = metadata.file_name.clone();
-        if let Some(n) = fname.split(".").nth(0) {
-            if !n.parse::<i64>().is_ok() {
-                metadata.title = Some(n.to_string());
-            }
+        if let Some(n) = fname.split(".").next()
+            && n.parse::<i64>().is_err()
+        {
+            metadata.title = Some(n.to_string());
         }
     }
 
@@ -84,7 +82,7 @@ fn series_cleanup(metadata: &mut FileScanCache) {
             metadata.dramatized = true;
         }
 
-        metadata.disc_number = capture_disc_order(&series);
+        metadata.disc_number = capture_disc_order(series);
 
         let (clean_series, extracted_info) = clean_metadata(series);
         metadata.clean_series = Some(clean_series);
@@ -117,7 +115,7 @@ fn author_cleanup(metadata: &mut FileScanCache) {
         if is_dramatized(author) {
             metadata.dramatized = true;
         }
-        clean_author = clean_metadata(&author).0;
+        clean_author = clean_metadata(author).0;
     }
     if !clean_author.is_empty() {
         metadata.author = Some(clean_author);
@@ -130,7 +128,7 @@ pub fn meta_cleanup(metadata: &mut FileScanCache) {
 
     // assign_track_number(metadata);
 
-    println!("");
+    println!();
     // assign_title_if_empty(metadata);
     // println!(
     //     "{} {}",
@@ -143,7 +141,7 @@ pub fn meta_cleanup(metadata: &mut FileScanCache) {
     // }
 }
 
-fn fuzzy_contain(text: &String, phrase: &str, threshold: usize) -> bool {
+fn fuzzy_contain(text: &str, phrase: &str, threshold: usize) -> bool {
     let clean_text: String = text
         .chars()
         .map(|c| {
@@ -166,12 +164,6 @@ fn fuzzy_contain(text: &String, phrase: &str, threshold: usize) -> bool {
     })
 }
 
-pub async fn grouped_meta_cleanup(db: &SqlitePool) -> Result<(), ApiError> {
-    //let grouped_data = group_meta_fetch(db).await?;
-    // grouped_data.
-    Ok(())
-}
-
 // fn assign_track_number(metadata: &mut FileScanCache) {
 //     if metadata.track_number.is_some() {
 //         return;
@@ -1,5 +1,8 @@
 pub mod book_cover;
+pub mod chapters;
+#[allow(clippy::module_inception)]
 pub mod file_ops;
 pub mod meta_cleanup;
 pub mod org_books;
 pub mod scan_files;
+pub mod zip_stream;
@@ -2,7 +2,10 @@ use sqlx::{Pool, Sqlite};
 
 use crate::{
     api::api_error::ApiError,
-    db::meta_scan::{apply_dbchanges, propagate_changes},
+    db::{
+        meta_scan::{apply_dbchanges, propagate_changes},
+        search::rebuild_search_index,
+    },
     models::meta_scan::ChangeDto,
 };
 
@@ -13,5 +16,6 @@ pub async fn save_organized_books(
 ) -> Result<(), ApiError> {
     apply_dbchanges(db, changes.clone()).await?;
     propagate_changes(db).await?; // TODO: verify if this affects files currently in progress
+    rebuild_search_index(db).await?;
     Ok(())
 }
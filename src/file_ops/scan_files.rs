@@ -1,11 +1,17 @@
-use std::{fs::File, io::BufReader, path::Path};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fs::File, io::BufReader, path::Path, time::UNIX_EPOCH};
 use walkdir::WalkDir;
 
 use crate::{
     api::api_error::ApiError,
-    db::meta_scan::save_meta,
-    file_ops::meta_cleanup::{grouped_meta_cleanup, meta_cleanup},
-    models::meta_scan::FileScanCache,
+    db::meta_scan::{find_by_hash, get_scan_index, mark_missing, move_scan_entry, save_meta},
+    file_ops::{
+        book_cover::{embedded_picture, store_cover, store_folder_cover},
+        chapters::extract_chapters,
+        meta_cleanup::meta_cleanup,
+    },
+    models::meta_scan::{FileScanCache, ScanIndexRow, ScanStats},
+    services::jobs::ScanJob,
 };
 
 use lofty::{
@@ -16,7 +22,13 @@ use lofty::{
 };
 
 use sqlx::SqlitePool;
-use tokio::fs;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt, SeekFrom},
+};
+use tracing::info;
+
+const HASH_WINDOW: u64 = 64 * 1024;
 
 pub async fn extract_besttag(tags: &[Tag]) -> Option<&Tag> {
     let priority = [
@@ -95,6 +107,16 @@ async fn extract_tag(
                 // }
             }
 
+            // Identical covers across a book's files hash to the same key
+            if let Some(picture) = embedded_picture(tagged_file.tags()) {
+                match store_cover(picture).await {
+                    Ok(key) => metadata.cover_art = Some(key),
+                    Err(e) => {
+                        tracing::warn!("Skipping embedded cover {} | {}", metadata.file_path, e)
+                    }
+                }
+            }
+
             let properties = tagged_file.properties();
 
             metadata.duration = properties.duration().as_millis() as i64;
@@ -102,7 +124,7 @@ async fn extract_tag(
         }
         Err(e) => {
             tracing::error!("Failed reading tagged file: {}", e);
-            // return Err(ApiError::Internal("Failed reading tagged file".into()));
+            return Err(ApiError::LoftyErr(e));
         }
     };
     Ok(())
@@ -115,23 +137,25 @@ pub async fn extract_metadata(metadata: &mut FileScanCache) -> Result<(), ApiErr
             &metadata.file_path,
             e.to_string()
         );
-    });
+    })?;
 
-    if let Ok(probe) = probe {
-        let probe = probe.options(ParseOptions::new().parsing_mode(ParsingMode::Relaxed));
+    let probe = probe
+        .options(ParseOptions::new().parsing_mode(ParsingMode::Relaxed))
+        .guess_file_type()
+        .inspect_err(|_| tracing::error!("Failed to guess file type {}", &metadata.file_path))?;
 
-        if let Ok(probe) = probe.guess_file_type() {
-            metadata.mime_type = get_mime_type(&probe.file_type());
-            let _ = extract_tag(probe, metadata).await;
-        } else {
-            tracing::error!("Failed to guess file type {}", &metadata.file_path);
-        }
+    metadata.mime_type = get_mime_type(&probe.file_type());
+    let tag_result = extract_tag(probe, metadata).await;
+
+    let chapters = extract_chapters(Path::new(&metadata.file_path), metadata.duration);
+    if !chapters.is_empty() {
+        metadata.chapters = serde_json::to_string(&chapters).ok();
     }
 
-    Ok(())
+    tag_result
 }
 
-async fn create_metadata(fpath: &Path) -> FileScanCache {
+async fn create_metadata(fpath: &Path, library_id: i64) -> FileScanCache {
     let file_name = fpath
         .file_name()
         .unwrap_or_default()
@@ -145,54 +169,207 @@ async fn create_metadata(fpath: &Path) -> FileScanCache {
 
     let path_owned = fpath.to_string_lossy().to_string();
 
-    let mut metadata = FileScanCache::new(path_owned, file_name, path_parent);
+    let mut metadata = FileScanCache::new(library_id, path_owned, file_name, path_parent);
     if let Ok(f_meta) = fs::metadata(&metadata.file_path).await {
         metadata.file_size = f_meta.len() as i64;
+        metadata.mtime = f_meta
+            .modified()
+            .ok()
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as i64);
     }
     metadata
 }
 
-pub async fn scan_files(path_str: &str, db: &SqlitePool) -> Result<i32, ApiError> {
-    let mut count = 0;
+/// Cheap content fingerprint: size plus the first and last 64 KiB.
+/// Good enough to recognise a renamed file without reading all of it.
+pub async fn partial_hash(path: &Path, size: u64) -> Result<String, ApiError> {
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    hasher.update(size.to_le_bytes());
+
+    let mut buf = vec![0u8; HASH_WINDOW.min(size) as usize];
+    file.read_exact(&mut buf).await?;
+    hasher.update(&buf);
+
+    if size > HASH_WINDOW {
+        let tail_start = size.saturating_sub(HASH_WINDOW).max(HASH_WINDOW);
+        let mut tail = vec![0u8; (size - tail_start) as usize];
+        file.seek(SeekFrom::Start(tail_start)).await?;
+        file.read_exact(&mut tail).await?;
+        hasher.update(&tail);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn is_audio_file(fpath: &Path) -> bool {
+    let ext = fpath
+        .extension()
+        .and_then(|f| f.to_str())
+        .map(|f| f.to_lowercase());
+
+    matches!(ext.as_deref(), Some("mp3" | "m4b" | "flac" | "m4a"))
+}
+
+// A file moved if an existing row has the same fingerprint and its old path is gone
+async fn find_moved_from(
+    db: &SqlitePool,
+    metadata: &FileScanCache,
+) -> Result<Option<ScanIndexRow>, ApiError> {
+    let Some(hash) = &metadata.hash else {
+        return Ok(None);
+    };
+
+    let candidates = find_by_hash(db, hash, metadata.file_size, metadata.library_id).await?;
+    for candidate in candidates {
+        if candidate.file_path != metadata.file_path
+            && fs::metadata(&candidate.file_path).await.is_err()
+        {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+/// Path prefix matching everything under `path_str`. The trailing separator
+/// keeps rescanning "Book" from claiming "Book 2".
+pub fn folder_prefix(path_str: &str) -> String {
+    if path_str.ends_with(std::path::MAIN_SEPARATOR) {
+        path_str.to_string()
+    } else {
+        format!("{}{}", path_str, std::path::MAIN_SEPARATOR)
+    }
+}
+
+/// Scans `path_str` for audio files and syncs them into `file_scan_cache`
+/// under `library_id`.
+/// Files whose size and mtime match the cache are skipped, renamed files are
+/// recognised by content hash and vanished files are flagged as missing.
+/// When run as a job, progress and per-file failures are reported to `job`
+/// and a cancelled job stops before the next file.
+pub async fn scan_files(
+    path_str: &str,
+    library_id: i64,
+    db: &SqlitePool,
+    job: Option<&ScanJob>,
+) -> Result<ScanStats, ApiError> {
+    let mut stats = ScanStats::default();
+    let mut cancelled = false;
+    // Loose folder images, looked up once per folder
+    let mut folder_covers: HashMap<String, Option<String>> = HashMap::new();
+
+    let prefix = folder_prefix(path_str);
+
+    let mut known: HashMap<String, ScanIndexRow> = get_scan_index(db, &prefix)
+        .await?
+        .into_iter()
+        .map(|row| (row.file_path.clone(), row))
+        .collect();
+
     for entry in WalkDir::new(path_str).contents_first(true) {
-        if let Ok(item) = entry {
-            if item.file_type().is_file() {
-                let fpath = item.path();
-
-                let ext = fpath
-                    .extension()
-                    .and_then(|f| f.to_str())
-                    .map(|f| f.to_lowercase());
-
-                // Skip execution if file isnt a valid format
-                if let Some(ext) = &ext {
-                    if !matches!(ext.as_str(), "mp3" | "m4b" | "flac" | "m4a") {
-                        continue;
+        if job.is_some_and(|j| j.is_cancelled()) {
+            cancelled = true;
+            break;
+        }
+
+        if let Ok(item) = entry
+            && item.file_type().is_file()
+        {
+            let fpath = item.path();
+
+            // Skip execution if file isnt a valid format
+            if !is_audio_file(fpath) {
+                continue;
+            }
+
+            stats.scanned += 1;
+            if let Some(job) = job {
+                job.file_processed();
+            }
+            let mut metadata = create_metadata(fpath, library_id).await;
+
+            if let Some(row) = known.remove(&metadata.file_path)
+                && !row.missing
+                && row.file_size == Some(metadata.file_size)
+                && row.mtime.is_some()
+                && row.mtime == metadata.mtime
+            {
+                stats.unchanged += 1;
+                continue;
+            }
+
+            match partial_hash(fpath, metadata.file_size as u64).await {
+                Ok(hash) => metadata.hash = Some(hash),
+                Err(e) => tracing::warn!("Failed to hash {} | {}", fpath.display(), e),
+            }
+
+            match find_moved_from(db, &metadata).await {
+                Ok(Some(previous)) => {
+                    info!(
+                        "Detected move {} -> {}",
+                        previous.file_path, metadata.file_path
+                    );
+                    known.remove(&previous.file_path);
+                    match move_scan_entry(db, previous.id, &previous.file_path, &metadata).await {
+                        Ok(()) => {
+                            stats.moved += 1;
+                            continue;
+                        }
+                        Err(e) => tracing::error!("Failed to record move {}", e),
                     }
-                } else {
-                    continue; // Skip if no extension present
                 }
+                Ok(None) => {}
+                Err(e) => tracing::error!("Failed to look up moved file {}", e),
+            }
 
-                let mut metadata = create_metadata(&fpath).await;
+            if let Err(e) = extract_metadata(&mut metadata).await {
+                tracing::error!("Failed to extract metadata {} | {}.", fpath.display(), e);
+                if let Some(job) = job {
+                    job.file_failed(&metadata.file_path, e.to_string());
+                }
+            }
 
-                if let Err(e) = extract_metadata(&mut metadata).await {
-                    tracing::error!("Failed to extract metadata {} | {}.", fpath.display(), e);
+            if metadata.cover_art.is_none() {
+                if !folder_covers.contains_key(&metadata.path_parent) {
+                    let key = store_folder_cover(Path::new(&metadata.path_parent)).await;
+                    folder_covers.insert(metadata.path_parent.clone(), key);
                 }
+                metadata.cover_art = folder_covers[&metadata.path_parent].clone();
+            }
 
-                meta_cleanup(&mut metadata);
-                if let Err(e) = save_meta(db, metadata).await {
-                    tracing::error!("Failed to save {}", e);
+            meta_cleanup(&mut metadata);
+            let file_path = metadata.file_path.clone();
+            if let Err(e) = save_meta(db, metadata).await {
+                tracing::error!("Failed to save {}", e);
+                if let Some(job) = job {
+                    job.file_failed(&file_path, e.to_string());
                 }
-                count += 1;
+                continue;
             }
+            stats.updated += 1;
         }
     }
 
+    // A partial walk says nothing about which files are gone
+    if cancelled {
+        return Ok(stats);
+    }
+
+    // Whatever is left in the index was not found on disk this time around
+    let vanished: Vec<i64> = known
+        .values()
+        .filter(|row| !row.missing)
+        .map(|row| row.id)
+        .collect();
+    stats.missing = vanished.len() as i32;
+    mark_missing(db, &vanished).await?;
+
     // Second db scan and cleanup
     // grouped_meta_cleanup(db).await;
 
     // capture the file name to look for clues on order of file
 
     // Cross referece the parents, grandparents to check for clues of series name or author name to verify
-    Ok(count)
+    Ok(stats)
 }
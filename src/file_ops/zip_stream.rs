@@ -0,0 +1,306 @@
+use std::path::PathBuf;
+
+use axum::body::{Body, Bytes};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use futures::stream;
+use tokio::{fs::File, io::AsyncReadExt, sync::mpsc};
+
+const LOCAL_HEADER_SIG: u32 = 0x04034b50;
+const DATA_DESCRIPTOR_SIG: u32 = 0x08074b50;
+const CENTRAL_HEADER_SIG: u32 = 0x02014b50;
+const ZIP64_EOCD_SIG: u32 = 0x06064b50;
+const ZIP64_LOCATOR_SIG: u32 = 0x07064b50;
+const EOCD_SIG: u32 = 0x06054b50;
+
+// bit 3: sizes/crc follow the data, bit 11: names are utf-8
+const FLAGS: u16 = 0x0808;
+const VERSION_ZIP: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+const READ_BUF_SIZE: usize = 64 * 1024;
+
+/// A file to be written as a stored (uncompressed) entry
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+}
+
+impl ZipEntry {
+    fn is_zip64(&self) -> bool {
+        self.size >= u32::MAX as u64
+    }
+}
+
+/// Stored-only ZIP writer that streams entries straight from disk.
+/// Because nothing is compressed the final archive size is known up front.
+pub struct ZipStream {
+    entries: Vec<ZipEntry>,
+}
+
+impl ZipStream {
+    pub fn new(entries: Vec<ZipEntry>) -> Self {
+        ZipStream { entries }
+    }
+
+    /// Exact number of bytes `into_body` will produce
+    pub fn content_length(&self) -> u64 {
+        let mut offset = 0u64;
+        let mut central_size = 0u64;
+
+        for entry in &self.entries {
+            central_size += central_header(entry, 0, offset).len() as u64;
+            offset += local_header(entry).len() as u64
+                + entry.size
+                + data_descriptor(entry, 0).len() as u64;
+        }
+
+        offset
+            + central_size
+            + end_of_central_dir(self.entries.len(), central_size, offset).len() as u64
+    }
+
+    /// Produces the archive as a streaming body. If a file disappears or
+    /// changes size mid-stream the body ends with an error so the client
+    /// sees an aborted transfer rather than a silently truncated archive.
+    pub fn into_body(self) -> Body {
+        let (tx, rx) = mpsc::channel::<std::io::Result<Bytes>>(4);
+
+        tokio::spawn(async move {
+            if let Err(e) = write_archive(&self.entries, &tx).await {
+                tracing::error!("Zip stream aborted: {}", e);
+                let _ = tx.send(Err(e)).await;
+            }
+        });
+
+        let body = stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|chunk| (chunk, rx))
+        });
+
+        Body::from_stream(body)
+    }
+}
+
+async fn write_archive(
+    entries: &[ZipEntry],
+    tx: &mpsc::Sender<std::io::Result<Bytes>>,
+) -> std::io::Result<()> {
+    let mut offset = 0u64;
+    let mut central = Vec::new();
+
+    for entry in entries {
+        let header = local_header(entry);
+        let header_offset = offset;
+        offset += header.len() as u64;
+        send(tx, header).await?;
+
+        let mut file = File::open(&entry.path).await?;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut written = 0u64;
+        let mut buf = vec![0u8; READ_BUF_SIZE];
+
+        loop {
+            let n = file.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            written += n as u64;
+            if written > entry.size {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            send(tx, buf[..n].to_vec()).await?;
+        }
+
+        if written != entry.size {
+            return Err(std::io::Error::other(format!(
+                "{} changed size while streaming",
+                entry.path.display()
+            )));
+        }
+
+        let crc = hasher.finalize();
+        let descriptor = data_descriptor(entry, crc);
+        offset += entry.size + descriptor.len() as u64;
+        send(tx, descriptor).await?;
+
+        central.extend(central_header(entry, crc, header_offset));
+    }
+
+    let central_size = central.len() as u64;
+    central.extend(end_of_central_dir(entries.len(), central_size, offset));
+    send(tx, central).await
+}
+
+async fn send(tx: &mpsc::Sender<std::io::Result<Bytes>>, data: Vec<u8>) -> std::io::Result<()> {
+    tx.send(Ok(Bytes::from(data)))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "client disconnected"))
+}
+
+fn dos_time(dt: &DateTime<Utc>) -> (u16, u16) {
+    // DOS dates cannot represent anything before 1980
+    if dt.year() < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let time = ((dt.hour() as u16) << 11) | ((dt.minute() as u16) << 5) | (dt.second() as u16 / 2);
+    let date = (((dt.year() - 1980) as u16) << 9) | ((dt.month() as u16) << 5) | dt.day() as u16;
+    (time, date)
+}
+
+fn local_header(entry: &ZipEntry) -> Vec<u8> {
+    let zip64 = entry.is_zip64();
+    let (time, date) = dos_time(&entry.modified);
+    let name = entry.name.as_bytes();
+
+    let mut buf = Vec::with_capacity(30 + name.len() + 20);
+    buf.extend(LOCAL_HEADER_SIG.to_le_bytes());
+    buf.extend(if zip64 { VERSION_ZIP64 } else { VERSION_ZIP }.to_le_bytes());
+    buf.extend(FLAGS.to_le_bytes());
+    buf.extend(0u16.to_le_bytes()); // stored
+    buf.extend(time.to_le_bytes());
+    buf.extend(date.to_le_bytes());
+    buf.extend(0u32.to_le_bytes()); // crc lives in the data descriptor
+    let size_marker = if zip64 { u32::MAX } else { 0 };
+    buf.extend(size_marker.to_le_bytes());
+    buf.extend(size_marker.to_le_bytes());
+    buf.extend((name.len() as u16).to_le_bytes());
+    buf.extend((if zip64 { 20u16 } else { 0 }).to_le_bytes());
+    buf.extend(name);
+
+    if zip64 {
+        buf.extend(0x0001u16.to_le_bytes());
+        buf.extend(16u16.to_le_bytes());
+        buf.extend(0u64.to_le_bytes());
+        buf.extend(0u64.to_le_bytes());
+    }
+    buf
+}
+
+fn data_descriptor(entry: &ZipEntry, crc: u32) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(24);
+    buf.extend(DATA_DESCRIPTOR_SIG.to_le_bytes());
+    buf.extend(crc.to_le_bytes());
+    if entry.is_zip64() {
+        buf.extend(entry.size.to_le_bytes());
+        buf.extend(entry.size.to_le_bytes());
+    } else {
+        buf.extend((entry.size as u32).to_le_bytes());
+        buf.extend((entry.size as u32).to_le_bytes());
+    }
+    buf
+}
+
+fn central_header(entry: &ZipEntry, crc: u32, offset: u64) -> Vec<u8> {
+    let (time, date) = dos_time(&entry.modified);
+    let name = entry.name.as_bytes();
+
+    // Zip64 extra only carries the fields that overflowed, in spec order
+    let mut extra = Vec::new();
+    if entry.is_zip64() {
+        extra.extend(entry.size.to_le_bytes());
+        extra.extend(entry.size.to_le_bytes());
+    }
+    if offset >= u32::MAX as u64 {
+        extra.extend(offset.to_le_bytes());
+    }
+    let zip64 = !extra.is_empty();
+
+    let size32 = if entry.is_zip64() {
+        u32::MAX
+    } else {
+        entry.size as u32
+    };
+    let offset32 = if offset >= u32::MAX as u64 {
+        u32::MAX
+    } else {
+        offset as u32
+    };
+    let version = if zip64 { VERSION_ZIP64 } else { VERSION_ZIP };
+
+    let mut buf = Vec::with_capacity(46 + name.len() + 4 + extra.len());
+    buf.extend(CENTRAL_HEADER_SIG.to_le_bytes());
+    buf.extend((0x0300 | version).to_le_bytes()); // made by unix
+    buf.extend(version.to_le_bytes());
+    buf.extend(FLAGS.to_le_bytes());
+    buf.extend(0u16.to_le_bytes());
+    buf.extend(time.to_le_bytes());
+    buf.extend(date.to_le_bytes());
+    buf.extend(crc.to_le_bytes());
+    buf.extend(size32.to_le_bytes());
+    buf.extend(size32.to_le_bytes());
+    buf.extend((name.len() as u16).to_le_bytes());
+    buf.extend((if zip64 { 4 + extra.len() as u16 } else { 0 }).to_le_bytes());
+    buf.extend(0u16.to_le_bytes()); // comment length
+    buf.extend(0u16.to_le_bytes()); // disk number
+    buf.extend(0u16.to_le_bytes()); // internal attributes
+    buf.extend((0o100644u32 << 16).to_le_bytes());
+    buf.extend(offset32.to_le_bytes());
+    buf.extend(name);
+
+    if zip64 {
+        buf.extend(0x0001u16.to_le_bytes());
+        buf.extend((extra.len() as u16).to_le_bytes());
+        buf.extend(extra);
+    }
+    buf
+}
+
+fn end_of_central_dir(count: usize, central_size: u64, central_offset: u64) -> Vec<u8> {
+    let zip64 = count >= u16::MAX as usize
+        || central_size >= u32::MAX as u64
+        || central_offset >= u32::MAX as u64;
+
+    let mut buf = Vec::with_capacity(98);
+    if zip64 {
+        let zip64_eocd_offset = central_offset + central_size;
+
+        buf.extend(ZIP64_EOCD_SIG.to_le_bytes());
+        buf.extend(44u64.to_le_bytes());
+        buf.extend((0x0300 | VERSION_ZIP64).to_le_bytes());
+        buf.extend(VERSION_ZIP64.to_le_bytes());
+        buf.extend(0u32.to_le_bytes());
+        buf.extend(0u32.to_le_bytes());
+        buf.extend((count as u64).to_le_bytes());
+        buf.extend((count as u64).to_le_bytes());
+        buf.extend(central_size.to_le_bytes());
+        buf.extend(central_offset.to_le_bytes());
+
+        buf.extend(ZIP64_LOCATOR_SIG.to_le_bytes());
+        buf.extend(0u32.to_le_bytes());
+        buf.extend(zip64_eocd_offset.to_le_bytes());
+        buf.extend(1u32.to_le_bytes());
+    }
+
+    let count16 = count.min(u16::MAX as usize) as u16;
+    buf.extend(EOCD_SIG.to_le_bytes());
+    buf.extend(0u16.to_le_bytes());
+    buf.extend(0u16.to_le_bytes());
+    buf.extend(count16.to_le_bytes());
+    buf.extend(count16.to_le_bytes());
+    buf.extend((central_size.min(u32::MAX as u64) as u32).to_le_bytes());
+    buf.extend((central_offset.min(u32::MAX as u64) as u32).to_le_bytes());
+    buf.extend(0u16.to_le_bytes());
+    buf
+}
+
+/// Archive name for `file_path` relative to the book folder. Files outside
+/// the folder fall back to their bare file name.
+pub fn relative_entry_name(book_root: &std::path::Path, file_path: &std::path::Path) -> String {
+    let relative = file_path.strip_prefix(book_root).unwrap_or_else(|_| {
+        file_path
+            .file_name()
+            .map(std::path::Path::new)
+            .unwrap_or(file_path)
+    });
+
+    relative
+        .components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -5,14 +5,21 @@ mod file_ops;
 mod models;
 mod services;
 use crate::{
+    api::auth_extractor::API_KEY_HEADER,
     config::Config,
-    db::cleanup,
-    file_ops::file_ops::scan_for_audiobooks,
-    services::startup::{init_logging, scan_files_startup, shutdown_signal},
+    db::libraries::{ensure_default_library, list_libraries},
+    services::{
+        events::EventHub,
+        jobs::JobRegistry,
+        keys::JwtKeys,
+        oidc::OidcClient,
+        startup::{init_logging, scan_files_startup, shutdown_signal},
+        watcher::spawn_library_watcher,
+    },
 };
 use axum::{
     Router,
-    http::{self, HeaderValue, Method, Request},
+    http::{self, Method, Request},
 };
 use dotenv::dotenv;
 use services::startup::ensure_admin_user;
@@ -29,6 +36,10 @@ use tracing::{Level, Span, info};
 pub struct AppState {
     pub db_pool: SqlitePool,
     pub config: Arc<Config>,
+    pub jobs: JobRegistry,
+    pub events: EventHub,
+    pub keys: Arc<JwtKeys>,
+    pub oidc: Option<Arc<OidcClient>>,
 }
 
 #[tokio::main]
@@ -37,24 +48,57 @@ async fn main() -> anyhow::Result<()> {
     init_logging();
 
     let config = Arc::new(Config::from_env().unwrap());
+    let keys = Arc::new(JwtKeys::from_config(&config)?);
+    let oidc = OidcClient::from_config(&config)?.map(Arc::new);
     let db_pool = db::init_db_pool(&config.database_url)
         .await
         .expect("Err connecting to database");
 
     // let _ = cleanup(&db_pool).await;
-    ensure_admin_user(&db_pool).await.unwrap();
+    ensure_admin_user(&db_pool, &config).await.unwrap();
+    ensure_default_library(&db_pool, &config.book_files).await?;
     let _ = scan_files_startup(&config.book_files, &db_pool).await;
 
+    let events = EventHub::new();
+    let jobs = JobRegistry::new(events.clone());
+    if config.watch_library {
+        for library in list_libraries(&db_pool).await? {
+            if !library.root_path.is_empty() {
+                spawn_library_watcher(
+                    library.id,
+                    library.root_path,
+                    db_pool.clone(),
+                    jobs.clone(),
+                    events.clone(),
+                );
+            }
+        }
+    }
+
     let state = AppState {
-        db_pool: db_pool,
+        db_pool,
         config: Arc::clone(&config),
+        jobs,
+        events,
+        keys,
+        oidc,
     };
 
     let cors = CorsLayer::new()
         // .allow_origin("http://localhost:3001".parse::<HeaderValue>().unwrap())
         .allow_origin(Any) // allows all origins
-        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
-        .allow_headers([http::header::CONTENT_TYPE, http::header::AUTHORIZATION]);
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        .allow_headers([
+            http::header::CONTENT_TYPE,
+            http::header::AUTHORIZATION,
+            http::header::HeaderName::from_static(API_KEY_HEADER),
+        ]);
     let app = Router::new()
         .nest("/api", api::routes().await)
         .with_state(state)
@@ -113,10 +157,13 @@ async fn main() -> anyhow::Result<()> {
 
     let addr: SocketAddr = "0.0.0.0:3000".parse().unwrap();
     info!(%addr, "listening");
-    axum::serve(tokio::net::TcpListener::bind(addr).await.unwrap(), app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .unwrap();
+    axum::serve(
+        tokio::net::TcpListener::bind(addr).await.unwrap(),
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .unwrap();
 
     Ok(())
 }
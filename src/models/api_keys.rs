@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Marks a bearer token as an API key rather than a JWT
+pub const API_KEY_PREFIX: &str = "ewk_";
+/// Longest lifetime a key can be created with, besides never expiring
+pub const MAX_API_KEY_TTL_DAYS: i64 = 5 * 365;
+
+/// Read only keys may only make GET and HEAD requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum ApiKeyScope {
+    #[default]
+    ReadOnly,
+    Full,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    pub prefix: String,
+    pub scope: ApiKeyScope,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyDto {
+    pub name: String,
+    #[serde(default)]
+    pub scope: ApiKeyScope,
+    /// Never expires when omitted
+    pub expires_in_days: Option<i64>,
+}
+
+/// Live key and the user it belongs to, as looked up on each request
+#[derive(Debug, FromRow)]
+pub struct ApiKeyOwner {
+    pub id: i64,
+    pub scope: ApiKeyScope,
+    pub user_id: i64,
+    pub username: String,
+    pub is_admin: bool,
+    pub must_change_password: bool,
+    pub stale: bool,
+}
@@ -1,9 +1,11 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct AudioBookRow {
     pub id: i64,
+    pub library_id: i64,
     pub author: String,
     pub series: Option<String>,
     pub title: String,
@@ -13,6 +15,152 @@ pub struct AudioBookRow {
     pub metadata: Option<String>,
 }
 
+/// Versioned so clients can cache the image until the cover changes
+pub fn book_cover_url(book_id: i64, cover_art: Option<&str>) -> Option<String> {
+    cover_art.map(|key| {
+        format!(
+            "/api/books/{}/cover?v={}",
+            book_id,
+            key.chars().take(12).collect::<String>()
+        )
+    })
+}
+
+impl AudioBookRow {
+    pub fn cover_url(&self) -> Option<String> {
+        book_cover_url(self.id, self.cover_art.as_deref())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BookSort {
+    #[default]
+    Title,
+    Author,
+    #[serde(alias = "recently_added")]
+    CreatedAt,
+    RecentlyListened,
+    Duration,
+}
+
+impl BookSort {
+    /// Direction used when the client does not pass `order`
+    pub fn default_order(&self) -> SortOrder {
+        match self {
+            BookSort::Title | BookSort::Author | BookSort::Duration => SortOrder::Asc,
+            BookSort::CreatedAt | BookSort::RecentlyListened => SortOrder::Desc,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Listening state of a book for the calling user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum CompletionState {
+    NotStarted,
+    InProgress,
+    Finished,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BookListQuery {
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub sort: BookSort,
+    pub order: Option<SortOrder>,
+    pub library_id: Option<i64>,
+    pub author: Option<String>,
+    pub series: Option<String>,
+    pub narrator: Option<String>,
+    pub dramatized: Option<bool>,
+    pub completion: Option<CompletionState>,
+}
+
+/// Position after the last book of a page. Opaque to clients, and only
+/// valid for the sort and order it was issued with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookCursor {
+    pub sort: BookSort,
+    pub order: SortOrder,
+    pub key: serde_json::Value,
+    pub id: i64,
+}
+
+impl BookCursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(value: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(value).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+#[derive(Debug, FromRow)]
+pub struct BookListRow {
+    #[sqlx(flatten)]
+    pub book: AudioBookRow,
+    pub created_at: String,
+    pub narrators: Option<String>,
+    pub dramatized: bool,
+    pub completion: CompletionState,
+    pub last_listened: Option<String>,
+}
+
+/// One page of the book list, `total` counts every book matching the filters
+#[derive(Debug)]
+pub struct BookPage {
+    pub books: Vec<BookListRow>,
+    pub total: i64,
+    pub next_cursor: Option<String>,
+}
+
+/// Book as returned by the book list, with a link to its cover
+#[derive(Debug, Serialize)]
+pub struct BookListItem {
+    #[serde(flatten)]
+    pub book: AudioBookRow,
+    pub cover_url: Option<String>,
+    pub created_at: String,
+    pub narrators: Vec<String>,
+    pub dramatized: bool,
+    pub completion: CompletionState,
+    pub last_listened: Option<String>,
+}
+
+impl From<BookListRow> for BookListItem {
+    fn from(row: BookListRow) -> Self {
+        BookListItem {
+            cover_url: row.book.cover_url(),
+            book: row.book,
+            created_at: row.created_at,
+            narrators: row
+                .narrators
+                .map(|n| n.split('\u{1f}').map(str::to_string).collect())
+                .unwrap_or_default(),
+            dramatized: row.dramatized,
+            completion: row.completion,
+            last_listened: row.last_listened,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CoverQuery {
+    pub size: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AudioBook {
     pub author: String,
@@ -33,10 +181,10 @@ impl AudioBook {
         content_path: String,
     ) -> AudioBook {
         AudioBook {
-            author: author,
-            series: series,
-            title: title,
-            content_path: content_path,
+            author,
+            series,
+            title,
+            content_path,
             cover_art: None,
             duration: 0,
             metadata: None,
@@ -78,13 +226,59 @@ impl CreateFileMetadata {
     ) -> CreateFileMetadata {
         CreateFileMetadata {
             book_id: -99,
-            file_id: file_id,
-            file_name: file_name,
-            file_path: file_path,
-            duration: duration,
-            channels: channels,
-            sample_rate: sample_rate,
-            bitrate: bitrate,
+            file_id,
+            file_name,
+            file_path,
+            duration,
+            channels,
+            sample_rate,
+            bitrate,
         }
     }
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chapter {
+    pub title: Option<String>,
+    pub start_ms: i64,
+    pub end_ms: i64,
+}
+
+#[derive(Debug, FromRow)]
+pub struct ChapterRow {
+    pub file_id: i64,
+    pub title: Option<String>,
+    pub start_ms: i64,
+    pub end_ms: i64,
+}
+
+// Chapter positioned on the whole book timeline, spanning multi-file books
+#[derive(Debug, Serialize)]
+pub struct BookChapter {
+    pub index: i64,
+    pub title: String,
+    pub file_id: i64,
+    pub file_start_ms: i64,
+    pub file_end_ms: i64,
+    pub start_ms: i64,
+    pub end_ms: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cover_url_version_is_char_bounded() {
+        assert_eq!(
+            book_cover_url(7, Some("0123456789abcdef")).as_deref(),
+            Some("/api/books/7/cover?v=0123456789ab")
+        );
+        // Multi-byte keys must not be split inside a character
+        assert_eq!(
+            book_cover_url(7, Some("ééééééééééééé")).as_deref(),
+            Some("/api/books/7/cover?v=éééééééééééé")
+        );
+        assert_eq!(book_cover_url(7, None), None);
+    }
+}
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+
+macro_rules! audit_actions {
+    ($($variant:ident => $name:literal,)*) => {
+        /// Everything the audit log records, stored by its dotted name
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum AuditAction {
+            $($variant,)*
+        }
+
+        impl AuditAction {
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(AuditAction::$variant => $name,)*
+                }
+            }
+        }
+    };
+}
+
+audit_actions! {
+    LoginSucceeded => "login.success",
+    LoginFailed => "login.failure",
+    OidcLogin => "login.oidc",
+    Logout => "logout",
+    RefreshTokenReused => "session.token_reuse",
+    UserCreated => "user.create",
+    UserRegistered => "user.register",
+    UserDeleted => "user.delete",
+    RoleChanged => "user.role",
+    UserDisabled => "user.disable",
+    UserEnabled => "user.enable",
+    PasswordReset => "user.password_reset",
+    PasswordChanged => "user.password_change",
+    UserPermissionsChanged => "user.permissions",
+    GroupCreated => "group.create",
+    GroupUpdated => "group.update",
+    GroupDeleted => "group.delete",
+    GroupMembersChanged => "group.members",
+    InviteCreated => "invite.create",
+    InviteRevoked => "invite.revoke",
+    LockoutCleared => "lockout.clear",
+    ApiKeyCreated => "api_key.create",
+    ApiKeyRevoked => "api_key.revoke",
+    LibraryCreated => "library.create",
+    LibraryUpdated => "library.update",
+    LibraryDeleted => "library.delete",
+    LibraryAccessChanged => "library.access",
+    LibraryOrganized => "library.organize",
+    ScanStarted => "scan.start",
+    ScanCancelled => "scan.cancel",
+    FileUploaded => "upload",
+}
+
+#[derive(Debug, FromRow)]
+pub struct AuditRow {
+    pub id: i64,
+    pub actor_id: Option<i64>,
+    pub actor_name: Option<String>,
+    pub action: String,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub before_json: Option<String>,
+    pub after_json: Option<String>,
+    pub request_id: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub actor_id: Option<i64>,
+    pub actor_name: Option<String>,
+    pub action: String,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub request_id: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: String,
+}
+
+fn parse_json(value: Option<String>) -> Option<Value> {
+    value.and_then(|v| serde_json::from_str(&v).ok())
+}
+
+impl From<AuditRow> for AuditEntry {
+    fn from(row: AuditRow) -> Self {
+        AuditEntry {
+            id: row.id,
+            actor_id: row.actor_id,
+            actor_name: row.actor_name,
+            action: row.action,
+            target_type: row.target_type,
+            target_id: row.target_id,
+            before: parse_json(row.before_json),
+            after: parse_json(row.after_json),
+            request_id: row.request_id,
+            ip_address: row.ip_address,
+            created_at: row.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub actor_id: Option<i64>,
+    /// Username at the time of the action
+    pub actor: Option<String>,
+    /// Exact action, or a prefix when it ends in `*`, e.g. `user.*`
+    pub action: Option<String>,
+    /// RFC 3339 timestamp or a plain date, inclusive
+    pub since: Option<String>,
+    /// RFC 3339 timestamp or a plain date, exclusive
+    pub until: Option<String>,
+    pub limit: Option<i64>,
+    /// Id of the last entry of the previous page
+    pub before_id: Option<i64>,
+}
+
+/// Filters after validation, times normalised to SQLite's format
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub actor_id: Option<i64>,
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub action_prefix: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub before_id: Option<i64>,
+}
@@ -0,0 +1,66 @@
+use serde::Serialize;
+
+use crate::models::{meta_scan::ScanStats, user::Progress};
+
+/// Pushed to a user's connected devices over `/api/events`. The variant
+/// name is sent as the SSE event name.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    ProgressChanged {
+        progress: Progress,
+    },
+    /// Books were added or changed, refetch the library
+    LibraryUpdated {
+        library_id: i64,
+        reason: LibraryUpdateReason,
+        stats: Option<ScanStats>,
+    },
+    SessionRevoked {
+        session_id: String,
+    },
+    /// Every session except `except_session_id` was signed out
+    AllSessionsRevoked {
+        except_session_id: Option<String>,
+    },
+    ApiKeyRevoked {
+        key_id: i64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LibraryUpdateReason {
+    Scan,
+    Organize,
+}
+
+impl ServerEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ServerEvent::ProgressChanged { .. } => "progress_changed",
+            ServerEvent::LibraryUpdated { .. } => "library_updated",
+            ServerEvent::SessionRevoked { .. } => "session_revoked",
+            ServerEvent::AllSessionsRevoked { .. } => "all_sessions_revoked",
+            ServerEvent::ApiKeyRevoked { .. } => "api_key_revoked",
+        }
+    }
+
+    /// Whether the stream opened with `session_id` must close after this
+    pub fn ends_session(&self, session_id: &str) -> bool {
+        match self {
+            ServerEvent::SessionRevoked {
+                session_id: revoked,
+            } => revoked == session_id,
+            ServerEvent::AllSessionsRevoked { except_session_id } => {
+                except_session_id.as_deref() != Some(session_id)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the stream opened with API key `key_id` must close after this
+    pub fn ends_api_key(&self, key_id: i64) -> bool {
+        matches!(self, ServerEvent::ApiKeyRevoked { key_id: revoked } if *revoked == key_id)
+    }
+}
@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Lifetime of an invite when the admin does not pick one
+pub const DEFAULT_INVITE_TTL_HOURS: i64 = 72;
+pub const MAX_INVITE_TTL_HOURS: i64 = 30 * 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum InviteStatus {
+    Pending,
+    Used,
+    Expired,
+}
+
+#[derive(Debug, FromRow)]
+pub struct InviteRow {
+    pub id: i64,
+    pub created_by: Option<i64>,
+    pub note: Option<String>,
+    pub is_admin: bool,
+    pub library_ids: Option<String>,
+    pub group_ids: Option<String>,
+    pub expires_at: String,
+    pub created_at: String,
+    pub used_at: Option<String>,
+    pub used_by: Option<i64>,
+    pub status: InviteStatus,
+}
+
+/// Invite as listed to admins, the token itself is only shown on creation
+#[derive(Debug, Serialize)]
+pub struct Invite {
+    pub id: i64,
+    pub created_by: Option<i64>,
+    pub note: Option<String>,
+    pub is_admin: bool,
+    pub library_ids: Option<Vec<i64>>,
+    pub group_ids: Option<Vec<i64>>,
+    pub expires_at: String,
+    pub created_at: String,
+    pub used_at: Option<String>,
+    pub used_by: Option<i64>,
+    pub status: InviteStatus,
+}
+
+/// Parses an id list column, NULL meaning the defaults apply
+pub fn parse_ids(value: Option<&str>) -> Option<Vec<i64>> {
+    value.and_then(|v| serde_json::from_str(v).ok())
+}
+
+impl From<InviteRow> for Invite {
+    fn from(row: InviteRow) -> Self {
+        Invite {
+            id: row.id,
+            created_by: row.created_by,
+            note: row.note,
+            is_admin: row.is_admin,
+            library_ids: parse_ids(row.library_ids.as_deref()),
+            group_ids: parse_ids(row.group_ids.as_deref()),
+            expires_at: row.expires_at,
+            created_at: row.created_at,
+            used_at: row.used_at,
+            used_by: row.used_by,
+            status: row.status,
+        }
+    }
+}
+
+/// What the account created from an invite is granted
+#[derive(Debug, FromRow)]
+pub struct InviteGrant {
+    pub id: i64,
+    pub is_admin: bool,
+    pub library_ids: Option<String>,
+    pub group_ids: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInviteDto {
+    /// Reminder of who the invite is for, e.g. "Grandma"
+    pub note: Option<String>,
+    #[serde(default)]
+    pub is_admin: bool,
+    /// Libraries the new user can see, the default library when omitted
+    pub library_ids: Option<Vec<i64>>,
+    /// Groups the new user joins, the default group when omitted
+    pub group_ids: Option<Vec<i64>>,
+    pub expires_in_hours: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterDto {
+    pub token: String,
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub device_name: Option<String>,
+}
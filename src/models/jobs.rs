@@ -0,0 +1,48 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::models::meta_scan::ScanStats;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobPhase {
+    Queued,
+    Scanning,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+impl JobPhase {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            JobPhase::Completed | JobPhase::Cancelled | JobPhase::Failed
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedFile {
+    pub file_path: String,
+    pub reason: String,
+}
+
+/// Snapshot of a job as reported by `GET /api/jobs/{id}`
+#[derive(Debug, Clone, Serialize)]
+pub struct JobReport {
+    pub id: String,
+    pub kind: String,
+    pub library_id: i64,
+    pub root: String,
+    pub requested_by: i64,
+    pub phase: JobPhase,
+    pub files_processed: i32,
+    pub files_failed: i32,
+    pub failures: Vec<FailedFile>,
+    pub stats: Option<ScanStats>,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub elapsed_ms: i64,
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Library created by the initial migration for the old single root
+pub const DEFAULT_LIBRARY_ID: i64 = 1;
+
+pub const MEDIA_TYPES: [&str; 2] = ["audiobook", "podcast"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Library {
+    pub id: i64,
+    pub name: String,
+    pub root_path: String,
+    pub media_type: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LibraryDto {
+    pub name: String,
+    pub root_path: String,
+    pub media_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LibraryUpdateDto {
+    pub name: Option<String>,
+    pub root_path: Option<String>,
+    pub media_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LibraryAccessDto {
+    pub user_ids: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct LibraryMember {
+    pub user_id: i64,
+    pub username: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScanQuery {
+    pub library_id: Option<i64>,
+}
@@ -0,0 +1,166 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::FromRow;
+
+/// A pause longer than this ends a listening session
+pub const LISTENING_IDLE_MINUTES: i64 = 10;
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ListeningSession {
+    pub id: i64,
+    pub book_id: i64,
+    pub device_id: Option<String>,
+    pub start_file_id: i64,
+    pub start_offset_ms: i64,
+    pub end_file_id: i64,
+    pub end_offset_ms: i64,
+    pub playback_rate: f64,
+    pub started_at: DateTime<Utc>,
+    pub last_active_at: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub duration_ms: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartListeningDto {
+    pub book_id: i64,
+    pub file_id: i64,
+    #[serde(default)]
+    pub offset_ms: i64,
+    pub device_id: Option<String>,
+    pub playback_rate: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StopListeningDto {
+    pub file_id: i64,
+    pub offset_ms: i64,
+}
+
+/// Where a session starts or ends, and when
+#[derive(Debug)]
+pub struct ListeningPoint<'a> {
+    pub book_id: i64,
+    pub file_id: i64,
+    pub offset_ms: i64,
+    pub device_id: Option<&'a str>,
+    pub playback_rate: Option<f64>,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListeningHistoryQuery {
+    pub book_id: Option<i64>,
+    pub limit: Option<i64>,
+    /// Id of the last session of the previous page
+    pub before_id: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    /// First day, inclusive. Defaults to 30 days before `to`.
+    pub from: Option<NaiveDate>,
+    /// Last day, inclusive. Defaults to today.
+    pub to: Option<NaiveDate>,
+    /// Offset of the caller's timezone, days are counted in local time
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct DayTotal {
+    pub day: NaiveDate,
+    pub listened_ms: i64,
+    pub sessions: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WeekTotal {
+    /// Monday the week starts on
+    pub week_start: NaiveDate,
+    pub listened_ms: i64,
+    pub sessions: i64,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct BookTotal {
+    pub book_id: i64,
+    pub title: String,
+    pub author: String,
+    pub listened_ms: i64,
+    pub sessions: i64,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct AuthorTotal {
+    pub author: String,
+    pub listened_ms: i64,
+    pub books: i64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Streak {
+    /// Consecutive days with listening up to today, or up to yesterday when
+    /// nothing has been played yet today
+    pub current_days: i64,
+    pub longest_days: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ListeningStats {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub listened_ms: i64,
+    pub sessions: i64,
+    pub daily: Vec<DayTotal>,
+    pub weekly: Vec<WeekTotal>,
+    pub books: Vec<BookTotal>,
+    pub authors: Vec<AuthorTotal>,
+    pub streak: Streak,
+}
+
+impl Streak {
+    /// `days` must be sorted and without duplicates
+    pub fn from_days(days: &[NaiveDate], today: NaiveDate) -> Self {
+        let mut streak = Streak::default();
+        let mut run = 0;
+        let mut previous: Option<NaiveDate> = None;
+
+        for &day in days.iter().filter(|d| **d <= today) {
+            run = match previous {
+                Some(p) if p.succ_opt() == Some(day) => run + 1,
+                _ => 1,
+            };
+            streak.longest_days = streak.longest_days.max(run);
+            previous = Some(day);
+        }
+
+        if let Some(last) = previous
+            && (last == today || last.succ_opt() == Some(today))
+        {
+            streak.current_days = run;
+        }
+        streak
+    }
+}
+
+/// Rolls daily totals up into weeks starting on Monday
+pub fn weekly_totals(daily: &[DayTotal]) -> Vec<WeekTotal> {
+    let mut weeks: Vec<WeekTotal> = Vec::new();
+    for day in daily {
+        let week_start =
+            day.day - chrono::Duration::days(day.day.weekday().num_days_from_monday().into());
+        match weeks.last_mut() {
+            Some(week) if week.week_start == week_start => {
+                week.listened_ms += day.listened_ms;
+                week.sessions += day.sessions;
+            }
+            _ => weeks.push(WeekTotal {
+                week_start,
+                listened_ms: day.listened_ms,
+                sessions: day.sessions,
+            }),
+        }
+    }
+    weeks
+}
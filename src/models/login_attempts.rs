@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// What a row of `login_attempts` counts failures against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum AttemptScope {
+    User,
+    Ip,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct LoginAttempt {
+    pub scope: AttemptScope,
+    pub key: String,
+    pub failures: i64,
+    pub last_failure_at: i64,
+    pub locked_until: Option<i64>,
+    // Logins reserved but not yet verified, including the caller's own
+    #[serde(skip)]
+    pub pending: i64,
+}
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
 use sqlx::sqlite::{SqliteTypeInfo, SqliteValueRef};
-use sqlx::{Decode, Encode, Sqlite, Type};
+use sqlx::{Decode, Sqlite, Type};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(i64)]
@@ -22,6 +22,7 @@ impl ResolvedStatus {
         }
     }
 
+    #[allow(dead_code)]
     pub const fn from_value(value: i64) -> Option<Self> {
         match value {
             0 => Some(ResolvedStatus::UnResolved),
@@ -63,7 +64,7 @@ impl<'r> Decode<'r, Sqlite> for ResolvedStatus {
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct FileScanCache {
-    // pub library_id: i64,
+    pub library_id: i64,
     pub author: Option<String>,
     pub title: Option<String>,
     pub clean_title: Option<String>,
@@ -88,15 +89,23 @@ pub struct FileScanCache {
     pub extracts: Option<String>,
     pub raw_metadata: Option<String>,
     pub hash: Option<String>,
+    pub chapters: Option<String>,
+    pub mtime: Option<i64>,
     pub resolve_status: ResolvedStatus,
 }
 
 impl FileScanCache {
-    pub fn new(file_path: String, file_name: String, path_parent: String) -> FileScanCache {
+    pub fn new(
+        library_id: i64,
+        file_path: String,
+        file_name: String,
+        path_parent: String,
+    ) -> FileScanCache {
         FileScanCache {
-            file_path: file_path,
-            file_name: file_name,
-            path_parent: path_parent,
+            library_id,
+            file_path,
+            file_name,
+            path_parent,
             dramatized: false,
             duration: 0,
             file_size: 0,
@@ -118,36 +127,62 @@ impl FileScanCache {
             extracts: None,
             raw_metadata: None,
             hash: None,
+            chapters: None,
+            mtime: None,
             resolve_status: ResolvedStatus::UnResolved,
         }
     }
 }
 
-#[derive(Serialize, Debug)]
-pub struct FileInfo {
+// What a rescan needs to know to decide whether a file changed
+#[derive(Debug, Clone, FromRow)]
+pub struct ScanIndexRow {
     pub id: i64,
-    pub title: String,
-    pub series: String,
     pub file_path: String,
-    pub path_parent: String,
-    pub file_name: String,
+    pub file_size: Option<i64>,
+    pub mtime: Option<i64>,
+    pub missing: bool,
 }
 
-#[derive(Serialize)]
-pub struct BookInfo {
-    pub series: String,
-    pub files: Vec<FileInfo>,
+// Fields `apply_dbchanges` can rewrite, recorded before and after in the audit log
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct OrganizedFile {
+    pub id: i64,
+    pub library_id: i64,
+    pub file_path: String,
+    pub author: Option<String>,
+    pub clean_series: Option<String>,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ScanStats {
+    pub scanned: i32,
+    pub unchanged: i32,
+    pub updated: i32,
+    pub moved: i32,
+    pub missing: i32,
 }
 
-#[derive(Serialize)]
-pub struct AuthorInfo {
-    pub books: Vec<BookInfo>,
+// Totals across several scans, e.g. the folders of one watcher rescan
+impl std::ops::AddAssign for ScanStats {
+    fn add_assign(&mut self, other: Self) {
+        self.scanned += other.scanned;
+        self.unchanged += other.unchanged;
+        self.updated += other.updated;
+        self.moved += other.moved;
+        self.missing += other.missing;
+    }
 }
 
-#[derive(Serialize)]
-pub struct FileScanGrouped {
+#[derive(Serialize, Debug)]
+pub struct FileInfo {
+    pub id: i64,
+    pub title: String,
     pub series: String,
-    pub authors: Vec<AuthorInfo>,
+    pub file_path: String,
+    pub path_parent: String,
+    pub file_name: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -1,3 +1,15 @@
+pub mod api_keys;
 pub mod audiobooks;
+pub mod audit;
+pub mod events;
+pub mod invites;
+pub mod jobs;
+pub mod libraries;
+pub mod listening;
+pub mod login_attempts;
 pub mod meta_scan;
+pub mod oidc;
+pub mod permissions;
+pub mod search;
+pub mod sessions;
 pub mod user;
@@ -0,0 +1,47 @@
+use serde::Deserialize;
+use sqlx::FromRow;
+
+/// Subset of the provider's `.well-known/openid-configuration`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+    pub userinfo_endpoint: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenSet {
+    pub id_token: Option<String>,
+    pub access_token: Option<String>,
+}
+
+/// Who the provider says signed in, after claim mapping
+#[derive(Debug)]
+pub struct OidcIdentity {
+    pub subject: String,
+    pub username: String,
+    pub email: Option<String>,
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, FromRow)]
+pub struct PendingLogin {
+    pub nonce: String,
+    pub code_verifier: String,
+    pub device_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcLoginQuery {
+    pub device_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
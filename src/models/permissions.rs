@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Group every new user joins unless other groups are given
+pub const DEFAULT_GROUP_ID: i64 = 1;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+pub enum Permission {
+    #[serde(rename = "library.scan")]
+    #[sqlx(rename = "library.scan")]
+    LibraryScan,
+    #[serde(rename = "library.organize")]
+    #[sqlx(rename = "library.organize")]
+    LibraryOrganize,
+    #[serde(rename = "library.manage")]
+    #[sqlx(rename = "library.manage")]
+    LibraryManage,
+    #[serde(rename = "upload")]
+    #[sqlx(rename = "upload")]
+    Upload,
+    #[serde(rename = "download")]
+    #[sqlx(rename = "download")]
+    Download,
+    #[serde(rename = "users.manage")]
+    #[sqlx(rename = "users.manage")]
+    UsersManage,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 6] = [
+        Permission::LibraryScan,
+        Permission::LibraryOrganize,
+        Permission::LibraryManage,
+        Permission::Upload,
+        Permission::Download,
+        Permission::UsersManage,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::LibraryScan => "library.scan",
+            Permission::LibraryOrganize => "library.organize",
+            Permission::LibraryManage => "library.manage",
+            Permission::Upload => "upload",
+            Permission::Download => "download",
+            Permission::UsersManage => "users.manage",
+        }
+    }
+
+    /// Whether the permission only reads, so a read-only API key may use it
+    pub fn is_read(&self) -> bool {
+        matches!(self, Permission::Download)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Permission::LibraryScan => "Start and cancel library scans",
+            Permission::LibraryOrganize => "Review scanned files and organise them into books",
+            Permission::LibraryManage => "Create, edit and delete libraries and their access lists",
+            Permission::Upload => "Upload audio files",
+            Permission::Download => "Stream and download books",
+            Permission::UsersManage => "Manage non admin users and login lockouts",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct GroupRow {
+    pub id: i64,
+    pub name: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Group {
+    #[serde(flatten)]
+    pub group: GroupRow,
+    pub permissions: Vec<Permission>,
+    pub user_ids: Vec<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GroupDto {
+    pub name: String,
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GroupUpdateDto {
+    pub name: Option<String>,
+    pub permissions: Option<Vec<Permission>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GroupMembersDto {
+    pub user_ids: Vec<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserPermissionsDto {
+    pub permissions: Vec<Permission>,
+}
+
+/// Where a user's permissions come from
+#[derive(Debug, Serialize)]
+pub struct UserPermissions {
+    pub user_id: i64,
+    pub is_admin: bool,
+    pub direct: Vec<Permission>,
+    pub group_ids: Vec<i64>,
+    pub effective: Vec<Permission>,
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::models::audiobooks::book_cover_url;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, FromRow)]
+pub struct SearchRow {
+    pub id: i64,
+    pub author: String,
+    pub series: Option<String>,
+    pub title: String,
+    pub cover_art: Option<String>,
+    pub duration: i64,
+    pub title_hl: Option<String>,
+    pub author_hl: Option<String>,
+    pub series_hl: Option<String>,
+    pub narrator_hl: Option<String>,
+    pub files_hl: Option<String>,
+    pub score: f64,
+}
+
+/// Matched fields with hits wrapped in `<mark>`. Fields without a hit are omitted.
+#[derive(Debug, Serialize)]
+pub struct SearchHighlights {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub series: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub narrator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_names: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    pub id: i64,
+    pub author: String,
+    pub series: Option<String>,
+    pub title: String,
+    pub duration: i64,
+    pub cover_url: Option<String>,
+    // bm25, lower is a better match
+    pub score: f64,
+    pub highlights: SearchHighlights,
+}
+
+fn matched(field: Option<String>) -> Option<String> {
+    field.filter(|f| f.contains("<mark>"))
+}
+
+impl From<SearchRow> for SearchHit {
+    fn from(row: SearchRow) -> Self {
+        SearchHit {
+            cover_url: book_cover_url(row.id, row.cover_art.as_deref()),
+            id: row.id,
+            author: row.author,
+            series: row.series,
+            title: row.title,
+            duration: row.duration,
+            score: row.score,
+            highlights: SearchHighlights {
+                title: matched(row.title_hl),
+                author: matched(row.author_hl),
+                series: matched(row.series_hl),
+                narrator: matched(row.narrator_hl),
+                file_names: matched(row.files_hl),
+            },
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Signed in device, refresh token hashes never leave the database
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Session {
+    pub id: String,
+    pub user_id: i64,
+    pub device_name: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: String,
+    pub last_seen_at: String,
+    pub expires_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionInfo {
+    #[serde(flatten)]
+    pub session: Session,
+    /// Session the request was made with
+    pub current: bool,
+}
+
+/// Current state of the user behind a live session
+#[derive(Debug, Clone, Copy, FromRow)]
+pub struct SessionUser {
+    pub is_admin: bool,
+    pub must_change_password: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshDto {
+    pub refresh_token: String,
+}
+
+/// Returned by login and refresh. `token` is the short lived access token.
+#[derive(Debug, Serialize)]
+pub struct TokenResponse {
+    pub token: String,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+    pub refresh_token: String,
+    pub session_id: String,
+    /// Every other endpoint is refused until the password is changed
+    pub must_change_password: bool,
+}
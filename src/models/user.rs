@@ -2,17 +2,38 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
 
+use crate::models::{
+    api_keys::ApiKeyScope,
+    audiobooks::{BookListItem, CompletionState},
+    permissions::Permission,
+};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserDto {
     pub username: String,
     pub password: String,
     pub is_admin: bool,
+    /// Force a password change on first login
+    #[serde(default)]
+    pub must_change_password: bool,
+    /// Libraries the user can see, the default library when omitted
+    #[serde(default)]
+    pub library_ids: Option<Vec<i64>>,
+    /// Permissions granted directly
+    #[serde(default)]
+    pub permissions: Option<Vec<Permission>>,
+    /// Groups to join, the default group when omitted
+    #[serde(default)]
+    pub group_ids: Option<Vec<i64>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginDto {
     pub username: String,
     pub password: String,
+    /// Shown in the session list, e.g. "Pixel 8"
+    #[serde(default)]
+    pub device_name: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
@@ -22,9 +43,51 @@ pub struct User {
     pub is_admin: bool,
     pub password_hash: String,
     pub salt: String,
+    pub disabled: bool,
+    pub must_change_password: bool,
+}
+
+/// User as shown to admins, without credentials
+#[derive(Debug, Serialize, FromRow)]
+pub struct UserSummary {
+    pub id: i64,
+    pub username: String,
+    pub is_admin: bool,
+    pub disabled: bool,
+    pub must_change_password: bool,
+    pub created_at: String,
+    pub last_seen_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    User,
+}
+
+/// Value of the `role` claim
+pub fn role_name(is_admin: bool) -> &'static str {
+    if is_admin { "admin" } else { "user" }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoleDto {
+    pub role: Role,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordDto {
+    pub new_password: String,
 }
 
-#[derive(sqlx::FromRow, Serialize, Deserialize, Debug)]
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordDto {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
 pub struct Progress {
     pub id: i64,
     pub user_id: i64,
@@ -33,14 +96,182 @@ pub struct Progress {
     pub progress_ms: i64,
     pub complete: bool,
     pub updated_at: DateTime<Utc>,
+    pub device_id: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProgressUpdate {
     pub book_id: i64,
     pub file_id: i64,
     pub progress_ms: i64,
     pub complete: bool,
+    /// When the position was reached on the device. Defaults to the time
+    /// the update arrives, for clients that do not send it.
+    pub updated_at: Option<DateTime<Utc>>,
+    pub device_id: Option<String>,
+    /// Recorded on the listening session the update belongs to
+    pub playback_rate: Option<f64>,
+}
+
+/// Outcome of a progress update. A write older than the stored position is
+/// not applied and both positions are returned so the client can reconcile.
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ProgressSync {
+    Applied {
+        progress: Progress,
+    },
+    Conflict {
+        progress: Progress,
+        rejected: ProgressUpdate,
+    },
+}
+
+/// Most progress events accepted in one batch
+pub const MAX_PROGRESS_BATCH: usize = 500;
+
+#[derive(Debug, Deserialize)]
+pub struct ProgressEvent {
+    /// Generated by the client, a retried event with the same id is skipped
+    pub event_id: String,
+    #[serde(flatten)]
+    pub update: ProgressUpdate,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProgressBatchDto {
+    pub events: Vec<ProgressEvent>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ProgressEventOutcome {
+    Applied {
+        progress: Progress,
+    },
+    Conflict {
+        progress: Progress,
+        rejected: ProgressUpdate,
+    },
+    /// Seen before, nothing was applied. Carries the current position.
+    Duplicate {
+        progress: Option<Progress>,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+impl From<ProgressSync> for ProgressEventOutcome {
+    fn from(sync: ProgressSync) -> Self {
+        match sync {
+            ProgressSync::Applied { progress } => Self::Applied { progress },
+            ProgressSync::Conflict { progress, rejected } => Self::Conflict { progress, rejected },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProgressEventResult {
+    pub event_id: String,
+    #[serde(flatten)]
+    pub outcome: ProgressEventOutcome,
+}
+
+#[derive(Debug, FromRow)]
+pub struct FileDuration {
+    pub id: i64,
+    pub duration: i64,
+}
+
+/// Position across the whole book, worked out from the per file rows
+#[derive(Debug, Serialize)]
+pub struct BookProgress {
+    pub book_id: i64,
+    pub completion: CompletionState,
+    pub elapsed_ms: i64,
+    pub duration_ms: i64,
+    pub percent: f64,
+    /// `files.id` to resume from and the offset into it
+    pub current_file_id: Option<i64>,
+    pub current_offset_ms: i64,
+    pub last_listened: Option<DateTime<Utc>>,
+    pub device_id: Option<String>,
+}
+
+impl BookProgress {
+    /// `files` must be in playback order. Resumes where the book was last
+    /// listened to; a completed file resumes at the start of the next one.
+    pub fn compute(book_id: i64, files: &[FileDuration], rows: &[Progress]) -> Self {
+        let duration_ms: i64 = files.iter().map(|f| f.duration).sum();
+        let latest = rows
+            .iter()
+            .filter(|p| files.iter().any(|f| f.id == p.file_id))
+            .max_by_key(|p| p.updated_at);
+        let finished = !files.is_empty()
+            && files
+                .iter()
+                .all(|f| rows.iter().any(|p| p.file_id == f.id && p.complete));
+
+        let (index, offset) = match latest {
+            None => (0, 0),
+            Some(p) => {
+                let index = files.iter().position(|f| f.id == p.file_id).unwrap_or(0);
+                let file_ms = files[index].duration;
+                if !p.complete {
+                    (index, p.progress_ms.clamp(0, file_ms.max(0)))
+                } else if index + 1 < files.len() {
+                    (index + 1, 0)
+                } else {
+                    (index, file_ms)
+                }
+            }
+        };
+
+        let elapsed_ms = if finished {
+            duration_ms
+        } else {
+            files.iter().take(index).map(|f| f.duration).sum::<i64>() + offset
+        };
+        let percent = if duration_ms > 0 {
+            (elapsed_ms as f64 * 1000.0 / duration_ms as f64).round() / 10.0
+        } else {
+            0.0
+        };
+        let completion = match latest {
+            None => CompletionState::NotStarted,
+            Some(_) if finished => CompletionState::Finished,
+            Some(_) => CompletionState::InProgress,
+        };
+
+        BookProgress {
+            book_id,
+            completion,
+            elapsed_ms,
+            duration_ms,
+            percent,
+            current_file_id: files.get(index).map(|f| f.id),
+            current_offset_ms: offset,
+            last_listened: latest.map(|p| p.updated_at),
+            device_id: latest.and_then(|p| p.device_id.clone()),
+        }
+    }
+}
+
+pub const DEFAULT_SHELF_SIZE: i64 = 20;
+pub const MAX_SHELF_SIZE: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct ShelfQuery {
+    pub limit: Option<i64>,
+}
+
+/// Book on the continue listening or recently finished shelf
+#[derive(Debug, Serialize)]
+pub struct ShelfItem {
+    #[serde(flatten)]
+    pub book: BookListItem,
+    pub progress: BookProgress,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -48,6 +279,24 @@ pub struct Claims {
     pub sub: i64,         // subject, usually user ID
     pub role: String,     // "admin" or "user"
     pub username: String, // optional additional info
+    pub sid: String,      // session the token was issued for
     pub exp: usize,       // expiration timestamp (seconds since epoch)
     pub iat: usize,       // issued at timestamp
+    #[serde(skip)]
+    pub api_key: Option<ApiKeyScope>, // set when authenticated with an API key
+    #[serde(skip)]
+    pub api_key_id: Option<i64>,
+    #[serde(skip)]
+    pub permissions: Vec<Permission>, // loaded per request, never encoded in the token
+}
+
+impl Claims {
+    pub fn is_admin(&self) -> bool {
+        self.role == role_name(true)
+    }
+
+    /// Admins implicitly hold every permission
+    pub fn has(&self, permission: Permission) -> bool {
+        self.is_admin() || self.permissions.contains(&permission)
+    }
 }
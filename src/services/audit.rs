@@ -0,0 +1,95 @@
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::{
+    db::audit::{NewAuditEntry, insert_audit_entry},
+    models::{audit::AuditAction, user::Claims},
+};
+
+/// One audit log entry, built up by the handler that made the change
+pub struct AuditEvent {
+    action: AuditAction,
+    actor_id: Option<i64>,
+    actor_name: Option<String>,
+    target_type: Option<&'static str>,
+    target_id: Option<String>,
+    before: Option<String>,
+    after: Option<String>,
+}
+
+fn to_json(value: &impl Serialize) -> Option<String> {
+    serde_json::to_string(value)
+        .inspect_err(|e| tracing::error!("Failed to serialize audit state {}", e))
+        .ok()
+}
+
+impl AuditEvent {
+    pub fn new(action: AuditAction) -> Self {
+        Self {
+            action,
+            actor_id: None,
+            actor_name: None,
+            target_type: None,
+            target_id: None,
+            before: None,
+            after: None,
+        }
+    }
+
+    pub fn actor(mut self, claims: &Claims) -> Self {
+        self.actor_id = Some(claims.sub);
+        self.actor_name = Some(claims.username.clone());
+        self
+    }
+
+    /// For events without a signed in caller, e.g. logins
+    pub fn actor_user(mut self, id: Option<i64>, name: &str) -> Self {
+        self.actor_id = id;
+        self.actor_name = Some(name.to_string());
+        self
+    }
+
+    pub fn target(mut self, target_type: &'static str, id: impl ToString) -> Self {
+        self.target_type = Some(target_type);
+        self.target_id = Some(id.to_string());
+        self
+    }
+
+    pub fn before(mut self, state: &impl Serialize) -> Self {
+        self.before = to_json(state);
+        self
+    }
+
+    pub fn after(mut self, state: &impl Serialize) -> Self {
+        self.after = to_json(state);
+        self
+    }
+}
+
+/// Writes the entry. The change it describes has already happened, so a
+/// failed write is logged rather than failing the request.
+pub async fn record(
+    db: &SqlitePool,
+    event: AuditEvent,
+    request_id: Option<&str>,
+    ip_address: Option<&str>,
+) {
+    let entry = NewAuditEntry {
+        actor_id: event.actor_id,
+        actor_name: event.actor_name.as_deref(),
+        action: event.action.as_str(),
+        target_type: event.target_type,
+        target_id: event.target_id.as_deref(),
+        before_json: event.before,
+        after_json: event.after,
+        request_id,
+        ip_address,
+    };
+    if let Err(e) = insert_audit_entry(db, entry).await {
+        tracing::error!(
+            "Failed to write audit entry {} {}",
+            event.action.as_str(),
+            e
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use tokio::sync::broadcast;
+
+use crate::models::events::ServerEvent;
+
+// Events a slow client may fall behind by before it is told it missed some
+const EVENT_BUFFER: usize = 256;
+
+/// Who an event is for
+#[derive(Debug, Clone, Copy)]
+pub enum Audience {
+    User(i64),
+    /// Everyone who can see the library
+    Library(i64),
+}
+
+#[derive(Debug)]
+pub struct Envelope {
+    pub audience: Audience,
+    pub event: ServerEvent,
+}
+
+/// Fan out of server events to connected clients, shared through
+/// `AppState`. Each stream filters for its own user.
+#[derive(Clone)]
+pub struct EventHub {
+    tx: broadcast::Sender<Arc<Envelope>>,
+}
+
+impl Default for EventHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventHub {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_BUFFER);
+        Self { tx }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Envelope>> {
+        self.tx.subscribe()
+    }
+
+    fn publish(&self, audience: Audience, event: ServerEvent) {
+        // Fails only when nobody is listening
+        let _ = self.tx.send(Arc::new(Envelope { audience, event }));
+    }
+
+    pub fn to_user(&self, user_id: i64, event: ServerEvent) {
+        self.publish(Audience::User(user_id), event);
+    }
+
+    pub fn to_library(&self, library_id: i64, event: ServerEvent) {
+        self.publish(Audience::Library(library_id), event);
+    }
+}
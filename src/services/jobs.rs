@@ -0,0 +1,232 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use chrono::{Duration, Utc};
+use sqlx::SqlitePool;
+use tokio::sync::OwnedMutexGuard;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::{
+    file_ops::scan_files::scan_files,
+    models::{
+        events::{LibraryUpdateReason, ServerEvent},
+        jobs::{FailedFile, JobPhase, JobReport},
+        meta_scan::ScanStats,
+    },
+    services::events::EventHub,
+};
+
+// Finished jobs stay queryable for this long before being dropped
+const JOB_RETENTION_MINS: i64 = 60;
+
+/// A scan running (or waiting to run) in the background
+pub struct ScanJob {
+    report: Mutex<JobReport>,
+    cancel: CancellationToken,
+}
+
+impl ScanJob {
+    fn new(library_id: i64, root: String, requested_by: i64) -> Self {
+        ScanJob {
+            report: Mutex::new(JobReport {
+                id: uuid::Uuid::new_v4().to_string(),
+                kind: "scan".to_string(),
+                library_id,
+                root,
+                requested_by,
+                phase: JobPhase::Queued,
+                files_processed: 0,
+                files_failed: 0,
+                failures: Vec::new(),
+                stats: None,
+                error: None,
+                started_at: Utc::now(),
+                finished_at: None,
+                elapsed_ms: 0,
+            }),
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    // A panic while holding the lock only loses a counter update, so keep going
+    fn lock(&self) -> MutexGuard<'_, JobReport> {
+        self.report.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn report(&self) -> JobReport {
+        let mut report = self.lock().clone();
+        let end = report.finished_at.unwrap_or_else(Utc::now);
+        report.elapsed_ms = (end - report.started_at).num_milliseconds();
+        report
+    }
+
+    pub fn id(&self) -> String {
+        self.lock().id.clone()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn file_processed(&self) {
+        self.lock().files_processed += 1;
+    }
+
+    pub fn file_failed(&self, file_path: &str, reason: String) {
+        let mut report = self.lock();
+        report.files_failed += 1;
+        report.failures.push(FailedFile {
+            file_path: file_path.to_string(),
+            reason,
+        });
+    }
+
+    fn set_phase(&self, phase: JobPhase) {
+        self.lock().phase = phase;
+    }
+
+    fn finish(&self, phase: JobPhase, stats: Option<ScanStats>, error: Option<String>) {
+        let mut report = self.lock();
+        report.phase = phase;
+        report.stats = stats;
+        report.error = error;
+        report.finished_at = Some(Utc::now());
+    }
+}
+
+/// In-memory job table shared through `AppState`. Also owns the lock that
+/// keeps two scans from writing to `file_scan_cache` at the same time, and
+/// the cancellation handles of the running library watchers.
+#[derive(Clone)]
+pub struct JobRegistry {
+    jobs: Arc<Mutex<HashMap<String, Arc<ScanJob>>>>,
+    scan_lock: Arc<tokio::sync::Mutex<()>>,
+    watchers: Arc<Mutex<HashMap<i64, CancellationToken>>>,
+    events: EventHub,
+}
+
+impl JobRegistry {
+    pub fn new(events: EventHub) -> Self {
+        Self {
+            jobs: Arc::default(),
+            scan_lock: Arc::default(),
+            watchers: Arc::default(),
+            events,
+        }
+    }
+
+    fn jobs(&self) -> MutexGuard<'_, HashMap<String, Arc<ScanJob>>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Waits until no other scan is running. Anything that writes to
+    /// `file_scan_cache` outside a job must hold this guard.
+    pub async fn scan_lock(&self) -> OwnedMutexGuard<()> {
+        self.scan_lock.clone().lock_owned().await
+    }
+
+    /// Registers a watcher for `library_id`, stopping any watcher already
+    /// running for it. The watcher must exit once the token is cancelled.
+    pub fn watch_library(&self, library_id: i64) -> CancellationToken {
+        let token = CancellationToken::new();
+        let mut watchers = self.watchers.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(old) = watchers.insert(library_id, token.clone()) {
+            old.cancel();
+        }
+        token
+    }
+
+    /// Stops the watcher of `library_id`, if one is running.
+    pub fn stop_watching(&self, library_id: i64) {
+        let mut watchers = self.watchers.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(token) = watchers.remove(&library_id) {
+            token.cancel();
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<ScanJob>> {
+        self.jobs().get(id).cloned()
+    }
+
+    pub fn list(&self) -> Vec<JobReport> {
+        let mut reports: Vec<JobReport> = self.jobs().values().map(|job| job.report()).collect();
+        reports.sort_by_key(|r| std::cmp::Reverse(r.started_at));
+        reports
+    }
+
+    /// Queues a scan of a library rooted at `root`. If a scan of the same
+    /// library is already queued or running that job is returned instead,
+    /// with `false` for `created`.
+    pub fn submit_scan(
+        &self,
+        library_id: i64,
+        root: String,
+        db: SqlitePool,
+        requested_by: i64,
+    ) -> (Arc<ScanJob>, bool) {
+        let mut jobs = self.jobs();
+
+        let cutoff = Utc::now() - Duration::minutes(JOB_RETENTION_MINS);
+        jobs.retain(|_, job| job.lock().finished_at.is_none_or(|t| t > cutoff));
+
+        if let Some(existing) = jobs.values().find(|job| {
+            let report = job.lock();
+            report.library_id == library_id && !report.phase.is_finished()
+        }) {
+            return (existing.clone(), false);
+        }
+
+        let job = Arc::new(ScanJob::new(library_id, root.clone(), requested_by));
+        jobs.insert(job.id(), job.clone());
+        drop(jobs);
+
+        let registry = self.clone();
+        let runner = job.clone();
+        tokio::spawn(async move { registry.run_scan(runner, library_id, root, db).await });
+
+        (job, true)
+    }
+
+    async fn run_scan(&self, job: Arc<ScanJob>, library_id: i64, root: String, db: SqlitePool) {
+        let _guard = tokio::select! {
+            guard = self.scan_lock() => guard,
+            _ = job.cancel.cancelled() => {
+                job.finish(JobPhase::Cancelled, None, None);
+                return;
+            }
+        };
+
+        info!("Scan job {} started on {}", job.id(), root);
+        job.set_phase(JobPhase::Scanning);
+
+        match scan_files(&root, library_id, &db, Some(&job)).await {
+            Ok(stats) if job.is_cancelled() => {
+                info!("Scan job {} cancelled", job.id());
+                job.finish(JobPhase::Cancelled, Some(stats), None);
+            }
+            Ok(stats) => {
+                info!("Scan job {} completed", job.id());
+                self.events.to_library(
+                    library_id,
+                    ServerEvent::LibraryUpdated {
+                        library_id,
+                        reason: LibraryUpdateReason::Scan,
+                        stats: Some(stats.clone()),
+                    },
+                );
+                job.finish(JobPhase::Completed, Some(stats), None);
+            }
+            Err(e) => {
+                tracing::error!("Scan job {} failed {}", job.id(), e);
+                job.finish(JobPhase::Failed, None, Some(e.to_string()));
+            }
+        }
+    }
+}
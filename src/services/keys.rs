@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, anyhow, bail};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, decode_header, encode,
+    errors::ErrorKind,
+};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{api::api_error::ApiError, config::Config};
+
+struct VerifyingKey {
+    algorithm: Algorithm,
+    key: DecodingKey,
+}
+
+/// Keys access tokens are signed and checked with. Tokens name their key in
+/// the `kid` header, so a new signing key can be rolled out while tokens
+/// signed with the previous ones (listed in JWT_PREVIOUS_KEYS) stay valid.
+/// Tokens issued before key ids and sessions existed are rejected, so
+/// upgrading to this scheme signs everyone out once.
+pub struct JwtKeys {
+    kid: String,
+    algorithm: Algorithm,
+    signing: EncodingKey,
+    verifying: HashMap<String, VerifyingKey>,
+}
+
+fn parse_algorithm(name: &str) -> anyhow::Result<Algorithm> {
+    match name {
+        "HS256" => Ok(Algorithm::HS256),
+        "RS256" => Ok(Algorithm::RS256),
+        "EdDSA" => Ok(Algorithm::EdDSA),
+        other => bail!(
+            "Unsupported JWT algorithm {}, expected HS256, RS256 or EdDSA",
+            other
+        ),
+    }
+}
+
+fn read_pem(path: &str) -> anyhow::Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("Failed to read key {}", path))
+}
+
+fn decoding_key(algorithm: Algorithm, value: &str) -> anyhow::Result<DecodingKey> {
+    Ok(match algorithm {
+        Algorithm::HS256 => DecodingKey::from_secret(value.as_bytes()),
+        Algorithm::RS256 => DecodingKey::from_rsa_pem(&read_pem(value)?)?,
+        Algorithm::EdDSA => DecodingKey::from_ed_pem(&read_pem(value)?)?,
+        other => bail!("Unsupported JWT algorithm {:?}", other),
+    })
+}
+
+impl JwtKeys {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let algorithm = parse_algorithm(&config.jwt_algorithm)?;
+        let secret = config.jwt_secret.as_ref().ok();
+
+        let (signing, current) = match algorithm {
+            Algorithm::HS256 => {
+                let secret = secret.ok_or_else(|| anyhow!("Please set JWT_SECRET"))?;
+                (
+                    EncodingKey::from_secret(secret.as_bytes()),
+                    DecodingKey::from_secret(secret.as_bytes()),
+                )
+            }
+            _ => {
+                let private = config
+                    .jwt_private_key_path
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("Please set JWT_PRIVATE_KEY_PATH"))?;
+                let public = config
+                    .jwt_public_key_path
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("Please set JWT_PUBLIC_KEY_PATH"))?;
+
+                let signing = match algorithm {
+                    Algorithm::RS256 => EncodingKey::from_rsa_pem(&read_pem(private)?)?,
+                    _ => EncodingKey::from_ed_pem(&read_pem(private)?)?,
+                };
+                (signing, decoding_key(algorithm, public)?)
+            }
+        };
+
+        let mut verifying = HashMap::new();
+        verifying.insert(
+            config.jwt_key_id.clone(),
+            VerifyingKey {
+                algorithm,
+                key: current,
+            },
+        );
+
+        // kid=ALG:secret for HS256, kid=ALG:/path/to/public.pem otherwise
+        for entry in &config.jwt_previous_keys {
+            let (kid, rest) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid JWT_PREVIOUS_KEYS entry, expected kid=ALG:key"))?;
+            let (alg, value) = rest
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Invalid JWT_PREVIOUS_KEYS entry {}", kid))?;
+            let alg = parse_algorithm(alg)?;
+
+            if verifying.contains_key(kid) {
+                bail!("Duplicate JWT key id {}", kid);
+            }
+            verifying.insert(
+                kid.to_string(),
+                VerifyingKey {
+                    algorithm: alg,
+                    key: decoding_key(alg, value)?,
+                },
+            );
+        }
+
+        Ok(JwtKeys {
+            kid: config.jwt_key_id.clone(),
+            algorithm,
+            signing,
+            verifying,
+        })
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, ApiError> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = Some(self.kid.clone());
+        Ok(encode(&header, claims, &self.signing)?)
+    }
+
+    /// Checks signature and expiry. Every failure is a 401, expired tokens
+    /// get their own message so clients know to refresh.
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, ApiError> {
+        let invalid = || ApiError::Unauthorized("Invalid token".into());
+        let header = decode_header(token).map_err(|_| invalid())?;
+
+        let kid = header.kid.as_deref().ok_or_else(invalid)?;
+        let key = self.verifying.get(kid).ok_or_else(invalid)?;
+
+        // The key decides the algorithm, never the token header
+        decode::<T>(token, &key.key, &Validation::new(key.algorithm))
+            .map(|data| data.claims)
+            .map_err(|e| match e.kind() {
+                ErrorKind::ExpiredSignature => ApiError::Unauthorized("Token has expired".into()),
+                _ => invalid(),
+            })
+    }
+}
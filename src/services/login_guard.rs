@@ -0,0 +1,150 @@
+use chrono::Utc;
+use sqlx::SqlitePool;
+use tracing::warn;
+
+use crate::{
+    api::api_error::ApiError,
+    db::login_attempts::{
+        clear_login_attempts, lock_login, record_login_failure, release_login_attempt,
+        reserve_login_attempt,
+    },
+    models::login_attempts::{AttemptScope, LoginAttempt},
+};
+
+/// Failures older than this are forgotten
+pub const FAILURE_WINDOW_SECS: i64 = 15 * 60;
+const LOCKOUT_SECS: i64 = 15 * 60;
+const MAX_BACKOFF_SECS: i64 = 60;
+// Password checks take well under a second, a reservation this old was
+// left behind by a request that never finished
+const PENDING_STALE_SECS: i64 = 30;
+
+struct Policy {
+    // Failures allowed before each attempt has to wait, doubling every time
+    backoff_after: i64,
+    // Failures before the key is locked out for LOCKOUT_SECS
+    lockout_after: i64,
+}
+
+const USER_POLICY: Policy = Policy {
+    backoff_after: 3,
+    lockout_after: 10,
+};
+
+// Addresses get more room, several users may sit behind one NAT
+const IP_POLICY: Policy = Policy {
+    backoff_after: 10,
+    lockout_after: 50,
+};
+
+fn policy(scope: AttemptScope) -> &'static Policy {
+    match scope {
+        AttemptScope::User => &USER_POLICY,
+        AttemptScope::Ip => &IP_POLICY,
+    }
+}
+
+/// Attempts are tracked whether or not the user exists, so a lockout says
+/// nothing about which usernames are real
+pub fn username_key(username: &str) -> String {
+    username.trim().to_lowercase()
+}
+
+// Seconds until `attempt` lets another login through, if it is holding one back
+fn retry_after(attempt: &LoginAttempt, now: i64) -> Option<i64> {
+    if let Some(until) = attempt.locked_until
+        && until > now
+    {
+        return Some(until - now);
+    }
+
+    let policy = policy(attempt.scope);
+    let failures = if now - attempt.last_failure_at > FAILURE_WINDOW_SECS {
+        0
+    } else {
+        attempt.failures
+    };
+
+    // Logins still in flight may all fail, so they count too. Once that
+    // reaches the backoff, only one login per key is verified at a time.
+    let in_flight = attempt.pending - 1;
+    if in_flight > 0 && failures + in_flight >= policy.backoff_after {
+        return Some(1);
+    }
+    if failures < policy.backoff_after {
+        return None;
+    }
+
+    let steps = (failures - policy.backoff_after).min(16) as u32;
+    let delay = (1i64 << steps).min(MAX_BACKOFF_SECS);
+    let ready = attempt.last_failure_at + delay;
+    (ready > now).then_some(ready - now)
+}
+
+/// Counts the login against the username and address before the password
+/// is checked, refusing it with 429 while either is backing off or locked
+/// out. A reserved login must end in `record_failed_login`,
+/// `record_successful_login` or `release_login`.
+pub async fn reserve_login(db: &SqlitePool, username: &str, ip: &str) -> Result<(), ApiError> {
+    let now = Utc::now().timestamp();
+    let user_key = username_key(username);
+    let keys = [
+        (AttemptScope::User, user_key.as_str()),
+        (AttemptScope::Ip, ip),
+    ];
+
+    let mut wait = None;
+    for (scope, key) in keys {
+        let attempt = reserve_login_attempt(db, scope, key, now, PENDING_STALE_SECS).await?;
+        wait = wait.max(retry_after(&attempt, now));
+    }
+
+    if let Some(wait) = wait {
+        release_login(db, username, ip).await?;
+        return Err(ApiError::TooManyRequests(wait as u64));
+    }
+    Ok(())
+}
+
+/// Ends a reserved login that was neither a wrong password nor a success
+pub async fn release_login(db: &SqlitePool, username: &str, ip: &str) -> Result<(), ApiError> {
+    release_login_attempt(db, AttemptScope::User, &username_key(username)).await?;
+    release_login_attempt(db, AttemptScope::Ip, ip).await?;
+    Ok(())
+}
+
+pub async fn record_failed_login(
+    db: &SqlitePool,
+    username: &str,
+    ip: &str,
+) -> Result<(), ApiError> {
+    let now = Utc::now().timestamp();
+    let user_key = username_key(username);
+
+    for (scope, key) in [
+        (AttemptScope::User, user_key.as_str()),
+        (AttemptScope::Ip, ip),
+    ] {
+        let attempt = record_login_failure(db, scope, key, now, FAILURE_WINDOW_SECS).await?;
+        if attempt.locked_until.is_none() && attempt.failures >= policy(scope).lockout_after {
+            lock_login(db, scope, key, now + LOCKOUT_SECS).await?;
+            warn!(
+                "Login locked for {:?} {} after {} failures",
+                scope, key, attempt.failures
+            );
+        }
+    }
+    Ok(())
+}
+
+/// A correct password clears the username's failures. The address keeps its
+/// count so one valid account does not unlock guessing at others.
+pub async fn record_successful_login(
+    db: &SqlitePool,
+    username: &str,
+    ip: &str,
+) -> Result<(), ApiError> {
+    clear_login_attempts(db, AttemptScope::User, &username_key(username)).await?;
+    release_login_attempt(db, AttemptScope::Ip, ip).await?;
+    Ok(())
+}
@@ -1 +1,8 @@
+pub mod audit;
+pub mod events;
+pub mod jobs;
+pub mod keys;
+pub mod login_guard;
+pub mod oidc;
 pub mod startup;
+pub mod watcher;
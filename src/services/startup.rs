@@ -1,14 +1,18 @@
 use crate::api::api_error::ApiError;
 use crate::api::user::save_pwd_hash;
-use crate::db::meta_scan::get_grouped_files;
-use crate::db::user::admin_exists;
-use crate::file_ops::book_cover::{cover_links, create_cover_link};
-use crate::file_ops::file_ops::scan_for_audiobooks;
-use crate::file_ops::scan_files::scan_files;
+use crate::config::Config;
+use crate::db::user::{admin_exists, get_user_by_username, set_must_change_password};
 use crate::models::user::UserDto;
-use axum::extract::path;
+use argon2::{
+    Argon2,
+    password_hash::{
+        PasswordHash, PasswordVerifier,
+        rand_core::{OsRng, RngCore},
+    },
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
 use sqlx::sqlite::SqlitePool;
-use tracing::info;
+use tracing::{info, warn};
 use tracing_appender::rolling::{self};
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::fmt::time::UtcTime;
@@ -49,24 +53,74 @@ pub fn init_logging() {
     std::mem::forget(_guard);
 }
 
-pub async fn ensure_admin_user(db: &SqlitePool) -> Result<(), ApiError> {
+/// Creates the first admin on a fresh install, from ADMIN_PASSWORD when set
+/// or else from a random password that is logged once. Either way it has to
+/// be changed on first login.
+pub async fn ensure_admin_user(db: &SqlitePool, config: &Config) -> Result<(), ApiError> {
     let admin_exists: i64 = admin_exists(db).await?;
 
     if admin_exists == 0 {
+        let generated = config.admin_password.is_none();
+        let password = config
+            .admin_password
+            .clone()
+            .unwrap_or_else(generate_password);
+
         let admin = UserDto {
-            username: "admin".to_string(),
-            password: "admin".to_string(),
+            username: config.admin_username.clone(),
+            password: password.clone(),
             is_admin: true,
+            must_change_password: true,
+            library_ids: None,
+            permissions: None,
+            group_ids: None,
         };
         save_pwd_hash(&admin, db).await?;
 
-        info!("Admin user created: username='admin'");
+        if generated {
+            warn!(
+                "Admin user created: username='{}' password='{}'. This password is only shown once and must be changed on first login",
+                admin.username, password
+            );
+        } else {
+            info!(
+                "Admin user created from ADMIN_PASSWORD: username='{}'",
+                admin.username
+            );
+        }
+        return Ok(());
+    }
+
+    flag_default_admin_password(db).await
+}
+
+fn generate_password() -> String {
+    let mut bytes = [0u8; 15];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// Installs from before forced rotation still have admin/admin
+async fn flag_default_admin_password(db: &SqlitePool) -> Result<(), ApiError> {
+    let Some(user) = get_user_by_username(db, "admin").await? else {
+        return Ok(());
+    };
+    if user.must_change_password {
+        return Ok(());
     }
 
+    let parsed_hash = PasswordHash::new(&user.password_hash)?;
+    if Argon2::default()
+        .verify_password(b"admin", &parsed_hash)
+        .is_ok()
+    {
+        set_must_change_password(db, user.id).await?;
+        warn!("Admin still uses the default password, it must be changed on next login");
+    }
     Ok(())
 }
 
-pub async fn scan_files_startup(path_str: &String, db: &SqlitePool) -> Result<(), ApiError> {
+pub async fn scan_files_startup(_path_str: &str, _db: &SqlitePool) -> Result<(), ApiError> {
     // info!("Scanning files on {}", path_str);
     // scan_for_audiobooks(path_str, db).await?;
     // scan_files(path_str, db).await?;
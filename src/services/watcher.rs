@@ -0,0 +1,210 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::{
+    db::{meta_scan::propagate_changes, search::reindex_books_under},
+    file_ops::{
+        book_cover::cover_links,
+        scan_files::{folder_prefix, scan_files},
+    },
+    models::{
+        events::{LibraryUpdateReason, ServerEvent},
+        meta_scan::ScanStats,
+    },
+    services::{events::EventHub, jobs::JobRegistry},
+};
+
+// Wait for the library to go quiet before rescanning so a copy of a
+// 40-file book triggers one scan instead of forty
+const DEBOUNCE: Duration = Duration::from_secs(3);
+
+/// Watches a library root and rescans only the folders that changed.
+/// Runs until stopped through `JobRegistry::stop_watching` or replaced by a
+/// new watcher for the same library; failures are logged, never fatal.
+pub fn spawn_library_watcher(
+    library_id: i64,
+    root: String,
+    db: SqlitePool,
+    jobs: JobRegistry,
+    events: EventHub,
+) {
+    let (tx, rx) = mpsc::unbounded_channel::<PathBuf>();
+
+    let watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+        Ok(event) => {
+            if is_relevant(&event.kind) {
+                for path in event.paths {
+                    let _ = tx.send(path);
+                }
+            }
+        }
+        Err(e) => tracing::error!("Library watcher error {}", e),
+    });
+
+    let mut watcher = match watcher {
+        Ok(w) => w,
+        Err(e) => {
+            tracing::error!("Failed to start library watcher {}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = watcher.watch(Path::new(&root), RecursiveMode::Recursive) {
+        tracing::error!("Failed to watch {} {}", root, e);
+        return;
+    }
+    info!("Watching {} for library changes", root);
+
+    let cancel = jobs.watch_library(library_id);
+    tokio::spawn(async move {
+        // The watcher stops when dropped, so it lives inside the task
+        let _watcher = watcher;
+        debounce_loop(rx, library_id, &db, &jobs, &events, &cancel).await;
+        info!("Stopped watching {}", root);
+    });
+}
+
+fn is_relevant(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    )
+}
+
+async fn debounce_loop(
+    mut rx: mpsc::UnboundedReceiver<PathBuf>,
+    library_id: i64,
+    db: &SqlitePool,
+    jobs: &JobRegistry,
+    events: &EventHub,
+    cancel: &CancellationToken,
+) {
+    let mut pending: HashSet<PathBuf> = HashSet::new();
+
+    loop {
+        if pending.is_empty() {
+            let received = tokio::select! {
+                path = rx.recv() => path,
+                _ = cancel.cancelled() => return,
+            };
+            match received {
+                Some(path) => {
+                    pending.insert(path);
+                }
+                None => return,
+            }
+            continue;
+        }
+
+        let received = tokio::select! {
+            path = tokio::time::timeout(DEBOUNCE, rx.recv()) => path,
+            _ = cancel.cancelled() => return,
+        };
+        match received {
+            Ok(Some(path)) => {
+                pending.insert(path);
+            }
+            Ok(None) => return,
+            Err(_) => {
+                let folders = affected_folders(pending.drain());
+                rescan(&folders, library_id, db, jobs, events, cancel).await;
+            }
+        }
+    }
+}
+
+// Map raw event paths to the folders that need rescanning, dropping any
+// folder already covered by one of its ancestors
+fn affected_folders(paths: impl Iterator<Item = PathBuf>) -> Vec<PathBuf> {
+    let mut folders: Vec<PathBuf> = paths
+        .filter_map(|path| {
+            if path.is_dir() {
+                return Some(path);
+            }
+            // Files (existing or just deleted) rescan their parent; a deleted
+            // folder has no extension and is rescanned as itself
+            if path.is_file() || path.extension().is_some() {
+                return path.parent().map(Path::to_path_buf);
+            }
+            Some(path)
+        })
+        .collect();
+
+    folders.sort();
+    folders.dedup();
+
+    let mut roots: Vec<PathBuf> = Vec::new();
+    for folder in folders {
+        if !roots.iter().any(|r| folder.starts_with(r)) {
+            roots.push(folder);
+        }
+    }
+    roots
+}
+
+async fn rescan(
+    folders: &[PathBuf],
+    library_id: i64,
+    db: &SqlitePool,
+    jobs: &JobRegistry,
+    events: &EventHub,
+    cancel: &CancellationToken,
+) {
+    // Wait for any running scan job rather than racing it on file_scan_cache
+    let _guard = jobs.scan_lock().await;
+    // The library may have been deleted or re-rooted while we waited
+    if cancel.is_cancelled() {
+        return;
+    }
+
+    let mut combined = ScanStats::default();
+    for folder in folders {
+        let Some(folder_str) = folder.to_str() else {
+            tracing::warn!("Skipping non utf-8 path {}", folder.display());
+            continue;
+        };
+
+        info!("Library change detected, rescanning {}", folder_str);
+        match scan_files(folder_str, library_id, db, None).await {
+            Ok(stats) => {
+                info!(
+                    "Rescanned {}: {} updated, {} moved, {} missing",
+                    folder_str, stats.updated, stats.moved, stats.missing
+                );
+                combined += stats;
+            }
+            Err(e) => tracing::error!("Rescan of {} failed {}", folder_str, e),
+        }
+    }
+
+    if let Err(e) = propagate_changes(db).await {
+        tracing::error!("Failed to propagate library changes {}", e);
+    } else {
+        // Search rows are built from books and files, so only after propagating
+        for folder in folders.iter().filter_map(|f| f.to_str()) {
+            if let Err(e) = reindex_books_under(db, &folder_prefix(folder)).await {
+                tracing::error!("Failed to reindex {} {}", folder, e);
+            }
+        }
+        events.to_library(
+            library_id,
+            ServerEvent::LibraryUpdated {
+                library_id,
+                reason: LibraryUpdateReason::Scan,
+                stats: Some(combined),
+            },
+        );
+    }
+    if let Err(e) = cover_links(db).await {
+        tracing::error!("Failed to link covers {}", e);
+    }
+}